@@ -1,9 +1,10 @@
-use rand::RngExt;
-
 use crate::{
     ray::Ray,
-    scene::{Float0, RNGType},
-    utils::{matrix::Float3x3, vector::Float3},
+    scene::Float0,
+    utils::{
+        matrix::Float3x3,
+        vector::{Float2, Float3},
+    },
 };
 
 #[derive(Debug)]
@@ -13,17 +14,7 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn get_ray(
-        &self,
-        x: Float0,
-        y: Float0,
-        width: Float0,
-        height: Float0,
-        rand_state: &mut RNGType,
-    ) -> Ray {
-        let x = x + rand_state.random_range(0.0..1.0) as Float0;
-        let y = y + rand_state.random_range(0.0..1.0) as Float0;
-
+    pub fn get_ray(&self, x: Float0, y: Float0, width: Float0, height: Float0) -> Ray {
         let x0 = (x / width) * 2.0 - 1.0;
         let y0 = (y / height) * 2.0 - 1.0;
         let mut direction = Float3::new([x0 * width / height, -y0, -1.0]);
@@ -38,6 +29,34 @@ impl Camera {
         }
     }
 
+    // Unit vector through the middle of the image.
+    pub fn forward(&self) -> Float3 {
+        self.get_rotation_matrix()
+            .multiply_by_vector(&Float3::new([0.0, 0.0, -1.0]))
+    }
+
+    // The inverse of `get_ray`: where on the image `point` is seen, or None
+    // when it's behind the camera or outside the image.
+    pub fn project(&self, point: Float3, width: Float0, height: Float0) -> Option<Float2> {
+        let rotation_matrix = self.get_rotation_matrix();
+        let right = rotation_matrix.multiply_by_vector(&Float3::new([1.0, 0.0, 0.0]));
+        let up = rotation_matrix.multiply_by_vector(&Float3::new([0.0, 1.0, 0.0]));
+        let offset = point - self.position;
+        let depth = offset.dot(&self.forward());
+        if depth <= 0.0 {
+            return None;
+        }
+        let x0 = offset.dot(&right) / depth * height / width;
+        let y0 = -offset.dot(&up) / depth;
+        if x0.abs() > 1.0 || y0.abs() > 1.0 {
+            return None;
+        }
+        Some(Float2::new([
+            (x0 + 1.0) * 0.5 * width,
+            (y0 + 1.0) * 0.5 * height,
+        ]))
+    }
+
     fn get_rotation_matrix(&self) -> Float3x3 {
         let yaw = self.rotation.x().to_radians();
         let pitch = self.rotation.y().to_radians();
@@ -62,3 +81,23 @@ impl Camera {
         rotation_z * rotation_y * rotation_x
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projects_back_onto_the_image() {
+        let camera = Camera {
+            position: Float3::new([1.0, 2.0, 3.0]),
+            rotation: Float3::new([30.0, -20.0, 10.0]),
+        };
+        let (width, height) = (64.0, 48.0);
+        let ray = camera.get_ray(10.5, 40.25, width, height);
+        let position = camera.project(ray.at(7.0), width, height).unwrap();
+        assert!((position.x() - 10.5).abs() < 1e-9 && (position.y() - 40.25).abs() < 1e-9);
+        assert!(camera.project(ray.at(-1.0), width, height).is_none());
+        let center = camera.get_ray(32.0, 24.0, width, height);
+        assert!((center.direction - camera.forward()).length() < 1e-9);
+    }
+}
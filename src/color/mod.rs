@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use crate::{
+    scene::Float0,
+    utils::{
+        matrix::{Float3x3, Matrix},
+        vector::Float3,
+    },
+};
+
+// Linear RGB spaces. All colour math in `Float3` happens in the scene's
+// working space; inputs are converted into it when the scene is parsed and
+// the output transform converts out of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    LinearSrgb,
+    AcesCg,
+    Rec2020,
+}
+
+impl FromStr for ColorSpace {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ColorSpace, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear_srgb" | "srgb_linear" | "lin_srgb" | "rec709" | "bt709" => Ok(Self::LinearSrgb),
+            "acescg" | "aces_cg" | "ap1" => Ok(Self::AcesCg),
+            "rec2020" | "bt2020" => Ok(Self::Rec2020),
+            _ => Err(()),
+        }
+    }
+}
+
+// Bradford cone response matrix for chromatic adaptation between white points.
+const BRADFORD: Float3x3 = Matrix([
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+]);
+
+impl ColorSpace {
+    // CIE xy chromaticities of the red, green and blue primaries and the white point.
+    pub fn chromaticities(self) -> [[Float0; 2]; 4] {
+        const D65: [Float0; 2] = [0.3127, 0.3290];
+        match self {
+            Self::LinearSrgb => [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06], D65],
+            Self::AcesCg => [
+                [0.713, 0.293],
+                [0.165, 0.830],
+                [0.128, 0.044],
+                [0.32168, 0.33767],
+            ],
+            Self::Rec2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046], D65],
+        }
+    }
+
+    pub fn rgb_to_xyz(self) -> Float3x3 {
+        let [red, green, blue, white] = self.chromaticities();
+        let primaries =
+            Float3x3::new_from_columns([xy_to_xyz(red), xy_to_xyz(green), xy_to_xyz(blue)]);
+        // Scale the primaries so RGB (1, 1, 1) lands on the white point with Y = 1.
+        let scale = invert(&primaries).multiply_by_vector(&Float3::new(xy_to_xyz(white)));
+        let mut matrix = primaries;
+        for row in matrix.0.iter_mut() {
+            for (value, scale) in row.iter_mut().zip(scale.0) {
+                *value *= scale;
+            }
+        }
+        matrix
+    }
+
+    pub fn xyz_to_rgb(self) -> Float3x3 {
+        invert(&self.rgb_to_xyz())
+    }
+
+    pub fn white(self) -> Float3 {
+        Float3::new(xy_to_xyz(self.chromaticities()[3]))
+    }
+
+    // Linear RGB in `self` to linear RGB in `target`, adapting the white point
+    // when the two spaces don't share one.
+    pub fn conversion_matrix(self, target: ColorSpace) -> Float3x3 {
+        let adaptation = chromatic_adaptation(self.white(), target.white());
+        target.xyz_to_rgb() * (adaptation * self.rgb_to_xyz())
+    }
+
+    pub fn convert(self, color: Float3, target: ColorSpace) -> Float3 {
+        if self == target {
+            return color;
+        }
+        self.conversion_matrix(target).multiply_by_vector(&color)
+    }
+}
+
+// Bradford transform taking XYZ colours seen under `source_white` to the
+// corresponding colours under `target_white`.
+pub fn chromatic_adaptation(source_white: Float3, target_white: Float3) -> Float3x3 {
+    let source_cone = BRADFORD.multiply_by_vector(&source_white);
+    let target_cone = BRADFORD.multiply_by_vector(&target_white);
+    let mut cone_scale = Matrix([[0.0; 3]; 3]);
+    for axis in 0..3 {
+        cone_scale[axis][axis] = target_cone.0[axis] / source_cone.0[axis];
+    }
+    invert(&BRADFORD) * (cone_scale * BRADFORD)
+}
+
+fn xy_to_xyz([x, y]: [Float0; 2]) -> [Float0; 3] {
+    [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+fn invert(matrix: &Float3x3) -> Float3x3 {
+    let m = &matrix.0;
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let determinant = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+        + m[0][2] * cofactor(1, 2, 0, 1);
+    let inverse = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    Matrix(inverse.map(|row| row.map(|value| value / determinant)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Float3, expected: [Float0; 3], tolerance: Float0) {
+        for (a, e) in actual.0.iter().zip(expected) {
+            assert!((a - e).abs() < tolerance, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn matches_reference_matrices() {
+        // sRGB red in ACEScg and Rec.2020, as published by OpenColorIO and BT.2087.
+        let red = Float3::new([1.0, 0.0, 0.0]);
+        assert_close(
+            ColorSpace::LinearSrgb.convert(red, ColorSpace::AcesCg),
+            [0.613_097, 0.070_194, 0.020_616],
+            1e-3,
+        );
+        assert_close(
+            ColorSpace::LinearSrgb.convert(red, ColorSpace::Rec2020),
+            [0.627_4, 0.069_1, 0.016_4],
+            1e-3,
+        );
+        // The middle row of the RGB to XYZ matrix is the luminance weighting.
+        assert_close(
+            Float3::new(ColorSpace::LinearSrgb.rgb_to_xyz()[1]),
+            [0.2126, 0.7152, 0.0722],
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn conversions_round_trip_and_keep_white() {
+        let spaces = [
+            ColorSpace::LinearSrgb,
+            ColorSpace::AcesCg,
+            ColorSpace::Rec2020,
+        ];
+        let color = Float3::new([0.8, 0.3, 0.05]);
+        for from in spaces {
+            for to in spaces {
+                let white = from.convert(Float3::new([1.0, 1.0, 1.0]), to);
+                assert_close(white, [1.0, 1.0, 1.0], 1e-9);
+                let back = to.convert(from.convert(color, to), from);
+                assert_close(back, color.0, 1e-9);
+            }
+        }
+    }
+}
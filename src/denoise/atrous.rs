@@ -0,0 +1,179 @@
+use rayon::prelude::*;
+
+use crate::{scene::Float0, utils::vector::Float3};
+
+use super::Guides;
+
+const ITERATIONS: u32 = 5;
+const KERNEL: [Float0; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+const SIGMA_COLOR: Float0 = 0.3;
+const SIGMA_ALBEDO: Float0 = 0.1;
+const NORMAL_POWER: i32 = 64;
+const SIGMA_DEPTH: Float0 = 0.05;
+
+// Dammertz et al., "Edge-Avoiding À-Trous Wavelet Transform for fast Global
+// Illumination Filtering": repeated 5x5 B3-spline passes with doubling tap
+// spacing, weighted down across albedo, normal and depth discontinuities.
+// Texture detail is kept by filtering the albedo-demodulated irradiance.
+pub fn denoise(width: usize, height: usize, buffer: &mut [Float3], guides: &Guides) {
+    let mut irradiance: Vec<Float3> = buffer
+        .iter()
+        .zip(guides.albedo)
+        .map(|(color, albedo)| demodulate(*color, *albedo))
+        .collect();
+
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        // The colour stopping function tightens as the noise is averaged away.
+        let sigma_color = SIGMA_COLOR / (1 << iteration) as Float0;
+        irradiance = (0..width * height)
+            .into_par_iter()
+            .map(|index| filter_pixel(width, height, index, step, sigma_color, &irradiance, guides))
+            .collect();
+    }
+
+    for ((pixel, irradiance), albedo) in buffer.iter_mut().zip(irradiance).zip(guides.albedo) {
+        *pixel = remodulate(irradiance, *albedo);
+    }
+}
+
+fn filter_pixel(
+    width: usize,
+    height: usize,
+    index: usize,
+    step: usize,
+    sigma_color: Float0,
+    irradiance: &[Float3],
+    guides: &Guides,
+) -> Float3 {
+    let x = (index % width) as isize;
+    let y = (index / width) as isize;
+
+    let color_p = irradiance[index];
+    let albedo_p = guides.albedo[index];
+    let normal_p = guides.normal[index];
+    let depth_p = guides.depth[index].x();
+
+    let mut sum = Float3::new([0.0, 0.0, 0.0]);
+    let mut weight_sum = 0.0;
+
+    for (j, kernel_y) in KERNEL.iter().enumerate() {
+        let qy = y + (j as isize - 2) * step as isize;
+        if qy < 0 || qy >= height as isize {
+            continue;
+        }
+        for (i, kernel_x) in KERNEL.iter().enumerate() {
+            let qx = x + (i as isize - 2) * step as isize;
+            if qx < 0 || qx >= width as isize {
+                continue;
+            }
+            let q = qy as usize * width + qx as usize;
+
+            // Relative colour difference, so the result doesn't depend on exposure.
+            let color_q = irradiance[q];
+            let color_distance = (color_q - color_p).length_squared()
+                / ((color_p.length() + color_q.length()).powi(2) + 1e-12);
+            let albedo_distance = (guides.albedo[q] - albedo_p).length_squared();
+            let depth_q = guides.depth[q].x();
+            let depth_distance =
+                (depth_q - depth_p).abs() / (SIGMA_DEPTH * depth_p.max(depth_q) + 1e-4);
+
+            let weight = kernel_x
+                * kernel_y
+                * (-color_distance / (sigma_color * sigma_color)).exp()
+                * (-albedo_distance / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp()
+                * normal_p.dot(&guides.normal[q]).max(0.0).powi(NORMAL_POWER)
+                * (-depth_distance).exp();
+
+            sum += irradiance[q].scale(weight);
+            weight_sum += weight;
+        }
+    }
+
+    // The centre tap always has full guide weight, so this only guards
+    // against underflow.
+    if weight_sum > 0.0 {
+        sum.divide(weight_sum)
+    } else {
+        irradiance[index]
+    }
+}
+
+fn demodulate(color: Float3, albedo: Float3) -> Float3 {
+    let channel = |c: Float0, a: Float0| if a > 1e-3 { c / a } else { c };
+    Float3::new([
+        channel(color.x(), albedo.x()),
+        channel(color.y(), albedo.y()),
+        channel(color.z(), albedo.z()),
+    ])
+}
+
+fn remodulate(irradiance: Float3, albedo: Float3) -> Float3 {
+    let channel = |c: Float0, a: Float0| if a > 1e-3 { c * a } else { c };
+    Float3::new([
+        channel(irradiance.x(), albedo.x()),
+        channel(irradiance.y(), albedo.y()),
+        channel(irradiance.z(), albedo.z()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngExt;
+
+    use super::*;
+    use crate::pathtracer::seeded_rng;
+
+    #[test]
+    fn smooths_noise_but_keeps_guide_edges() {
+        let (width, height) = (32, 32);
+        let mut rng = seeded_rng(3);
+        let grey = Float3::new([0.5, 0.5, 0.5]);
+        let up = Float3::new([0.0, 0.0, 1.0]);
+        // Left half is a dark, right half a bright wall at a different depth.
+        let is_left = |index: usize| index % width < width / 2;
+        let clean = |index: usize| if is_left(index) { 0.1 } else { 0.9 };
+
+        let mut buffer: Vec<Float3> = (0..width * height)
+            .map(|index| {
+                let value = clean(index) * (0.5 + rng.random::<Float0>());
+                Float3::new([value, value, value])
+            })
+            .collect();
+        let albedo = vec![grey; width * height];
+        let normal = vec![up; width * height];
+        let depth: Vec<Float3> = (0..width * height)
+            .map(|index| {
+                let depth = if is_left(index) { 1.0 } else { 4.0 };
+                Float3::new([depth, depth, depth])
+            })
+            .collect();
+
+        let error = |buffer: &[Float3]| {
+            buffer
+                .iter()
+                .enumerate()
+                .map(|(index, color)| (color.x() - clean(index)).powi(2))
+                .sum::<Float0>()
+        };
+        let noisy_error = error(&buffer);
+
+        denoise(
+            width,
+            height,
+            &mut buffer,
+            &Guides {
+                albedo: &albedo,
+                normal: &normal,
+                depth: &depth,
+            },
+        );
+
+        assert!(error(&buffer) < noisy_error * 0.25);
+        // Pixels next to the depth edge must not bleed into each other.
+        let row = height / 2 * width;
+        assert!(buffer[row + width / 2 - 1].x() < 0.3);
+        assert!(buffer[row + width / 2].x() > 0.6);
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(feature = "oidn")]
+use crate::scene::Float0;
+use crate::utils::vector::Float3;
+
+pub mod atrous;
+
+// First-hit feature buffers that steer the denoiser around edges.
+pub struct Guides<'a> {
+    pub albedo: &'a [Float3],
+    pub normal: &'a [Float3],
+    pub depth: &'a [Float3],
+}
+
+// Uses Open Image Denoise when the `oidn` feature is on and the built-in
+// à-trous filter otherwise. `prefilter_aux` only applies to OIDN.
+pub fn denoise(
+    width: usize,
+    height: usize,
+    buffer: &mut [Float3],
+    guides: &Guides,
+    prefilter_aux: bool,
+) {
+    #[cfg(feature = "oidn")]
+    denoise_oidn(width, height, buffer, guides, prefilter_aux);
+
+    #[cfg(not(feature = "oidn"))]
+    {
+        let _ = prefilter_aux;
+        atrous::denoise(width, height, buffer, guides);
+    }
+}
+
+#[cfg(feature = "oidn")]
+fn denoise_oidn(
+    width: usize,
+    height: usize,
+    buffer: &mut [Float3],
+    guides: &Guides,
+    prefilter_aux: bool,
+) {
+    let mut color_buffer = to_f32_buffer(buffer);
+    let mut albedo_buffer = to_f32_buffer(guides.albedo);
+    let mut normal_buffer = to_f32_buffer(guides.normal);
+
+    let device = oidn::Device::cpu();
+
+    if prefilter_aux {
+        oidn::RayTracing::new(&device)
+            .hdr(false)
+            .srgb(false)
+            .image_dimensions(width, height)
+            .filter_in_place(&mut albedo_buffer)
+            .expect("Albedo prefilter config error!");
+
+        // Normals live in [-1, 1]; remap them into the LDR range the
+        // prefilter expects and back afterwards.
+        for value in normal_buffer.iter_mut() {
+            *value = *value * 0.5 + 0.5;
+        }
+        oidn::RayTracing::new(&device)
+            .hdr(false)
+            .srgb(false)
+            .image_dimensions(width, height)
+            .filter_in_place(&mut normal_buffer)
+            .expect("Normal prefilter config error!");
+        for value in normal_buffer.iter_mut() {
+            *value = *value * 2.0 - 1.0;
+        }
+    }
+
+    oidn::RayTracing::new(&device)
+        .hdr(true)
+        .srgb(false)
+        .image_dimensions(width, height)
+        .albedo_normal(&albedo_buffer, &normal_buffer)
+        .clean_aux(prefilter_aux)
+        .filter_in_place(&mut color_buffer)
+        .expect("Filter config error!");
+
+    for (pixel, slice) in buffer.iter_mut().zip(color_buffer.chunks_exact(3)) {
+        *pixel = Float3::new([slice[0] as Float0, slice[1] as Float0, slice[2] as Float0]);
+    }
+}
+
+#[cfg(feature = "oidn")]
+fn to_f32_buffer(buffer: &[Float3]) -> Vec<f32> {
+    buffer
+        .iter()
+        .flat_map(|v| v.as_array())
+        .map(|x| x as f32)
+        .collect()
+}
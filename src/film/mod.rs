@@ -0,0 +1,553 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    filter::{Filter, FilterMode},
+    sampler::mix_bits,
+    scene::Float0,
+    spectrum::SampledWavelengths,
+    utils::vector::{Float2, Float3},
+};
+
+#[derive(Debug, Default)]
+struct AtomicFloat(AtomicU64);
+
+impl AtomicFloat {
+    fn add(&self, value: Float0) {
+        let mut current = self.0.load(Ordering::Relaxed);
+        loop {
+            let new = (Float0::from_bits(current) + value).to_bits();
+            match self
+                .0
+                .compare_exchange_weak(current, new, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn get(&self) -> Float0 {
+        Float0::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, value: Float0) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+struct FilmPixel {
+    color: [AtomicFloat; 3],
+    weight: AtomicFloat,
+    // Light arriving from paths traced from the lights, which don't belong
+    // to any camera sample.
+    splat: [AtomicFloat; 3],
+}
+
+impl FilmPixel {
+    fn add(&self, color: Float3, weight: Float0) {
+        for (channel, value) in self.color.iter().zip(color.0) {
+            channel.add(value * weight);
+        }
+        self.weight.add(weight);
+    }
+
+    fn add_splat(&self, color: Float3) {
+        for (channel, value) in self.splat.iter().zip(color.0) {
+            channel.add(value);
+        }
+    }
+
+    fn set(&self, color: Float3) {
+        for (channel, value) in self.color.iter().zip(color.0) {
+            channel.set(value);
+        }
+        self.weight.set(1.0);
+        for channel in &self.splat {
+            channel.set(0.0);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Beauty,
+    Alpha,
+    Raw,
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ObjectId,
+    MaterialId,
+    Direct,
+    Indirect,
+    Light(usize),
+    SampleCount,
+}
+
+impl FromStr for Channel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Channel, ()> {
+        match s {
+            "beauty" => Ok(Self::Beauty),
+            "alpha" => Ok(Self::Alpha),
+            "raw" => Ok(Self::Raw),
+            "albedo" => Ok(Self::Albedo),
+            "normal" => Ok(Self::Normal),
+            "depth" => Ok(Self::Depth),
+            "position" => Ok(Self::Position),
+            "object_id" => Ok(Self::ObjectId),
+            "material_id" => Ok(Self::MaterialId),
+            "direct" => Ok(Self::Direct),
+            "indirect" => Ok(Self::Indirect),
+            "sample_count" => Ok(Self::SampleCount),
+            _ => s
+                .strip_prefix("light_")
+                .and_then(|index| index.parse().ok())
+                .map(Self::Light)
+                .ok_or(()),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Beauty => write!(f, "beauty"),
+            Self::Alpha => write!(f, "alpha"),
+            Self::Raw => write!(f, "raw"),
+            Self::Albedo => write!(f, "albedo"),
+            Self::Normal => write!(f, "normal"),
+            Self::Depth => write!(f, "depth"),
+            Self::Position => write!(f, "position"),
+            Self::ObjectId => write!(f, "object_id"),
+            Self::MaterialId => write!(f, "material_id"),
+            Self::Direct => write!(f, "direct"),
+            Self::Indirect => write!(f, "indirect"),
+            Self::Light(index) => write!(f, "light_{index}"),
+            Self::SampleCount => write!(f, "sample_count"),
+        }
+    }
+}
+
+impl Channel {
+    // IDs and sample counts are not reconstructed with the pixel filter: IDs
+    // keep the first sample of the pixel and counts are summed.
+    fn is_filtered(&self) -> bool {
+        !matches!(self, Self::ObjectId | Self::MaterialId | Self::SampleCount)
+    }
+
+    fn value(&self, path: &PathSample) -> Float3 {
+        let id = |id: Option<usize>| {
+            let value = id.map_or(0.0, |id| (id + 1) as Float0);
+            Float3::new([value, value, value])
+        };
+        match self {
+            Self::Beauty | Self::Raw => path.to_rgb(path.color),
+            Self::Alpha => Float3::new([path.alpha, path.alpha, path.alpha]),
+            Self::Albedo => path.albedo,
+            Self::Normal => path.normal,
+            Self::Depth => Float3::new([path.depth, path.depth, path.depth]),
+            Self::Position => path.position,
+            Self::ObjectId => id(path.object_id),
+            Self::MaterialId => id(path.material_id),
+            Self::Direct => path.to_rgb(path.direct),
+            Self::Indirect => path.to_rgb(path.color - path.direct),
+            Self::Light(index) => path.to_rgb(
+                path.lights
+                    .get(*index)
+                    .copied()
+                    .unwrap_or_else(|| Float3::new([0.0, 0.0, 0.0])),
+            ),
+            Self::SampleCount => Float3::new([1.0, 1.0, 1.0]),
+        }
+    }
+
+    // Maps a resolved channel into [0, 1] so it can be written as an 8-bit image.
+    pub fn display(&self, image: &[Float3]) -> Vec<Float3> {
+        let max_component = image
+            .iter()
+            .flat_map(|pixel| pixel.0)
+            .fold(0.0, |max: Float0, value| max.max(value.abs()));
+        match self {
+            Self::Normal => image
+                .iter()
+                .map(|normal| normal.scale(0.5) + Float3::new([0.5, 0.5, 0.5]))
+                .collect(),
+            Self::Depth | Self::SampleCount if max_component > 0.0 => image
+                .iter()
+                .map(|pixel| pixel.scale(1.0 / max_component))
+                .collect(),
+            Self::Position if max_component > 0.0 => image
+                .iter()
+                .map(|pixel| pixel.scale(0.5 / max_component) + Float3::new([0.5, 0.5, 0.5]))
+                .collect(),
+            Self::ObjectId | Self::MaterialId => image
+                .iter()
+                .map(|pixel| match pixel.x() as u64 {
+                    0 => Float3::new([0.0, 0.0, 0.0]),
+                    id => {
+                        let bits = mix_bits(id);
+                        Float3::new([
+                            (bits & 0xff) as Float0 / 255.0,
+                            ((bits >> 8) & 0xff) as Float0 / 255.0,
+                            ((bits >> 16) & 0xff) as Float0 / 255.0,
+                        ])
+                    }
+                })
+                .collect(),
+            _ => image.to_vec(),
+        }
+    }
+}
+
+// Everything the integrator learned about one camera sample. Radiance is
+// RGB, or spectral samples at `wavelengths` in spectral mode; the guides are
+// always RGB.
+#[derive(Debug, Clone)]
+pub struct PathSample {
+    pub wavelengths: Option<SampledWavelengths>,
+    pub color: Float3,
+    pub direct: Float3,
+    pub lights: Vec<Float3>,
+    // Coverage, with the colour premultiplied by it.
+    pub alpha: Float0,
+    pub albedo: Float3,
+    pub normal: Float3,
+    pub position: Float3,
+    pub depth: Float0,
+    pub object_id: Option<usize>,
+    pub material_id: Option<usize>,
+}
+
+impl PathSample {
+    pub fn new(light_count: usize) -> Self {
+        Self {
+            wavelengths: None,
+            color: Float3::new([0.0, 0.0, 0.0]),
+            direct: Float3::new([0.0, 0.0, 0.0]),
+            lights: vec![Float3::new([0.0, 0.0, 0.0]); light_count],
+            alpha: 1.0,
+            albedo: Float3::new([0.0, 0.0, 0.0]),
+            normal: Float3::new([0.0, 0.0, 0.0]),
+            position: Float3::new([0.0, 0.0, 0.0]),
+            depth: 0.0,
+            object_id: None,
+            material_id: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let mut lights = std::mem::take(&mut self.lights);
+        lights.fill(Float3::new([0.0, 0.0, 0.0]));
+        *self = Self {
+            lights,
+            ..Self::new(0)
+        };
+    }
+
+    pub fn to_rgb(&self, radiance: Float3) -> Float3 {
+        match &self.wavelengths {
+            Some(wavelengths) => wavelengths.to_rgb(radiance),
+            None => radiance,
+        }
+    }
+
+    pub fn discard_radiance(&mut self) {
+        self.color = Float3::new([0.0, 0.0, 0.0]);
+        self.direct = Float3::new([0.0, 0.0, 0.0]);
+        self.lights.fill(Float3::new([0.0, 0.0, 0.0]));
+    }
+}
+
+#[derive(Debug)]
+struct FilmChannel {
+    channel: Channel,
+    pixels: Vec<FilmPixel>,
+}
+
+#[derive(Debug)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    filter: Filter,
+    filter_mode: FilterMode,
+    channels: Vec<FilmChannel>,
+    splat_scale: Float0,
+}
+
+impl Film {
+    pub fn new(
+        width: usize,
+        height: usize,
+        filter: Filter,
+        filter_mode: FilterMode,
+        channels: &[Channel],
+    ) -> Self {
+        let mut film_channels = vec![FilmChannel {
+            channel: Channel::Beauty,
+            pixels: Self::pixels(width, height),
+        }];
+        for channel in channels {
+            if film_channels
+                .iter()
+                .all(|existing| existing.channel != *channel)
+            {
+                film_channels.push(FilmChannel {
+                    channel: *channel,
+                    pixels: Self::pixels(width, height),
+                });
+            }
+        }
+        Self {
+            width,
+            height,
+            filter,
+            filter_mode,
+            channels: film_channels,
+            splat_scale: 1.0,
+        }
+    }
+
+    // Splats are summed rather than averaged, and scaled by this when
+    // resolved: one over the number of light paths traced per pixel.
+    pub fn with_splat_scale(mut self, splat_scale: Float0) -> Self {
+        self.splat_scale = splat_scale;
+        self
+    }
+
+    fn pixels(width: usize, height: usize) -> Vec<FilmPixel> {
+        (0..width * height).map(|_| FilmPixel::default()).collect()
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    pub fn filter_mode(&self) -> FilterMode {
+        self.filter_mode
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = Channel> + '_ {
+        self.channels.iter().map(|channel| channel.channel)
+    }
+
+    pub fn has_channel(&self, channel: Channel) -> bool {
+        self.channels().any(|existing| existing == channel)
+    }
+
+    // `position` is the film-space sample location and `weight` the filter
+    // importance weight; in splat mode the weight comes from the filter instead.
+    // Safe to call from several threads.
+    pub fn add_sample(
+        &self,
+        x: usize,
+        y: usize,
+        position: Float2,
+        weight: Float0,
+        sample_index: usize,
+        path: &PathSample,
+    ) {
+        let index = y * self.width + x;
+        for film_channel in &self.channels {
+            let channel = film_channel.channel;
+            let value = channel.value(path);
+            if channel.is_filtered() {
+                match self.filter_mode {
+                    FilterMode::Importance => film_channel.pixels[index].add(value, weight),
+                    FilterMode::Splat => self.splat(&film_channel.pixels, position, value),
+                }
+            } else if channel == Channel::SampleCount || sample_index == 0 {
+                film_channel.pixels[index].add(value, 1.0);
+            }
+        }
+    }
+
+    // Adds `color` to every pixel whose center lies within the filter radius of
+    // `position`, weighted by the filter.
+    fn splat(&self, pixels: &[FilmPixel], position: Float2, color: Float3) {
+        let radius = self.filter.radius();
+        let min_x = (position.x() - 0.5 - radius).ceil().max(0.0) as usize;
+        let min_y = (position.y() - 0.5 - radius).ceil().max(0.0) as usize;
+        let max_x = ((position.x() - 0.5 + radius).floor() as isize).min(self.width as isize - 1);
+        let max_y = ((position.y() - 0.5 + radius).floor() as isize).min(self.height as isize - 1);
+        if max_x < 0 || max_y < 0 {
+            return;
+        }
+
+        for y in min_y..=max_y as usize {
+            for x in min_x..=max_x as usize {
+                let offset = Float2::new([
+                    x as Float0 + 0.5 - position.x(),
+                    y as Float0 + 0.5 - position.y(),
+                ]);
+                let weight = self.filter.evaluate(offset);
+                if weight != 0.0 {
+                    pixels[y * self.width + x].add(color, weight);
+                }
+            }
+        }
+    }
+
+    // Adds light carried by a path from a light to the pixel it reaches at
+    // `position`, unfiltered. Only the beauty receives it.
+    pub fn add_splat(&self, position: Float2, color: Float3) {
+        if position.x() < 0.0 || position.y() < 0.0 {
+            return;
+        }
+        let (x, y) = (position.x() as usize, position.y() as usize);
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        for film_channel in &self.channels {
+            if matches!(film_channel.channel, Channel::Beauty | Channel::Raw) {
+                film_channel.pixels[y * self.width + x].add_splat(color);
+            }
+        }
+    }
+
+    pub fn resolve(&self, channel: Channel) -> Option<Vec<Float3>> {
+        let film_channel = self
+            .channels
+            .iter()
+            .find(|film_channel| film_channel.channel == channel)?;
+        Some(
+            film_channel
+                .pixels
+                .iter()
+                .map(|pixel| {
+                    let sum = Float3::new(pixel.color.each_ref().map(AtomicFloat::get));
+                    let weight = pixel.weight.get();
+                    let splat = Float3::new(pixel.splat.each_ref().map(AtomicFloat::get))
+                        .scale(self.splat_scale);
+                    if channel == Channel::SampleCount {
+                        sum
+                    } else if weight == 0.0 {
+                        splat
+                    } else {
+                        sum.scale(1.0 / weight) + splat
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    // Replaces a channel with an already resolved image, e.g. after denoising.
+    pub fn set(&self, channel: Channel, image: &[Float3]) {
+        if let Some(film_channel) = self
+            .channels
+            .iter()
+            .find(|film_channel| film_channel.channel == channel)
+        {
+            for (pixel, value) in film_channel.pixels.iter().zip(image) {
+                pixel.set(*value);
+            }
+        }
+    }
+
+    pub fn beauty(&self) -> Vec<Float3> {
+        self.resolve(Channel::Beauty)
+            .expect("film always has a beauty channel")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::prelude::*;
+
+    use crate::filter::FilterType;
+
+    use super::*;
+
+    fn sample(color: [Float0; 3]) -> PathSample {
+        PathSample {
+            color: Float3::new(color),
+            ..PathSample::new(0)
+        }
+    }
+
+    #[test]
+    fn splat_reaches_neighbouring_pixels() {
+        let film = Film::new(
+            3,
+            3,
+            Filter::new(FilterType::Tent, 1.0),
+            FilterMode::Splat,
+            &[],
+        );
+        film.add_sample(1, 1, Float2::new([2.0, 1.5]), 1.0, 0, &sample([1.0; 3]));
+
+        let pixels = &film.channels[0].pixels;
+        assert_eq!(pixels[4].weight.get(), 0.5);
+        assert_eq!(pixels[5].weight.get(), 0.5);
+        assert_eq!(pixels[3].weight.get(), 0.0);
+
+        let image = film.beauty();
+        assert_eq!(image[4].0, [1.0, 1.0, 1.0]);
+        assert_eq!(image[3].0, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn concurrent_samples_are_not_lost() {
+        let film = Film::new(
+            1,
+            1,
+            Filter::default(),
+            FilterMode::Importance,
+            &[Channel::SampleCount],
+        );
+        (0..10_000).into_par_iter().for_each(|index| {
+            film.add_sample(
+                0,
+                0,
+                Float2::new([0.5, 0.5]),
+                1.0,
+                index,
+                &sample([1.0, 2.0, 3.0]),
+            );
+        });
+        assert_eq!(film.beauty()[0].0, [1.0, 2.0, 3.0]);
+        assert_eq!(
+            film.resolve(Channel::SampleCount).unwrap()[0].0,
+            [10_000.0; 3]
+        );
+    }
+
+    #[test]
+    fn channel_names_round_trip() {
+        for channel in [
+            Channel::Alpha,
+            Channel::Albedo,
+            Channel::ObjectId,
+            Channel::Light(3),
+            Channel::SampleCount,
+        ] {
+            assert_eq!(channel.to_string().parse(), Ok(channel));
+        }
+    }
+
+    #[test]
+    fn ids_keep_the_first_sample() {
+        let film = Film::new(
+            1,
+            1,
+            Filter::default(),
+            FilterMode::Importance,
+            &[Channel::ObjectId],
+        );
+        for (sample_index, object_id) in [Some(4), None, Some(2)].into_iter().enumerate() {
+            let path = PathSample {
+                object_id,
+                ..PathSample::new(0)
+            };
+            film.add_sample(0, 0, Float2::new([0.5, 0.5]), 1.0, sample_index, &path);
+        }
+        assert_eq!(film.resolve(Channel::ObjectId).unwrap()[0].0, [5.0; 3]);
+    }
+}
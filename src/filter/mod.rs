@@ -0,0 +1,204 @@
+use std::str::FromStr;
+
+use crate::{
+    scene::{Float0, PI},
+    utils::{distribution::Distribution1D, vector::Float2},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterType {
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FromStr for FilterType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FilterType, ()> {
+        match s {
+            "box" => Ok(Self::Box),
+            "tent" | "triangle" => Ok(Self::Tent),
+            "gaussian" => Ok(Self::Gaussian),
+            "mitchell" | "mitchell_netravali" => Ok(Self::Mitchell),
+            "lanczos" => Ok(Self::Lanczos),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FilterType {
+    pub fn default_radius(&self) -> Float0 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.0,
+            Self::Lanczos => 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    #[default]
+    Importance,
+    Splat,
+}
+
+impl FromStr for FilterMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FilterMode, ()> {
+        match s {
+            "importance" => Ok(Self::Importance),
+            "splat" => Ok(Self::Splat),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FilterSample {
+    pub offset: Float2,
+    pub weight: Float0,
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    filter_type: FilterType,
+    radius: Float0,
+    distribution: Distribution1D,
+}
+
+impl Filter {
+    pub fn new(filter_type: FilterType, radius: Float0) -> Self {
+        assert!(
+            radius > 0.0 && radius.is_finite(),
+            "filter radius must be positive, not {radius}"
+        );
+        let buckets = (64.0 * radius).ceil() as usize;
+        let func: Vec<Float0> = (0..buckets)
+            .map(|bucket| {
+                let x = -radius + (bucket as Float0 + 0.5) * 2.0 * radius / buckets as Float0;
+                evaluate_1d(filter_type, radius, x)
+            })
+            .collect();
+        Self {
+            filter_type,
+            radius,
+            distribution: Distribution1D::new(&func, -radius, radius),
+        }
+    }
+
+    pub fn filter_type(&self) -> FilterType {
+        self.filter_type
+    }
+
+    pub fn radius(&self) -> Float0 {
+        self.radius
+    }
+
+    pub fn evaluate(&self, offset: Float2) -> Float0 {
+        evaluate_1d(self.filter_type, self.radius, offset.x())
+            * evaluate_1d(self.filter_type, self.radius, offset.y())
+    }
+
+    // Filter importance sampling: offsets are drawn proportionally to |f| and
+    // carry a signed weight so negative lobes still contribute.
+    pub fn sample(&self, u: Float2) -> FilterSample {
+        let (x, pdf_x, _) = self.distribution.sample_continuous(u.x());
+        let (y, pdf_y, _) = self.distribution.sample_continuous(u.y());
+        let offset = Float2::new([x, y]);
+        let pdf = pdf_x * pdf_y;
+        FilterSample {
+            offset,
+            weight: if pdf > 0.0 {
+                self.evaluate(offset) / pdf
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        let filter_type = FilterType::default();
+        Self::new(filter_type, filter_type.default_radius())
+    }
+}
+
+fn evaluate_1d(filter_type: FilterType, radius: Float0, x: Float0) -> Float0 {
+    let x = x.abs();
+    if x > radius {
+        return 0.0;
+    }
+    match filter_type {
+        FilterType::Box => 1.0,
+        FilterType::Tent => radius - x,
+        FilterType::Gaussian => {
+            let sigma = radius / 3.0;
+            let gaussian = |x: Float0| (-x * x / (2.0 * sigma * sigma)).exp();
+            (gaussian(x) - gaussian(radius)).max(0.0)
+        }
+        FilterType::Mitchell => mitchell_1d(2.0 * x / radius, 1.0 / 3.0, 1.0 / 3.0),
+        FilterType::Lanczos => sinc(x) * sinc(x / radius),
+    }
+}
+
+fn mitchell_1d(x: Float0, b: Float0, c: Float0) -> Float0 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: Float0) -> Float0 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_filter_samples_uniformly_inside_the_pixel() {
+        let filter = Filter::default();
+        let sample = filter.sample(Float2::new([0.25, 0.75]));
+        assert!((sample.offset.x() + 0.25).abs() < 1e-9);
+        assert!((sample.offset.y() - 0.25).abs() < 1e-9);
+        assert!((sample.weight - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mitchell_filter_has_negative_lobes() {
+        let filter = Filter::new(FilterType::Mitchell, 2.0);
+        assert!(filter.evaluate(Float2::new([0.0, 0.0])) > 0.0);
+        assert!(filter.evaluate(Float2::new([1.5, 0.0])) < 0.0);
+
+        let negative = (0..64)
+            .map(|i| filter.sample(Float2::new([(i as Float0 + 0.5) / 64.0, 0.5])))
+            .filter(|sample| sample.weight < 0.0)
+            .count();
+        assert!(negative > 0);
+    }
+}
@@ -0,0 +1,814 @@
+use crate::{
+    film::{Film, PathSample},
+    material::{
+        Material, SamplingFunctions,
+        dielectric::{self, Ior},
+    },
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI, Scene, ShadingPoint},
+    spectrum,
+    utils::vector::{Float2, Float3},
+};
+
+use super::{
+    CameraSample, EmissionSampler, Integrator, RenderSettings, trace_camera_samples,
+    without_path_traced_features,
+};
+
+// Bidirectional path tracing after Veach, as structured in pbrt-v3: a
+// subpath from the camera and one from a light chosen by power are
+// connected at every pair of vertices, and each of these strategies is
+// weighted by the balance heuristic against all others that could have
+// made the same path. Paths ending at the camera's first vertex are light
+// tracing and splat onto the film wherever they land.
+//
+// Surfaces scatter as in `Scene::trace_ray`. Scenes with media, subsurface
+// materials, shadow catchers or shadow flags are refused: a subpath from a
+// light can't pass through objects that don't cast shadows while lighting
+// them too.
+#[derive(Debug, Default)]
+pub struct BidirectionalIntegrator;
+
+impl Integrator for BidirectionalIntegrator {
+    fn render(&self, scene: &Scene, film: &Film, settings: &RenderSettings) {
+        let bdpt = Bdpt::new(scene, film, settings);
+        trace_camera_samples(scene, film, settings, |sample, sampler, path| {
+            bdpt.trace(sample, sampler, path)
+        });
+    }
+
+    fn supports(&self, scene: &Scene) -> Result<(), String> {
+        without_path_traced_features(scene, "bdpt")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light(usize),
+    // A light at infinity, shining from `direction`. Camera paths that
+    // leave the scene end at one, the skybox's light if it has one.
+    InfiniteLight {
+        light: Option<usize>,
+        direction: Float3,
+    },
+    Surface(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    kind: VertexKind,
+    point: Float3,
+    // On surfaces, facing the side the subpath arrived from; on area
+    // lights, the side the light leaves from.
+    normal: Option<Float3>,
+    // Diffuse reflectance, in the path's wavelengths.
+    albedo: Float3,
+    beta: Float3,
+    // Densities per unit area of sampling this vertex from its neighbour
+    // on its own subpath, and from the neighbour on the other side.
+    pdf_fwd: Float0,
+    pdf_rev: Float0,
+    delta: bool,
+}
+
+impl Vertex {
+    fn new(kind: VertexKind, point: Float3, normal: Option<Float3>, beta: Float3) -> Self {
+        Self {
+            kind,
+            point,
+            normal,
+            albedo: Float3::new([0.0, 0.0, 0.0]),
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn is_infinite(&self) -> bool {
+        matches!(self.kind, VertexKind::InfiniteLight { .. })
+    }
+
+    // Whether another subpath can be joined to this vertex.
+    fn is_connectible(&self) -> bool {
+        matches!(self.kind, VertexKind::Surface(_)) && !self.delta
+    }
+
+    fn light_index(&self) -> Option<usize> {
+        match self.kind {
+            VertexKind::Light(index)
+            | VertexKind::InfiniteLight {
+                light: Some(index), ..
+            } => Some(index),
+            _ => None,
+        }
+    }
+
+    fn object(&self) -> Option<usize> {
+        match self.kind {
+            VertexKind::Surface(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    // Unit direction towards `other` and the squared distance to it.
+    fn towards(&self, other: &Vertex) -> (Float3, Float0) {
+        match (self.kind, other.kind) {
+            (_, VertexKind::InfiniteLight { direction, .. }) => (direction, Float0::INFINITY),
+            (VertexKind::InfiniteLight { direction, .. }, _) => (-direction, Float0::INFINITY),
+            _ => {
+                let offset = other.point - self.point;
+                let distance_squared = offset.length_squared();
+                (
+                    offset.scale(distance_squared.sqrt().recip()),
+                    distance_squared,
+                )
+            }
+        }
+    }
+
+    fn cos_towards(&self, other: &Vertex) -> Float0 {
+        self.normal
+            .map_or(1.0, |normal| normal.dot(&self.towards(other).0).abs())
+    }
+
+    // Turns a density per solid angle at this vertex into one per unit area
+    // at `next`. Rays from a light at infinity are sampled per unit area of
+    // the disk they leave from instead.
+    fn convert_density(&self, pdf: Float0, next: &Vertex) -> Float0 {
+        if next.is_infinite() {
+            return pdf;
+        }
+        if self.is_infinite() {
+            return pdf * next.cos_towards(self);
+        }
+        let (_, distance_squared) = self.towards(next);
+        pdf * next.cos_towards(self) / distance_squared
+    }
+
+    // The BSDF towards `next`, from whichever side of the surface the
+    // subpath is on.
+    fn f(&self, next: &Vertex) -> Float3 {
+        match (self.kind, self.normal) {
+            (VertexKind::Surface(_), Some(normal)) if !self.delta => {
+                if normal.dot(&self.towards(next).0) > 0.0 {
+                    self.albedo.scale(1.0 / PI)
+                } else {
+                    Float3::new([0.0, 0.0, 0.0])
+                }
+            }
+            _ => Float3::new([0.0, 0.0, 0.0]),
+        }
+    }
+}
+
+struct Bdpt<'a> {
+    scene: &'a Scene,
+    film: &'a Film,
+    width: Float0,
+    height: Float0,
+    max_depth: usize,
+    lights: EmissionSampler,
+    camera_forward: Float3,
+    // Area of the image plane at unit distance from the camera.
+    image_area: Float0,
+}
+
+// A subpath's first ray and what it carries. Light subpaths remember their
+// light for its links.
+struct Walk {
+    ray: Ray,
+    beta: Float3,
+    pdf: Float0,
+    max_vertices: usize,
+    light: Option<usize>,
+}
+
+impl<'a> Bdpt<'a> {
+    fn new(scene: &'a Scene, film: &'a Film, settings: &RenderSettings) -> Self {
+        Self {
+            scene,
+            film,
+            width: settings.width as Float0,
+            height: settings.height as Float0,
+            max_depth: settings.max_depth as usize,
+            lights: EmissionSampler::new(scene),
+            camera_forward: scene.camera.forward(),
+            image_area: 4.0 * settings.width as Float0 / settings.height as Float0,
+        }
+    }
+
+    fn trace(&self, sample: &CameraSample, sampler: &mut dyn Sampler, path: &mut PathSample) {
+        path.reset();
+        path.wavelengths = sample.wavelengths;
+        let mut camera = Vec::with_capacity(self.max_depth + 2);
+        let mut light = Vec::with_capacity(self.max_depth + 1);
+        self.camera_subpath(sample.ray, sampler, path, &mut camera);
+        self.light_subpath(sampler, path, &mut light);
+
+        for t in 1..=camera.len() {
+            for s in 0..=light.len() {
+                if (s == 1 && t == 1) || s + t < 2 || s + t > self.max_depth + 2 {
+                    continue;
+                }
+                if t == 1 {
+                    if let Some((radiance, position)) = self.connect_to_camera(&light, s) {
+                        self.film.add_splat(position, path.to_rgb(radiance));
+                    }
+                    continue;
+                }
+                let (radiance, light_index) = match s {
+                    0 => (self.escaped(&camera, t, path), None),
+                    1 => self.connect_to_light(&camera, t, sampler, path),
+                    _ => (self.connect(&light, &camera, s, t), None),
+                };
+                if radiance.length_squared() == 0.0 {
+                    continue;
+                }
+                path.color += radiance;
+                let scattering_vertices = camera[1..t]
+                    .iter()
+                    .chain(light.iter().take(s).skip(1))
+                    .filter(|vertex| vertex.is_connectible())
+                    .count();
+                if scattering_vertices <= 1 {
+                    path.direct += radiance;
+                }
+                let light_index = light_index.or_else(|| {
+                    light
+                        .first()
+                        .filter(|_| s > 0)
+                        .and_then(Vertex::light_index)
+                });
+                if let Some(total) = light_index.and_then(|index| path.lights.get_mut(index)) {
+                    *total += radiance;
+                }
+            }
+        }
+    }
+
+    fn camera_subpath(
+        &self,
+        ray: Ray,
+        sampler: &mut dyn Sampler,
+        path: &mut PathSample,
+        vertices: &mut Vec<Vertex>,
+    ) {
+        let direction = ray.direction.normalize();
+        vertices.push(Vertex::new(
+            VertexKind::Camera,
+            ray.origin,
+            None,
+            Float3::new([1.0, 1.0, 1.0]),
+        ));
+        let walk = Walk {
+            ray: Ray::new(ray.origin, direction),
+            beta: Float3::new([1.0, 1.0, 1.0]),
+            pdf: self.camera_pdf(direction),
+            max_vertices: self.max_depth + 2,
+            light: None,
+        };
+        self.random_walk(walk, sampler, path, vertices);
+    }
+
+    fn light_subpath(
+        &self,
+        sampler: &mut dyn Sampler,
+        path: &mut PathSample,
+        vertices: &mut Vec<Vertex>,
+    ) {
+        let Some((light_index, pmf)) = self.lights.choose(sampler.get_1d()) else {
+            return;
+        };
+        let Some(emission) =
+            self.lights
+                .emit(self.scene, light_index, sampler, path.wavelengths.as_ref())
+        else {
+            return;
+        };
+        // Rays from a light at infinity are sampled per unit area of the disk
+        // they leave from, after their direction.
+        let (kind, pdf_fwd, pdf) = match emission.infinite {
+            Some(direction) => (
+                VertexKind::InfiniteLight {
+                    light: Some(light_index),
+                    direction,
+                },
+                pmf * emission.pdf_direction,
+                emission.pdf_position,
+            ),
+            None => (
+                VertexKind::Light(light_index),
+                pmf * emission.pdf_position,
+                emission.pdf_direction,
+            ),
+        };
+        vertices.push(Vertex {
+            pdf_fwd,
+            ..Vertex::new(
+                kind,
+                emission.ray.origin,
+                emission.normal,
+                emission.radiance,
+            )
+        });
+        let walk = Walk {
+            ray: emission.ray,
+            beta: emission.beta(pmf),
+            pdf,
+            max_vertices: self.max_depth + 1,
+            light: Some(light_index),
+        };
+        self.random_walk(walk, sampler, path, vertices);
+    }
+
+    // Extends a subpath from its first vertex. Camera subpaths also record
+    // what the pixel sees first, and end at the skybox if they escape.
+    fn random_walk(
+        &self,
+        walk: Walk,
+        sampler: &mut dyn Sampler,
+        path: &mut PathSample,
+        vertices: &mut Vec<Vertex>,
+    ) {
+        let scene = self.scene;
+        let from_camera = walk.light.is_none();
+        let Walk {
+            mut ray,
+            mut beta,
+            mut pdf,
+            ..
+        } = walk;
+        let mut guide_throughput = Float3::new([1.0, 1.0, 1.0]);
+        let mut found_diffuse = false;
+        while vertices.len() < walk.max_vertices {
+            let first_hit = vertices.len() == 1;
+            let hit = if from_camera && first_hit {
+                scene.hit_from_camera(&ray)
+            } else {
+                scene.hit_object(&ray, 0.001)
+            };
+            let previous = vertices.len() - 1;
+
+            let Some((object_index, record)) = hit else {
+                if from_camera {
+                    if first_hit {
+                        path.alpha = 0.0;
+                        if !scene.background_visible() {
+                            return;
+                        }
+                    }
+                    if !found_diffuse {
+                        path.albedo = guide_throughput * scene.skybox.radiance(ray.direction);
+                    }
+                    let mut vertex = Vertex::new(
+                        VertexKind::InfiniteLight {
+                            light: scene.sky_light(),
+                            direction: ray.direction,
+                        },
+                        ray.origin,
+                        None,
+                        beta,
+                    );
+                    vertex.pdf_fwd = vertices[previous].convert_density(pdf, &vertex);
+                    vertices.push(vertex);
+                }
+                return;
+            };
+
+            if from_camera && first_hit {
+                path.position = record.point;
+                path.depth = record.t;
+                path.object_id = Some(object_index);
+                path.material_id = Some(scene.material_id(object_index));
+                if scene.visibility(object_index).holdout {
+                    path.alpha = 0.0;
+                    return;
+                }
+            }
+            if let Some(light) = walk.light
+                && first_hit
+                && !scene.light_links(light).illuminates(object_index)
+            {
+                return;
+            }
+
+            let material = record.material;
+            let direction = ray.direction;
+            let mut vertex = Vertex::new(
+                VertexKind::Surface(object_index),
+                record.point,
+                Some(record.normal),
+                beta,
+            );
+            vertex.pdf_fwd = vertices[previous].convert_density(pdf, &vertex);
+
+            let next = if let Some(ior) = &material.ior {
+                if material.is_interface() {
+                    ray = Ray::new(record.point + direction.scale(0.001), direction);
+                    continue;
+                }
+                let lambda = match path.wavelengths.as_mut() {
+                    Some(wavelengths) => {
+                        if ior.is_dispersive() {
+                            wavelengths.terminate_secondary();
+                        }
+                        wavelengths.hero()
+                    }
+                    None => Ior::REFERENCE_WAVELENGTH,
+                };
+                let eta = if record.front_face {
+                    ior.at(lambda).recip()
+                } else {
+                    ior.at(lambda)
+                };
+                let reflectance = dielectric::fresnel(-direction.dot(&record.normal), eta);
+                let refracted = dielectric::refract(&direction, &record.normal, eta)
+                    .filter(|_| sampler.get_1d() >= reflectance);
+                vertex.delta = true;
+                match refracted {
+                    Some(refracted) => {
+                        let tint = material.color(&record.uv);
+                        beta *= spectrum::uplift(tint, path.wavelengths.as_ref());
+                        guide_throughput *= tint;
+                        refracted
+                    }
+                    None => Material::reflect(&direction, &record.normal),
+                }
+            } else if material.subsurface.is_none() && material.reflectivity == 1.0 {
+                vertex.delta = true;
+                Material::reflect(&direction, &record.normal)
+            } else {
+                let color = material.color(&record.uv);
+                if from_camera && !found_diffuse {
+                    path.albedo = guide_throughput * color;
+                    path.normal = record.normal;
+                }
+                found_diffuse = true;
+                vertex.albedo = spectrum::uplift(color, path.wavelengths.as_ref());
+                vertices.push(vertex);
+                if vertices.len() == walk.max_vertices {
+                    return;
+                }
+
+                let (scattered, scatter_pdf) =
+                    material.scatter(&record, sampler, &SamplingFunctions::CosineWeightedSample1);
+                let cos_theta = scattered.direction.dot(&record.normal);
+                if scatter_pdf <= 0.0 || cos_theta <= 0.0 {
+                    return;
+                }
+                beta *= vertex.albedo.scale(cos_theta / (PI * scatter_pdf));
+                let pdf_rev = record.normal.dot(&-direction).max(0.0) / PI;
+                vertices[previous].pdf_rev = vertex.convert_density(pdf_rev, &vertices[previous]);
+                pdf = scatter_pdf;
+                ray = scattered;
+                continue;
+            };
+
+            // Specular vertices can't be connected to, so their densities
+            // stay zero and drop out of the weights.
+            vertices.push(vertex);
+            vertices[previous].pdf_rev = 0.0;
+            pdf = 0.0;
+            ray = Ray::new(record.point + next.scale(0.001), next);
+        }
+    }
+
+    // Density per solid angle of the camera sampling `direction`.
+    fn camera_pdf(&self, direction: Float3) -> Float0 {
+        let cos_theta = self.camera_forward.dot(&direction);
+        let camera = &self.scene.camera;
+        if cos_theta <= 0.0
+            || camera
+                .project(camera.position + direction, self.width, self.height)
+                .is_none()
+        {
+            return 0.0;
+        }
+        1.0 / (self.image_area * cos_theta.powi(3))
+    }
+
+    // Density per unit area at `next` of `vertex` sampling it as the next
+    // vertex on its subpath.
+    fn pdf(&self, vertex: &Vertex, next: &Vertex) -> Float0 {
+        match vertex.kind {
+            VertexKind::Camera => {
+                vertex.convert_density(self.camera_pdf(vertex.towards(next).0), next)
+            }
+            VertexKind::Light(_) | VertexKind::InfiniteLight { .. } => self.light_pdf(vertex, next),
+            VertexKind::Surface(_) => match vertex.normal {
+                Some(normal) if !vertex.delta => {
+                    let cos_theta = normal.dot(&vertex.towards(next).0).max(0.0);
+                    vertex.convert_density(cos_theta / PI, next)
+                }
+                _ => 0.0,
+            },
+        }
+    }
+
+    // Density per unit area at `next` of a light subpath from `light`
+    // reaching it first.
+    fn light_pdf(&self, light: &Vertex, next: &Vertex) -> Float0 {
+        let pdf = match light.kind {
+            VertexKind::Light(index) => {
+                self.scene.lights[index]
+                    .emission_pdf(light.point, light.towards(next).0)
+                    .1
+            }
+            _ => {
+                let (_, radius) = self.scene.bounding_sphere();
+                1.0 / (PI * radius * radius)
+            }
+        };
+        light.convert_density(pdf, next)
+    }
+
+    // Whether the light at the start of a light subpath could also be
+    // found by a camera subpath.
+    fn is_hittable(&self, light: &Vertex) -> bool {
+        light
+            .light_index()
+            .is_some_and(|index| self.scene.lights[index].found_by_scattering())
+    }
+
+    // Nothing but index-matched boundaries between the points. Objects
+    // hidden from the camera don't block what it sees.
+    fn unoccluded(&self, from: Float3, to: Float3, from_camera: bool) -> bool {
+        let offset = to - from;
+        let mut remaining = offset.length();
+        let direction = offset.scale(remaining.recip());
+        let mut origin = from;
+        loop {
+            let hit = self
+                .scene
+                .hit_object(&Ray::new(origin, direction), 0.001)
+                .filter(|(_, record)| record.t < remaining - 0.001);
+            match hit {
+                None => return true,
+                Some((object_index, record)) => {
+                    let hidden = from_camera && !self.scene.visibility(object_index).camera;
+                    if !record.material.is_interface() && !hidden {
+                        return false;
+                    }
+                    origin = record.point;
+                    remaining -= record.t;
+                }
+            }
+        }
+    }
+
+    // The camera subpath of length `t` ends in the sky.
+    fn escaped(&self, camera: &[Vertex], t: usize, path: &PathSample) -> Float3 {
+        let pt = &camera[t - 1];
+        let VertexKind::InfiniteLight { light, direction } = pt.kind else {
+            return Float3::new([0.0, 0.0, 0.0]);
+        };
+        let selection_pdf = light.map_or(0.0, |light| self.lights.pmf(light));
+        let radiance = self.scene.skybox.weighted_radiance(direction, |light_pdf| {
+            self.mis_weight(&[], camera, 0, t, None, selection_pdf * light_pdf)
+        });
+        pt.beta * spectrum::uplift(radiance, path.wavelengths.as_ref())
+    }
+
+    // Next-event estimation at the end of the camera subpath of length `t`.
+    fn connect_to_light(
+        &self,
+        camera: &[Vertex],
+        t: usize,
+        sampler: &mut dyn Sampler,
+        path: &PathSample,
+    ) -> (Float3, Option<usize>) {
+        let zero = (Float3::new([0.0, 0.0, 0.0]), None);
+        let pt = &camera[t - 1];
+        if !pt.is_connectible() {
+            return zero;
+        }
+        let Some((light_index, pmf)) = self.lights.choose(sampler.get_1d()) else {
+            return zero;
+        };
+        if pt
+            .object()
+            .is_some_and(|object| !self.scene.light_links(light_index).illuminates(object))
+        {
+            return zero;
+        }
+        let light = &self.scene.lights[light_index];
+        let sample = light.sample(pt.point, sampler, path.wavelengths.as_ref());
+        if sample.pdf <= 0.0 || sample.radiance.length_squared() == 0.0 {
+            return zero;
+        }
+        let beta = sample.radiance.scale(1.0 / (sample.pdf * pmf));
+        let vertex = if self.lights.is_infinite(light_index) {
+            Vertex {
+                pdf_fwd: pmf * if sample.delta { 1.0 } else { sample.pdf },
+                ..Vertex::new(
+                    VertexKind::InfiniteLight {
+                        light: Some(light_index),
+                        direction: sample.direction,
+                    },
+                    pt.point,
+                    None,
+                    beta,
+                )
+            }
+        } else {
+            let point = pt.point + sample.direction.scale(sample.distance);
+            Vertex {
+                pdf_fwd: pmf * light.emission_pdf(point, -sample.direction).0,
+                ..Vertex::new(VertexKind::Light(light_index), point, None, beta)
+            }
+        };
+
+        let radiance = pt.beta * pt.f(&vertex) * beta.scale(pt.cos_towards(&vertex));
+        if radiance.length_squared() == 0.0 {
+            return zero;
+        }
+        let transmittance = self.scene.transmittance_to_light(
+            ShadingPoint {
+                point: pt.point,
+                normal: pt.normal,
+                object: pt.object(),
+            },
+            light_index,
+            &sample,
+            None,
+            sampler,
+            path.wavelengths.as_ref(),
+        );
+        if transmittance.length_squared() == 0.0 {
+            return zero;
+        }
+        let weight = self.mis_weight(&[], camera, 1, t, Some(&vertex), 0.0);
+        (radiance * transmittance.scale(weight), Some(light_index))
+    }
+
+    // Joins the light subpath of length `s` to the camera, returning what
+    // it adds and where on the image.
+    fn connect_to_camera(&self, light: &[Vertex], s: usize) -> Option<(Float3, Float2)> {
+        let qs = &light[s - 1];
+        if !qs.is_connectible() {
+            return None;
+        }
+        let object = qs.object()?;
+        let visibility = self.scene.visibility(object);
+        if !visibility.camera || visibility.holdout {
+            return None;
+        }
+        let camera = &self.scene.camera;
+        let position = camera.project(qs.point, self.width, self.height)?;
+        let offset = qs.point - camera.position;
+        let distance_squared = offset.length_squared();
+        let cos_theta = self
+            .camera_forward
+            .dot(&offset.scale(distance_squared.sqrt().recip()));
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        // Importance of a pinhole spread evenly over the image plane, and
+        // the density of having picked the pinhole from `qs`.
+        let importance = 1.0 / (self.image_area * cos_theta.powi(4));
+        let pdf = distance_squared / cos_theta;
+        let vertex = Vertex::new(
+            VertexKind::Camera,
+            camera.position,
+            None,
+            Float3::new([importance / pdf; 3]),
+        );
+
+        let radiance = qs.beta * qs.f(&vertex) * vertex.beta.scale(qs.cos_towards(&vertex));
+        if radiance.length_squared() == 0.0 || !self.unoccluded(camera.position, qs.point, true) {
+            return None;
+        }
+        let weight = self.mis_weight(light, &[], s, 1, Some(&vertex), 0.0);
+        Some((radiance.scale(weight), position))
+    }
+
+    // Joins subpaths whose ends are both on surfaces.
+    fn connect(&self, light: &[Vertex], camera: &[Vertex], s: usize, t: usize) -> Float3 {
+        let zero = Float3::new([0.0, 0.0, 0.0]);
+        let (qs, pt) = (&light[s - 1], &camera[t - 1]);
+        if !qs.is_connectible() || !pt.is_connectible() {
+            return zero;
+        }
+        let radiance = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta;
+        if radiance.length_squared() == 0.0 {
+            return zero;
+        }
+        let (_, distance_squared) = qs.towards(pt);
+        let geometry = qs.cos_towards(pt) * pt.cos_towards(qs) / distance_squared;
+        if geometry == 0.0 || !self.unoccluded(qs.point, pt.point, false) {
+            return zero;
+        }
+        let weight = self.mis_weight(light, camera, s, t, None, 0.0);
+        radiance.scale(geometry * weight)
+    }
+
+    // Balance heuristic weight of the strategy joining `s` light and `t`
+    // camera vertices, against every other strategy for the same path.
+    // `sampled` replaces the lone light or camera vertex when s or t is 1,
+    // and `sky_pdf` is the density of sampling the sky when s is 0.
+    fn mis_weight(
+        &self,
+        light: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        t: usize,
+        sampled: Option<&Vertex>,
+        sky_pdf: Float0,
+    ) -> Float0 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let remap = |pdf: Float0| if pdf != 0.0 { pdf } else { 1.0 };
+        let densities = |vertex: &Vertex| (vertex.pdf_fwd, vertex.pdf_rev, vertex.delta);
+        let mut light_densities: Vec<_> = light.iter().take(s).map(densities).collect();
+        let mut camera_densities: Vec<_> = camera.iter().take(t).map(densities).collect();
+        let (qs, pt) = match (s, t) {
+            (0, _) => (None, &camera[t - 1]),
+            (1, _) => {
+                let sampled = sampled.expect("light vertex");
+                light_densities = vec![densities(sampled)];
+                (Some(sampled), &camera[t - 1])
+            }
+            (_, 1) => {
+                let sampled = sampled.expect("camera vertex");
+                camera_densities = vec![densities(sampled)];
+                (Some(&light[s - 1]), sampled)
+            }
+            _ => (Some(&light[s - 1]), &camera[t - 1]),
+        };
+        let qs_minus = (s > 1).then(|| &light[s - 2]);
+        let pt_minus = (t > 1).then(|| &camera[t - 2]);
+
+        // The connected vertices as the other strategies would have
+        // sampled them.
+        camera_densities[t - 1].2 = false;
+        camera_densities[t - 1].1 = match qs {
+            Some(qs) => self.pdf(qs, pt),
+            None => sky_pdf,
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera_densities[t - 2].1 = match qs {
+                Some(_) => self.pdf(pt, pt_minus),
+                None => self.light_pdf(pt, pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light_densities[s - 1].2 = false;
+            light_densities[s - 1].1 = self.pdf(pt, qs);
+            if let Some(qs_minus) = qs_minus {
+                light_densities[s - 2].1 = self.pdf(qs, qs_minus);
+            }
+        }
+
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera_densities[i].1) / remap(camera_densities[i].0);
+            if !camera_densities[i].2 && !camera_densities[i - 1].2 {
+                sum += ratio;
+            }
+        }
+        // Light endpoints are never delta vertices themselves: a light that
+        // shines from a single point or direction only rules out camera
+        // paths finding it, the i = 0 term, and no such light can be hit.
+        let light_hittable = match s {
+            0 => false,
+            1 => qs.is_some_and(|qs| self.is_hittable(qs)),
+            _ => self.is_hittable(&light[0]),
+        };
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light_densities[i].1) / remap(light_densities[i].0);
+            let delta_before = if i > 0 {
+                light_densities[i - 1].2
+            } else {
+                !light_hittable
+            };
+            if !light_densities[i].2 && !delta_before {
+                sum += ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::testing::{
+        assert_agrees_with_the_path_tracer, assert_refuses_path_traced_features, settings,
+    };
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        assert_agrees_with_the_path_tracer(&BidirectionalIntegrator, &settings(16));
+    }
+
+    #[test]
+    fn refuses_what_only_the_path_tracer_renders() {
+        assert_refuses_path_traced_features(&BidirectionalIntegrator);
+    }
+}
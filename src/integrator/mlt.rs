@@ -0,0 +1,198 @@
+use rand::RngExt;
+use rayon::prelude::*;
+
+use crate::{
+    film::{Film, PathSample},
+    material::SamplingFunctions,
+    pathtracer::seeded_rng,
+    sampler::{Sampler, hash, metropolis::MetropolisSampler},
+    scene::{Float0, Scene},
+    spectrum::SampledWavelengths,
+    utils::{
+        distribution::Distribution1D,
+        vector::{Float2, Float3},
+    },
+};
+
+use super::{Integrator, RenderSettings, trace_camera_samples};
+
+// Primary sample space Metropolis light transport (Kelemen et al.) over the
+// path tracer, as in pbrt-v3. Bootstrap paths from random points estimate
+// the image's total brightness and seed Markov chains, which wander where
+// paths carry the most light and splat every path they visit onto the film,
+// so rare bright paths, like light through a small opening, are explored
+// once found. Every sample per pixel is one mutation, spread over the
+// chains.
+//
+// The chains only add to the beauty and denoiser input; one ordinary path
+// per pixel records the other channels.
+#[derive(Debug, Default)]
+pub struct MetropolisIntegrator;
+
+// How the Markov chains explore primary sample space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetropolisSettings {
+    // Paths traced to normalise the image and choose the chains' starts.
+    pub bootstrap_samples: usize,
+    pub chains: usize,
+    // Standard deviation of a small step, per coordinate.
+    pub sigma: Float0,
+    // Probability of a mutation drawing an entirely new path.
+    pub large_step_probability: Float0,
+}
+
+impl Default for MetropolisSettings {
+    fn default() -> Self {
+        Self {
+            bootstrap_samples: 100_000,
+            chains: 1000,
+            sigma: 0.01,
+            large_step_probability: 0.3,
+        }
+    }
+}
+
+impl Integrator for MetropolisIntegrator {
+    fn render(&self, scene: &Scene, film: &Film, settings: &RenderSettings) {
+        let guides = RenderSettings {
+            samples: 1,
+            ..settings.clone()
+        };
+        trace_camera_samples(scene, film, &guides, |sample, sampler, path| {
+            scene.trace_ray(
+                &sample.ray,
+                settings.max_depth,
+                sampler,
+                &sample.sample_type,
+                sample.wavelengths,
+                path,
+            );
+            path.discard_radiance();
+        });
+
+        let metropolis = settings.metropolis;
+        let weights = Float3::new(scene.color_space.rgb_to_xyz()[1]);
+        let new_sampler = |index: usize| {
+            MetropolisSampler::new(
+                hash(&[settings.seed, index as u64]),
+                metropolis.sigma,
+                metropolis.large_step_probability,
+            )
+        };
+        let bootstrap: Vec<Float0> = (0..metropolis.bootstrap_samples)
+            .into_par_iter()
+            .map(|index| {
+                let mut path = PathSample::new(0);
+                let (_, color) = evaluate(scene, settings, &mut new_sampler(index), &mut path);
+                luminance(color, weights)
+            })
+            .collect();
+        let brightness = bootstrap.iter().sum::<Float0>() / bootstrap.len().max(1) as Float0;
+        if brightness <= 0.0 || !brightness.is_finite() {
+            return;
+        }
+        let starts = Distribution1D::new(&bootstrap, 0.0, 1.0);
+
+        let chains = metropolis.chains.max(1);
+        let mutations = settings.samples * settings.width * settings.height;
+        (0..chains).into_par_iter().for_each(|chain| {
+            let mut rng = seeded_rng(hash(&[settings.seed, chain as u64, chains as u64]));
+            let (_, _, start) = starts.sample_continuous(rng.random_range(0.0..1.0));
+            let mut sampler = new_sampler(start);
+            let mut path = PathSample::new(0);
+            let (mut position, mut color) = evaluate(scene, settings, &mut sampler, &mut path);
+            let mut current = luminance(color, weights);
+
+            let chain_mutations = (chain + 1) * mutations / chains - chain * mutations / chains;
+            for _ in 0..chain_mutations {
+                sampler.start_iteration();
+                let (proposed_position, proposed_color) =
+                    evaluate(scene, settings, &mut sampler, &mut path);
+                let proposed = luminance(proposed_color, weights);
+                let acceptance = if current > 0.0 {
+                    (proposed / current).min(1.0)
+                } else {
+                    1.0
+                };
+
+                // Both paths are splatted in expectation of which is kept.
+                if acceptance > 0.0 && proposed > 0.0 {
+                    film.add_splat(
+                        proposed_position,
+                        proposed_color.scale(acceptance * brightness / proposed),
+                    );
+                }
+                if acceptance < 1.0 && current > 0.0 {
+                    film.add_splat(
+                        position,
+                        color.scale((1.0 - acceptance) * brightness / current),
+                    );
+                }
+
+                if rng.random_range(0.0..1.0) < acceptance {
+                    position = proposed_position;
+                    color = proposed_color;
+                    current = proposed;
+                    sampler.accept();
+                } else {
+                    sampler.reject();
+                }
+            }
+        });
+    }
+}
+
+// Traces the path at the sampler's current point: its first two
+// coordinates place it on the image, the rest drive the path tracer.
+fn evaluate(
+    scene: &Scene,
+    settings: &RenderSettings,
+    sampler: &mut MetropolisSampler,
+    path: &mut PathSample,
+) -> (Float2, Float3) {
+    let u = sampler.get_2d();
+    let (width, height) = (settings.width as Float0, settings.height as Float0);
+    let position = Float2::new([u.x() * width, u.y() * height]);
+    let ray = scene
+        .camera
+        .get_ray(position.x(), position.y(), width, height);
+    let wavelengths = settings
+        .spectral
+        .then(|| SampledWavelengths::sample_visible(sampler.get_1d(), scene.color_space));
+    scene.trace_ray(
+        &ray,
+        settings.max_depth,
+        sampler,
+        &SamplingFunctions::CosineWeightedSample1,
+        wavelengths,
+        path,
+    );
+    (position, path.to_rgb(path.color))
+}
+
+// Paths are chosen by their luminance, with `weights` the Y row of the
+// working space's RGB to XYZ matrix.
+fn luminance(color: Float3, weights: Float3) -> Float0 {
+    weights.dot(&color).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::testing::{assert_agrees_with_the_path_tracer, settings};
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        assert_agrees_with_the_path_tracer(
+            &MetropolisIntegrator,
+            &RenderSettings {
+                metropolis: MetropolisSettings {
+                    bootstrap_samples: 50_000,
+                    chains: 64,
+                    ..Default::default()
+                },
+                ..settings(16)
+            },
+        );
+    }
+}
@@ -0,0 +1,519 @@
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+use crate::{
+    film::{Channel, Film, PathSample},
+    filter::FilterMode,
+    material::{SamplingFunctions, generate_coordinate_system},
+    ray::Ray,
+    sampler::{Sampler, SamplerType},
+    scene::{Float0, PI, Scene},
+    spectrum::SampledWavelengths,
+    utils::{
+        distribution::Distribution1D,
+        vector::{Float2, Float3},
+    },
+};
+
+use bdpt::BidirectionalIntegrator;
+use mlt::{MetropolisIntegrator, MetropolisSettings};
+use path::PathIntegrator;
+use sppm::PhotonMappingIntegrator;
+
+pub mod bdpt;
+pub mod mlt;
+pub mod path;
+pub mod sppm;
+
+// How light transport is estimated: paths traced from the camera with
+// next-event estimation, bidirectional paths connecting subpaths from the
+// camera and from the lights, progressive photon mapping, or Metropolis
+// sampling of camera paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntegratorType {
+    #[default]
+    Path,
+    Bidirectional,
+    PhotonMapping,
+    Metropolis,
+}
+
+impl FromStr for IntegratorType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<IntegratorType, ()> {
+        match s {
+            "path" => Ok(Self::Path),
+            "bdpt" | "bidirectional" => Ok(Self::Bidirectional),
+            "sppm" | "photon" => Ok(Self::PhotonMapping),
+            "mlt" | "pssmlt" | "metropolis" => Ok(Self::Metropolis),
+            _ => Err(()),
+        }
+    }
+}
+
+impl IntegratorType {
+    pub fn build(&self) -> Box<dyn Integrator> {
+        match self {
+            Self::Path => Box::new(PathIntegrator),
+            Self::Bidirectional => Box::new(BidirectionalIntegrator),
+            Self::PhotonMapping => Box::new(PhotonMappingIntegrator),
+            Self::Metropolis => Box::new(MetropolisIntegrator),
+        }
+    }
+}
+
+// What an integrator needs to know about the render besides the scene.
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub seed: u64,
+    pub sampler: SamplerType,
+    pub spectral: bool,
+    // Splits the image between two ways of sampling diffuse bounces.
+    pub debug: bool,
+    // Longest path, in bounces.
+    pub max_depth: u32,
+    // Photons per photon mapping iteration, one per pixel by default.
+    pub photons: Option<usize>,
+    // Initial photon gather radius, a hundredth of the scene's by default.
+    pub photon_radius: Option<Float0>,
+    pub metropolis: MetropolisSettings,
+}
+
+pub trait Integrator: Sync {
+    // Adds its estimate of every pixel to `film`.
+    fn render(&self, scene: &Scene, film: &Film, settings: &RenderSettings);
+    // Refuses scenes the integrator would render differently from the path
+    // tracer.
+    fn supports(&self, _scene: &Scene) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// For integrators that only follow surfaces lit along plain rays.
+pub(crate) fn without_path_traced_features(scene: &Scene, integrator: &str) -> Result<(), String> {
+    let features = scene.path_traced_features();
+    if features.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the {integrator} integrator doesn't support {}; use the path tracer",
+            features.join(", ")
+        ))
+    }
+}
+
+// A ray through the image, and where and with what weight its estimate
+// lands on the film.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSample {
+    pub x: usize,
+    pub y: usize,
+    pub position: Float2,
+    pub weight: Float0,
+    pub ray: Ray,
+    pub sample_type: SamplingFunctions,
+    pub wavelengths: Option<SampledWavelengths>,
+}
+
+// Runs `trace` for every sample of every pixel, in parallel over pixels,
+// and adds the path it records to the film.
+pub fn trace_camera_samples(
+    scene: &Scene,
+    film: &Film,
+    settings: &RenderSettings,
+    trace: impl Fn(&CameraSample, &mut dyn Sampler, &mut PathSample) + Sync,
+) {
+    let light_count = light_count(scene, film);
+
+    (0..settings.width * settings.height)
+        .into_par_iter()
+        .for_each(|index| {
+            let mut sampler = settings.sampler.build(
+                settings.samples,
+                settings.width,
+                settings.height,
+                settings.seed,
+            );
+            let mut path = PathSample::new(light_count);
+
+            let x = index % settings.width;
+            let y = index / settings.width;
+
+            for sample_index in 0..settings.samples {
+                sampler.start_pixel_sample(x, y, sample_index);
+                let camera_sample = camera_sample(scene, film, settings, sampler.as_mut(), x, y);
+                trace(&camera_sample, sampler.as_mut(), &mut path);
+
+                film.add_sample(
+                    x,
+                    y,
+                    camera_sample.position,
+                    camera_sample.weight,
+                    sample_index,
+                    &path,
+                );
+            }
+        });
+}
+
+// Lights the film keeps a channel for, which paths must track.
+pub fn light_count(scene: &Scene, film: &Film) -> usize {
+    if film
+        .channels()
+        .any(|channel| matches!(channel, Channel::Light(_)))
+    {
+        scene.lights.len()
+    } else {
+        0
+    }
+}
+
+// Draws the next camera ray through pixel (`x`, `y`) from a sampler
+// already started on the sample.
+pub fn camera_sample(
+    scene: &Scene,
+    film: &Film,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    x: usize,
+    y: usize,
+) -> CameraSample {
+    let (position, weight) = match film.filter_mode() {
+        FilterMode::Importance => {
+            let filter_sample = film.filter().sample(sampler.get_2d());
+            let center = Float2::new([x as Float0 + 0.5, y as Float0 + 0.5]);
+            (center + filter_sample.offset, filter_sample.weight)
+        }
+        FilterMode::Splat => {
+            let jitter = sampler.get_2d();
+            (
+                Float2::new([x as Float0 + jitter.x(), y as Float0 + jitter.y()]),
+                1.0,
+            )
+        }
+    };
+    let ray = scene.camera.get_ray(
+        position.x(),
+        position.y(),
+        settings.width as Float0,
+        settings.height as Float0,
+    );
+    let is_left = x < settings.width / 2;
+
+    let sample_type = if settings.debug {
+        if is_left {
+            SamplingFunctions::RandomUnitVector
+        } else {
+            SamplingFunctions::CosineWeightedSample2
+        }
+    } else {
+        SamplingFunctions::CosineWeightedSample1
+    };
+    let wavelengths = settings
+        .spectral
+        .then(|| SampledWavelengths::sample_visible(sampler.get_1d(), scene.color_space));
+    CameraSample {
+        x,
+        y,
+        position,
+        weight,
+        ray,
+        sample_type,
+        wavelengths,
+    }
+}
+
+// A ray leaving a light. Lights at infinity send parallel rays from a disk
+// just outside the scene, facing the direction `infinite` sampled towards
+// the light from its centre.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Emission {
+    pub ray: Ray,
+    pub normal: Option<Float3>,
+    pub radiance: Float3,
+    pub pdf_position: Float0,
+    pub pdf_direction: Float0,
+    pub infinite: Option<Float3>,
+}
+
+impl Emission {
+    // Flux carried by the ray, from a light chosen with probability `pmf`.
+    pub fn beta(&self, pmf: Float0) -> Float3 {
+        let cos_theta = self
+            .normal
+            .map_or(1.0, |normal| normal.dot(&self.ray.direction).abs());
+        self.radiance
+            .scale(cos_theta / (pmf * self.pdf_position * self.pdf_direction))
+    }
+}
+
+// Chooses lights in proportion to their power and starts rays from them,
+// for integrators that trace light paths.
+pub(crate) struct EmissionSampler {
+    distribution: Distribution1D,
+    infinite: Vec<bool>,
+}
+
+impl EmissionSampler {
+    pub fn new(scene: &Scene) -> Self {
+        let powers: Vec<Float0> = scene
+            .lights
+            .iter()
+            .map(|light| light.intensity().max(0.0))
+            .collect();
+        Self {
+            distribution: Distribution1D::new(&powers, 0.0, 1.0),
+            infinite: scene
+                .lights
+                .iter()
+                .map(|light| light.bounds().is_none())
+                .collect(),
+        }
+    }
+
+    pub fn pmf(&self, light: usize) -> Float0 {
+        let count = self.infinite.len() as Float0;
+        self.distribution.pdf((light as Float0 + 0.5) / count) / count
+    }
+
+    pub fn choose(&self, u: Float0) -> Option<(usize, Float0)> {
+        if self.infinite.is_empty() {
+            return None;
+        }
+        let (_, pdf, index) = self.distribution.sample_continuous(u);
+        (pdf > 0.0).then_some((index, pdf / self.infinite.len() as Float0))
+    }
+
+    pub fn is_infinite(&self, light: usize) -> bool {
+        self.infinite[light]
+    }
+
+    // Samples a ray leaving `light_index`, or nothing if it carries no
+    // light.
+    pub fn emit(
+        &self,
+        scene: &Scene,
+        light_index: usize,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<Emission> {
+        let light = &scene.lights[light_index];
+        let (u_point, u_direction) = (sampler.get_2d(), sampler.get_2d());
+
+        let emission = if self.infinite[light_index] {
+            let (center, radius) = scene.bounding_sphere();
+            let sample = light.sample(center, sampler, wavelengths);
+            if sample.pdf <= 0.0 {
+                return None;
+            }
+            let (tangent, bitangent) = generate_coordinate_system(&sample.direction);
+            let r = radius * u_point.x().sqrt();
+            let phi = 2.0 * PI * u_point.y();
+            let origin = center
+                + sample.direction.scale(radius)
+                + tangent.scale(r * phi.cos())
+                + bitangent.scale(r * phi.sin());
+            Emission {
+                ray: Ray::new(origin, -sample.direction),
+                normal: None,
+                radiance: sample.radiance,
+                pdf_position: 1.0 / (PI * radius * radius),
+                pdf_direction: if sample.delta { 1.0 } else { sample.pdf },
+                infinite: Some(sample.direction),
+            }
+        } else {
+            let sample = light.sample_emission(u_point, u_direction, wavelengths)?;
+            Emission {
+                ray: sample.ray,
+                normal: sample.normal,
+                radiance: sample.radiance,
+                pdf_position: sample.pdf_position,
+                pdf_direction: sample.pdf_direction,
+                infinite: None,
+            }
+        };
+        let carries_light = emission.pdf_position > 0.0
+            && emission.pdf_direction > 0.0
+            && emission.radiance.length_squared() > 0.0;
+        carries_light.then_some(emission)
+    }
+}
+
+// A small scene every integrator is checked against the path tracer on.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use crate::filter::Filter;
+
+    const POINT_AND_AREA: &str = r#"
+        [[lights]]
+        type = "point"
+        position = [0.5, 0.5, 0.5]
+        color = [1.0, 1.0, 1.0]
+        [[lights]]
+        type = "area"
+        point1 = [-0.8, 0.6, -0.5]
+        point2 = [-0.4, 0.6, -0.5]
+        point3 = [-0.4, 0.6, 0.5]
+        point4 = [-0.8, 0.6, 0.5]
+        color = [2.0, 2.0, 2.0]
+    "#;
+
+    // Lights only through a delta direction.
+    const DIRECTIONAL: &str = r#"
+        [[lights]]
+        type = "directional"
+        direction = [-0.3, -1.0, -0.2]
+        angle = 0.0
+        color = [1.0, 1.0, 1.0]
+    "#;
+
+    // The cube only lit by light the floor reflects.
+    const LINKED: &str = r#"
+        [[lights]]
+        type = "point"
+        position = [0.5, 0.5, 0.5]
+        color = [1.0, 1.0, 1.0]
+        exclude = ["cube"]
+        [[lights]]
+        type = "area"
+        point1 = [-0.8, 0.6, -0.5]
+        point2 = [-0.4, 0.6, -0.5]
+        point3 = [-0.4, 0.6, 0.5]
+        point4 = [-0.8, 0.6, 0.5]
+        color = [2.0, 2.0, 2.0]
+        include = ["floor"]
+    "#;
+
+    // A floor with a cube on it, and whatever `fragment` adds.
+    fn scene(fragment: &str) -> Scene {
+        Scene::try_from_toml(
+            &toml::from_str(&format!(
+                r#"
+                [[objects]]
+                type = "quad"
+                name = "floor"
+                point1 = [-1.0, -1.0, -1.0]
+                point2 = [1.0, -1.0, -1.0]
+                point3 = [1.0, -1.0, 1.0]
+                point4 = [-1.0, -1.0, 1.0]
+                infinite = false
+                material = "white"
+                [[objects]]
+                type = "cube"
+                name = "cube"
+                min = [-0.3, -1.0, -0.3]
+                max = [0.3, -0.4, 0.3]
+                material = "red"
+                {fragment}
+                [camera]
+                position = [0.0, 0.5, 3.0]
+                rotation = [0.0, 0.0, 0.0]
+                [skybox]
+                color = [0.0, 0.0, 0.0]
+                "#
+            ))
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    pub fn settings(samples: usize) -> RenderSettings {
+        RenderSettings {
+            width: 24,
+            height: 18,
+            samples,
+            seed: 5,
+            sampler: Default::default(),
+            spectral: false,
+            debug: false,
+            max_depth: 10,
+            photons: None,
+            photon_radius: None,
+            metropolis: Default::default(),
+        }
+    }
+
+    // Mean of the beauty channel.
+    fn render(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings) -> Float0 {
+        let film = Film::new(
+            settings.width,
+            settings.height,
+            Filter::default(),
+            FilterMode::default(),
+            &[],
+        )
+        .with_splat_scale(1.0 / settings.samples as Float0);
+        integrator.render(scene, &film, settings);
+        let beauty = film.beauty();
+        beauty
+            .iter()
+            .map(|pixel| pixel.0.iter().sum::<Float0>())
+            .sum::<Float0>()
+            / (3 * beauty.len()) as Float0
+    }
+
+    // Within 5% of the path tracer at 64 samples per pixel, with point and
+    // area lights, with a directional one and with light links.
+    pub fn assert_agrees_with_the_path_tracer(
+        integrator: &dyn Integrator,
+        integrator_settings: &RenderSettings,
+    ) {
+        for lights in [POINT_AND_AREA, DIRECTIONAL, LINKED] {
+            let scene = scene(lights);
+            let path = render(&scene, &PathIntegrator, &settings(64));
+            let estimate = render(&scene, integrator, integrator_settings);
+            assert!(path > 0.0);
+            assert!(
+                (estimate - path).abs() < 0.05 * path,
+                "{estimate} vs {path}"
+            );
+        }
+    }
+
+    // Accepts the plain scene and names each feature it refuses.
+    pub fn assert_refuses_path_traced_features(integrator: &dyn Integrator) {
+        let light = r#"
+            [[lights]]
+            type = "point"
+            position = [0.5, 0.5, 0.5]
+            color = [1.0, 1.0, 1.0]
+        "#;
+        assert_eq!(integrator.supports(&scene(light)), Ok(()));
+        let sphere = |fields: &str| {
+            format!(
+                r#"
+                [[objects]]
+                type = "sphere"
+                position = [0.0, 0.0, 0.0]
+                radius = 0.1
+                {fields}
+                "#
+            )
+        };
+        for (fragment, feature) in [
+            (
+                sphere(r#"medium = { sigma_a = [0.1, 0.1, 0.1] }"#),
+                "participating media",
+            ),
+            (
+                sphere(
+                    r#"material = { type = "subsurface", color = [0.8, 0.5, 0.4], mean_free_path = [0.3, 0.1, 0.05] }"#,
+                ),
+                "subsurface materials",
+            ),
+            (sphere(r#"material = "shadow_catcher""#), "shadow catchers"),
+            (sphere("cast_shadows = false"), "shadow flags"),
+        ] {
+            let error = integrator
+                .supports(&scene(&format!("{light}{fragment}")))
+                .unwrap_err();
+            assert!(error.contains(feature), "{error}");
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{film::Film, scene::Scene};
+
+use super::{Integrator, RenderSettings, trace_camera_samples};
+
+// Paths traced from the camera, with next-event estimation at every vertex;
+// see `Scene::trace_ray`.
+#[derive(Debug, Default)]
+pub struct PathIntegrator;
+
+impl Integrator for PathIntegrator {
+    fn render(&self, scene: &Scene, film: &Film, settings: &RenderSettings) {
+        trace_camera_samples(scene, film, settings, |sample, sampler, path| {
+            scene.trace_ray(
+                &sample.ray,
+                settings.max_depth,
+                sampler,
+                &sample.sample_type,
+                sample.wavelengths,
+                path,
+            );
+        });
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::{
+    film::{Film, PathSample},
+    material::{
+        Material, SamplingFunctions,
+        dielectric::{self, Ior},
+    },
+    object::HitRecord,
+    pathtracer::seeded_rng,
+    ray::Ray,
+    sampler::{Sampler, hash, independent::IndependentSampler},
+    scene::{Float0, PI, Scene, ShadingPoint},
+    utils::vector::{Float2, Float3},
+};
+
+use super::{
+    EmissionSampler, Integrator, RenderSettings, camera_sample, light_count,
+    without_path_traced_features,
+};
+
+// Stochastic progressive photon mapping (Hachisuka and Jensen), as in
+// pbrt-v3. Every iteration follows one sample per pixel through specular
+// surfaces to its first diffuse one and samples direct light there, then
+// shoots photons from lights chosen by power and gathers those that have
+// bounced at least once around each pixel's point. A pixel's gather radius
+// shrinks as its photons accumulate, so the estimate converges, and
+// caustics that paths from the camera rarely find come out of the photons.
+//
+// Photons are traced in RGB and only reach the beauty, and a sky that isn't
+// a light only lights surfaces directly. Scenes with media, subsurface
+// materials, shadow catchers or shadow flags are refused, as by the
+// bidirectional integrator.
+#[derive(Debug, Default)]
+pub struct PhotonMappingIntegrator;
+
+// Share of each iteration's photons a pixel keeps as its radius shrinks.
+const ALPHA: Float0 = 2.0 / 3.0;
+const PHOTONS_PER_TASK: usize = 4096;
+
+impl Integrator for PhotonMappingIntegrator {
+    fn render(&self, scene: &Scene, film: &Film, settings: &RenderSettings) {
+        let settings = RenderSettings {
+            spectral: false,
+            ..settings.clone()
+        };
+        let pixel_count = settings.width * settings.height;
+        let photon_count = settings.photons.unwrap_or(pixel_count).max(1);
+        // A scene without extent, such as a lone point light, gathers as
+        // if it were the unit sphere.
+        let radius = settings.photon_radius.unwrap_or_else(|| {
+            let scene_radius = scene.bounding_sphere().1;
+            if scene_radius > 0.0 && scene_radius.is_finite() {
+                scene_radius / 100.0
+            } else {
+                0.01
+            }
+        });
+        let lights = EmissionSampler::new(scene);
+        let light_count = light_count(scene, film);
+        let mut estimates = vec![
+            PixelEstimate {
+                radius,
+                photons: 0.0,
+                flux: Float3::new([0.0, 0.0, 0.0]),
+            };
+            pixel_count
+        ];
+
+        for iteration in 0..settings.samples {
+            let visible_points: Vec<Option<VisiblePoint>> = (0..pixel_count)
+                .into_par_iter()
+                .map(|index| {
+                    let mut sampler = settings.sampler.build(
+                        settings.samples,
+                        settings.width,
+                        settings.height,
+                        settings.seed,
+                    );
+                    let mut path = PathSample::new(light_count);
+                    let x = index % settings.width;
+                    let y = index / settings.width;
+
+                    sampler.start_pixel_sample(x, y, iteration);
+                    let sample = camera_sample(scene, film, &settings, sampler.as_mut(), x, y);
+                    let visible_point = visible_point(
+                        scene,
+                        sample.ray,
+                        settings.max_depth,
+                        sampler.as_mut(),
+                        &mut path,
+                    );
+                    film.add_sample(x, y, sample.position, sample.weight, iteration, &path);
+                    visible_point
+                })
+                .collect();
+
+            let photons = trace_photons(scene, &lights, &settings, iteration, photon_count);
+            let max_radius = estimates
+                .iter()
+                .map(|estimate| estimate.radius)
+                .fold(0.0, Float0::max);
+            let grid = PhotonGrid::new(&photons, max_radius);
+            estimates
+                .par_iter_mut()
+                .zip(&visible_points)
+                .for_each(|(estimate, visible_point)| {
+                    if let Some(visible_point) = visible_point {
+                        estimate.gather(visible_point, &grid);
+                    }
+                });
+        }
+
+        // The film divides splats by the number of iterations.
+        for (index, estimate) in estimates.iter().enumerate() {
+            let area = PI * estimate.radius * estimate.radius;
+            let center = Float2::new([
+                (index % settings.width) as Float0 + 0.5,
+                (index / settings.width) as Float0 + 0.5,
+            ]);
+            film.add_splat(
+                center,
+                estimate.flux.scale(1.0 / (photon_count as Float0 * area)),
+            );
+        }
+    }
+
+    fn supports(&self, scene: &Scene) -> Result<(), String> {
+        without_path_traced_features(scene, "sppm")
+    }
+}
+
+// Where a pixel's sample of this iteration met its first diffuse surface,
+// with the throughput and reflectance there.
+#[derive(Debug, Clone, Copy)]
+struct VisiblePoint {
+    point: Float3,
+    normal: Float3,
+    beta: Float3,
+    albedo: Float3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Photon {
+    point: Float3,
+    direction: Float3,
+    power: Float3,
+}
+
+// What a pixel has gathered over all iterations so far: its radius, how
+// many photons it counts, and their flux weighted by its visible points.
+#[derive(Debug, Clone, Copy)]
+struct PixelEstimate {
+    radius: Float0,
+    photons: Float0,
+    flux: Float3,
+}
+
+impl PixelEstimate {
+    fn gather(&mut self, visible_point: &VisiblePoint, grid: &PhotonGrid) {
+        let brdf = visible_point.albedo.scale(1.0 / PI);
+        let mut flux = Float3::new([0.0, 0.0, 0.0]);
+        let mut count = 0.0;
+        grid.for_each_near(visible_point.point, self.radius, |photon| {
+            if photon.direction.dot(&visible_point.normal) < 0.0 {
+                flux += photon.power * brdf;
+                count += 1.0;
+            }
+        });
+        if count == 0.0 {
+            return;
+        }
+        let photons = self.photons + ALPHA * count;
+        let radius = self.radius * (photons / (self.photons + count)).sqrt();
+        let shrink = (radius / self.radius).powi(2);
+        self.flux = (self.flux + visible_point.beta * flux).scale(shrink);
+        self.photons = photons;
+        self.radius = radius;
+    }
+}
+
+// Photons hashed by the cube of side `cell_size` they fall in.
+struct PhotonGrid<'a> {
+    photons: &'a [Photon],
+    cell_size: Float0,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl<'a> PhotonGrid<'a> {
+    fn new(photons: &'a [Photon], cell_size: Float0) -> Self {
+        assert!(
+            cell_size > 0.0 && cell_size.is_finite(),
+            "photon grid cells must have a positive size, not {cell_size}"
+        );
+        let mut grid = Self {
+            photons,
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for (index, photon) in photons.iter().enumerate() {
+            let cell = grid.cell(photon.point);
+            grid.cells.entry(cell).or_default().push(index);
+        }
+        grid
+    }
+
+    fn cell(&self, point: Float3) -> [i64; 3] {
+        point
+            .0
+            .map(|coordinate| (coordinate / self.cell_size).floor() as i64)
+    }
+
+    // Calls `f` with every photon within `radius` of `point`, which must be
+    // no larger than the cells.
+    fn for_each_near(&self, point: Float3, radius: Float0, mut f: impl FnMut(&Photon)) {
+        let offset = Float3::new([radius, radius, radius]);
+        let (low, high) = (self.cell(point - offset), self.cell(point + offset));
+        for x in low[0]..=high[0] {
+            for y in low[1]..=high[1] {
+                for z in low[2]..=high[2] {
+                    let Some(indices) = self.cells.get(&[x, y, z]) else {
+                        continue;
+                    };
+                    for photon in indices.iter().map(|&index| &self.photons[index]) {
+                        if (photon.point - point).length_squared() < radius * radius {
+                            f(photon);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Follows a camera ray through specular surfaces to its first diffuse one,
+// recording in `path` what the pixel sees and the light reaching that
+// surface directly, which photons leave out.
+fn visible_point(
+    scene: &Scene,
+    ray: Ray,
+    max_depth: u32,
+    sampler: &mut dyn Sampler,
+    path: &mut PathSample,
+) -> Option<VisiblePoint> {
+    path.reset();
+    path.wavelengths = None;
+    let mut ray = ray;
+    let mut beta = Float3::new([1.0, 1.0, 1.0]);
+    for bounce in 0..max_depth {
+        let hit = if bounce == 0 {
+            scene.hit_from_camera(&ray)
+        } else {
+            scene.hit_object(&ray, 0.001)
+        };
+        let Some((object_index, record)) = hit else {
+            if bounce == 0 {
+                path.alpha = 0.0;
+                if !scene.background_visible() {
+                    return None;
+                }
+            }
+            let sky = beta * scene.skybox.radiance(ray.direction);
+            path.albedo = sky;
+            path.color += sky;
+            path.direct += sky;
+            return None;
+        };
+        if bounce == 0 {
+            path.position = record.point;
+            path.depth = record.t * ray.direction.length();
+            path.object_id = Some(object_index);
+            path.material_id = Some(scene.material_id(object_index));
+            if scene.visibility(object_index).holdout {
+                path.alpha = 0.0;
+                return None;
+            }
+        }
+
+        let material = record.material;
+        let direction = ray.direction.normalize();
+        let next = if let Some(ior) = &material.ior {
+            if material.is_interface() {
+                ray = Ray::new(record.point + direction.scale(0.001), direction);
+                continue;
+            }
+            let (next, refracted) = dielectric_scatter(ior, &record, direction, sampler);
+            if refracted {
+                beta *= material.color(&record.uv);
+            }
+            next
+        } else if material.subsurface.is_none() && material.reflectivity == 1.0 {
+            Material::reflect(&direction, &record.normal)
+        } else {
+            let color = material.color(&record.uv);
+            let normal = record.normal;
+            path.albedo = beta * color;
+            path.normal = normal;
+
+            let brdf = color.scale(1.0 / PI);
+            let mut direct = scene
+                .direct_lighting(
+                    ShadingPoint {
+                        point: record.point,
+                        normal: Some(normal),
+                        object: Some(object_index),
+                    },
+                    None,
+                    beta,
+                    sampler,
+                    path,
+                    |to_light| {
+                        let cos_theta = normal.dot(&to_light).max(0.0);
+                        (
+                            brdf.scale(cos_theta),
+                            Material::sample_pdf(
+                                &normal,
+                                &to_light,
+                                &SamplingFunctions::CosineWeightedSample1,
+                            ),
+                        )
+                    },
+                )
+                .radiance;
+            // The sky seen by a scattered ray, which photons don't bring
+            // either.
+            let (scattered, pdf) =
+                material.scatter(&record, sampler, &SamplingFunctions::CosineWeightedSample1);
+            let cos_theta = scattered.direction.dot(&normal);
+            if pdf > 0.0 && cos_theta > 0.0 && escapes(scene, scattered) {
+                direct += beta
+                    * brdf.scale(cos_theta / pdf)
+                    * scene.escaped_radiance(&scattered, Some(pdf));
+            }
+            path.color += direct;
+            path.direct += direct;
+            return Some(VisiblePoint {
+                point: record.point,
+                normal,
+                beta,
+                albedo: color,
+            });
+        };
+        ray = Ray::new(record.point + next.scale(0.001), next);
+    }
+    None
+}
+
+// Nothing but index-matched boundaries along the ray.
+fn escapes(scene: &Scene, mut ray: Ray) -> bool {
+    loop {
+        match scene.hit_object(&ray, 0.001) {
+            None => return true,
+            Some((_, record)) if record.material.is_interface() => {
+                ray = Ray::new(record.point, ray.direction);
+            }
+            Some(_) => return false,
+        }
+    }
+}
+
+// Reflects or refracts at a dielectric in proportion to its Fresnel
+// reflectance, returning the new direction and whether it was refracted.
+fn dielectric_scatter(
+    ior: &Ior,
+    record: &HitRecord,
+    direction: Float3,
+    sampler: &mut dyn Sampler,
+) -> (Float3, bool) {
+    let eta = if record.front_face {
+        ior.at(Ior::REFERENCE_WAVELENGTH).recip()
+    } else {
+        ior.at(Ior::REFERENCE_WAVELENGTH)
+    };
+    let reflectance = dielectric::fresnel(-direction.dot(&record.normal), eta);
+    match dielectric::refract(&direction, &record.normal, eta)
+        .filter(|_| sampler.get_1d() >= reflectance)
+    {
+        Some(refracted) => (refracted, true),
+        None => (Material::reflect(&direction, &record.normal), false),
+    }
+}
+
+// Shoots `count` photons for an iteration, in parallel, and keeps those
+// landing on diffuse surfaces after at least one bounce.
+fn trace_photons(
+    scene: &Scene,
+    lights: &EmissionSampler,
+    settings: &RenderSettings,
+    iteration: usize,
+    count: usize,
+) -> Vec<Photon> {
+    (0..count.div_ceil(PHOTONS_PER_TASK))
+        .into_par_iter()
+        .flat_map_iter(|task| {
+            let mut sampler = IndependentSampler::from_rng(seeded_rng(hash(&[
+                settings.seed,
+                iteration as u64,
+                task as u64,
+                count as u64,
+            ])));
+            let mut photons = Vec::new();
+            let task_count = PHOTONS_PER_TASK.min(count - task * PHOTONS_PER_TASK);
+            for _ in 0..task_count {
+                trace_photon(
+                    scene,
+                    lights,
+                    settings.max_depth,
+                    &mut sampler,
+                    &mut photons,
+                );
+            }
+            photons
+        })
+        .collect()
+}
+
+fn trace_photon(
+    scene: &Scene,
+    lights: &EmissionSampler,
+    max_depth: u32,
+    sampler: &mut dyn Sampler,
+    photons: &mut Vec<Photon>,
+) {
+    let Some((light_index, pmf)) = lights.choose(sampler.get_1d()) else {
+        return;
+    };
+    let Some(emission) = lights.emit(scene, light_index, sampler, None) else {
+        return;
+    };
+    let mut beta = emission.beta(pmf);
+    let mut ray = emission.ray;
+    for depth in 0..max_depth {
+        let Some((object_index, record)) = scene.hit_object(&ray, 0.001) else {
+            return;
+        };
+        if depth == 0 && !scene.light_links(light_index).illuminates(object_index) {
+            return;
+        }
+
+        let material = record.material;
+        let direction = ray.direction.normalize();
+        let next = if let Some(ior) = &material.ior {
+            if material.is_interface() {
+                ray = Ray::new(record.point + direction.scale(0.001), direction);
+                continue;
+            }
+            let (next, refracted) = dielectric_scatter(ior, &record, direction, sampler);
+            if refracted {
+                beta *= material.color(&record.uv);
+            }
+            next
+        } else if material.subsurface.is_none() && material.reflectivity == 1.0 {
+            Material::reflect(&direction, &record.normal)
+        } else {
+            if depth > 0 {
+                photons.push(Photon {
+                    point: record.point,
+                    direction,
+                    power: beta,
+                });
+            }
+            let albedo = material.color(&record.uv);
+            let (scattered, pdf) =
+                material.scatter(&record, sampler, &SamplingFunctions::CosineWeightedSample1);
+            let cos_theta = scattered.direction.dot(&record.normal);
+            // Russian roulette on the reflectance keeps photon powers even.
+            let survival = albedo
+                .0
+                .iter()
+                .fold(0.0, |max: Float0, &value| max.max(value))
+                .min(1.0);
+            if pdf <= 0.0 || cos_theta <= 0.0 || sampler.get_1d() >= survival {
+                return;
+            }
+            beta *= albedo.scale(cos_theta / (PI * pdf * survival));
+            ray = scattered;
+            continue;
+        };
+        ray = Ray::new(record.point + next.scale(0.001), next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::testing::{
+        assert_agrees_with_the_path_tracer, assert_refuses_path_traced_features, settings,
+    };
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        assert_agrees_with_the_path_tracer(
+            &PhotonMappingIntegrator,
+            &RenderSettings {
+                photons: Some(20_000),
+                photon_radius: Some(0.05),
+                ..settings(16)
+            },
+        );
+    }
+
+    #[test]
+    fn refuses_what_only_the_path_tracer_renders() {
+        assert_refuses_path_traced_features(&PhotonMappingIntegrator);
+    }
+}
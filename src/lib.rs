@@ -1,9 +1,18 @@
 pub mod camera;
+pub mod color;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod integrator;
 pub mod light;
 pub mod material;
+pub mod medium;
 pub mod object;
+pub mod output;
 pub mod pathtracer;
 pub mod ray;
+pub mod sampler;
 pub mod scene;
 pub mod skybox;
+pub mod spectrum;
 pub mod utils;
@@ -1,23 +1,42 @@
-use rand::RngExt;
-
 use crate::{
-    scene::{Float0, RNGType},
-    utils::vector::Float3,
+    object::aabb::Aabb,
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::{SampledWavelengths, Spectrum},
+    utils::vector::{Float2, Float3},
 };
 
-use super::{Light, LightSample};
+use super::{
+    EmissionSample, Light, LightSample, bvh::LightBounds, flat_emission, flat_emission_pdf,
+};
 
+// A parallelogram spanned from `a` by the edges to `b` and `d`, emitting
+// from the side the edges' cross product faces or from both sides when
+// two-sided.
 #[derive(Debug, Clone)]
 pub struct Arealight {
     a: Float3,
     b: Float3,
     d: Float3,
-    color: Float3,
+    emission: Spectrum,
+    two_sided: bool,
 }
 
 impl Arealight {
-    pub fn new(a: Float3, b: Float3, d: Float3, color: Float3) -> Self {
-        Self { a, b, d, color }
+    pub fn new(a: Float3, b: Float3, d: Float3, emission: Spectrum) -> Self {
+        Self {
+            a,
+            b,
+            d,
+            emission,
+            two_sided: false,
+        }
+    }
+
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
     }
 
     fn edge_u(&self) -> Float3 {
@@ -39,21 +58,29 @@ impl Arealight {
 
 impl Light for Arealight {
     fn illuminate(&self) -> Float3 {
-        self.color
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        self.emission.rgb().scale(sides * PI * self.area())
     }
 
     fn position(&self) -> Float3 {
         self.a + (self.edge_u() + self.edge_v()).scale(0.5)
     }
 
-    fn sample(&self, point: Float3, rand_state: &mut RNGType) -> LightSample {
-        let u = rand_state.random_range(0.0..1.0);
-        let v = rand_state.random_range(0.0..1.0);
-        let sample_point = self.a + self.edge_u().scale(u) + self.edge_v().scale(v);
+    fn sample(
+        &self,
+        point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let uv = sampler.get_2d();
+        let sample_point = self.a + self.edge_u().scale(uv.x()) + self.edge_v().scale(uv.y());
         let to_light = sample_point - point;
         let distance = to_light.length();
         let direction = to_light.normalize();
-        let cos_light = self.normal().dot(&-direction).max(0.0);
+        let mut cos_light = self.normal().dot(&-direction);
+        if self.two_sided {
+            cos_light = cos_light.abs();
+        }
         let area = self.area();
 
         if area <= 0.0 || cos_light <= 0.0 {
@@ -69,17 +96,54 @@ impl Light for Arealight {
         LightSample {
             direction,
             distance,
-            radiance: self.color,
+            radiance: self.emission.evaluate(wavelengths),
             pdf: distance * distance / (cos_light * area),
             delta: false,
         }
     }
 
-    fn intensity(&self) -> Float0 {
-        self.color.length() * self.area()
+    fn color(&self) -> Float3 {
+        self.emission.rgb()
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let c = self.b + self.edge_v();
+        Some(LightBounds {
+            two_sided: self.two_sided,
+            ..LightBounds::facing(
+                Aabb::from_points(&[self.a, self.b, c, self.d])?,
+                self.intensity(),
+                self.normal(),
+            )
+        })
     }
 
-    fn color(&self) -> Float3 {
-        self.color
+    fn sample_emission(
+        &self,
+        u_point: Float2,
+        u_direction: Float2,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<EmissionSample> {
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+        let origin = self.a + self.edge_u().scale(u_point.x()) + self.edge_v().scale(u_point.y());
+        let (direction, normal, pdf_direction) =
+            flat_emission(self.normal(), self.two_sided, u_direction);
+        Some(EmissionSample {
+            ray: Ray::new(origin, direction),
+            normal: Some(normal),
+            radiance: self.emission.evaluate(wavelengths),
+            pdf_position: 1.0 / area,
+            pdf_direction,
+        })
+    }
+
+    fn emission_pdf(&self, _point: Float3, direction: Float3) -> (Float0, Float0) {
+        (
+            1.0 / self.area(),
+            flat_emission_pdf(self.normal(), self.two_sided, direction),
+        )
     }
 }
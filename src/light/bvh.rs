@@ -0,0 +1,305 @@
+use crate::{
+    object::aabb::Aabb,
+    scene::{Float0, PI},
+    utils::vector::Float3,
+};
+
+// Where a light, or a cluster of lights, is and which way it shines, after
+// Conty Estevez and Kulla's "Importance Sampling of Many Lights with Adaptive
+// Tree Splitting" as formulated in pbrt-v4. Emission leaves within
+// `cos_theta_o` of `axis` (the normals' spread) and then up to
+// `cos_theta_e` further out (the emission's spread around a normal).
+#[derive(Debug, Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    pub power: Float0,
+    pub axis: Float3,
+    pub cos_theta_o: Float0,
+    pub cos_theta_e: Float0,
+    pub two_sided: bool,
+}
+
+impl LightBounds {
+    // A point light or any other that shines in every direction.
+    pub fn omnidirectional(bounds: Aabb, power: Float0) -> Self {
+        Self {
+            bounds,
+            power,
+            axis: Float3::new([0.0, 0.0, 1.0]),
+            cos_theta_o: -1.0,
+            cos_theta_e: 0.0,
+            two_sided: false,
+        }
+    }
+
+    // A flat emitter facing `normal` with a cosine falloff.
+    pub fn facing(bounds: Aabb, power: Float0, normal: Float3) -> Self {
+        Self {
+            bounds,
+            power,
+            axis: normal.normalize(),
+            cos_theta_o: 1.0,
+            cos_theta_e: 0.0,
+            two_sided: false,
+        }
+    }
+
+    fn union(a: &Self, b: &Self) -> Self {
+        if a.power == 0.0 {
+            return *b;
+        }
+        if b.power == 0.0 {
+            return *a;
+        }
+        let (axis, cos_theta_o) = cone_union(a.axis, a.cos_theta_o, b.axis, b.cos_theta_o);
+        Self {
+            bounds: Aabb::surrounding(a.bounds, b.bounds),
+            power: a.power + b.power,
+            axis,
+            cos_theta_o,
+            cos_theta_e: a.cos_theta_e.min(b.cos_theta_e),
+            two_sided: a.two_sided || b.two_sided,
+        }
+    }
+
+    // How much light from these bounds could reach `point`, a surface with
+    // `normal` or a point in a medium when None.
+    pub fn importance(&self, point: Float3, normal: Option<Float3>) -> Float0 {
+        let center = (self.bounds.min + self.bounds.max).scale(0.5);
+        let radius = (self.bounds.max - self.bounds.min).length() * 0.5;
+        let offset = point - center;
+        let distance_squared = offset.length_squared().max(radius);
+
+        // Directions from the bounds can be off by the angle they subtend.
+        let (cos_b, sin_b) = if offset.length_squared() <= radius * radius {
+            (-1.0, 0.0)
+        } else {
+            let sin_squared = radius * radius / offset.length_squared();
+            ((1.0 - sin_squared).max(0.0).sqrt(), sin_squared.sqrt())
+        };
+
+        let mut cos_w = offset.normalize().dot(&self.axis);
+        if self.two_sided {
+            cos_w = cos_w.abs();
+        }
+        let sin_w = (1.0 - cos_w * cos_w).max(0.0).sqrt();
+        let sin_o = (1.0 - self.cos_theta_o * self.cos_theta_o).max(0.0).sqrt();
+        // cos(max(0, theta_w - theta_o - theta_b))
+        let (cos_wo, sin_wo) = subtract_angle(cos_w, sin_w, self.cos_theta_o, sin_o);
+        let (cos_x, _) = subtract_angle(cos_wo, sin_wo, cos_b, sin_b);
+        if cos_x <= self.cos_theta_e {
+            return 0.0;
+        }
+        let mut importance = self.power * cos_x / distance_squared;
+
+        if let Some(normal) = normal {
+            let cos_i = (-offset).normalize().dot(&normal).abs();
+            let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+            let (cos_ib, _) = subtract_angle(cos_i, sin_i, cos_b, sin_b);
+            importance *= cos_ib;
+        }
+        importance.max(0.0)
+    }
+}
+
+// cos and sin of max(0, a - b) from those of a and b.
+fn subtract_angle(cos_a: Float0, sin_a: Float0, cos_b: Float0, sin_b: Float0) -> (Float0, Float0) {
+    if cos_a >= cos_b {
+        return (1.0, 0.0);
+    }
+    (
+        cos_a * cos_b + sin_a * sin_b,
+        (sin_a * cos_b - cos_a * sin_b).max(0.0),
+    )
+}
+
+// Smallest cone around both cones, each given by its axis and the cosine of
+// its half-angle.
+fn cone_union(a: Float3, cos_a: Float0, b: Float3, cos_b: Float0) -> (Float3, Float0) {
+    let theta_a = cos_a.clamp(-1.0, 1.0).acos();
+    let theta_b = cos_b.clamp(-1.0, 1.0).acos();
+    let theta_d = a.dot(&b).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return (a, cos_a);
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return (b, cos_b);
+    }
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return (a, -1.0);
+    }
+    let rotation_axis = a.cross(&b);
+    if rotation_axis.length_squared() < 1e-12 {
+        return (a, -1.0);
+    }
+    // Turn a towards b by theta_o - theta_a.
+    let k = rotation_axis.normalize();
+    let (sin_r, cos_r) = (theta_o - theta_a).sin_cos();
+    let axis = a.scale(cos_r) + k.cross(&a).scale(sin_r) + k.scale(k.dot(&a) * (1.0 - cos_r));
+    (axis.normalize(), theta_o.cos())
+}
+
+// A binary tree over the lights that have bounds, built by splitting the
+// longest axis at the median. Leaves are single lights.
+#[derive(Debug)]
+pub struct LightBvh {
+    root: Option<LightBvhNode>,
+}
+
+#[derive(Debug)]
+enum LightBvhNode {
+    Leaf {
+        bounds: LightBounds,
+        light: usize,
+    },
+    Branch {
+        bounds: LightBounds,
+        left: Box<LightBvhNode>,
+        right: Box<LightBvhNode>,
+    },
+}
+
+impl LightBvh {
+    pub fn build(mut lights: Vec<(usize, LightBounds)>) -> Self {
+        lights.retain(|(_, bounds)| bounds.power > 0.0);
+        Self {
+            root: LightBvhNode::build(&mut lights),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    // Walks down the tree choosing children by importance. Returns the
+    // light and the probability of having chosen it.
+    pub fn sample(
+        &self,
+        point: Float3,
+        normal: Option<Float3>,
+        mut u: Float0,
+    ) -> Option<(usize, Float0)> {
+        let mut node = self.root.as_ref()?;
+        let mut probability = 1.0;
+        loop {
+            match node {
+                LightBvhNode::Leaf { bounds, light } => {
+                    return (bounds.importance(point, normal) > 0.0)
+                        .then_some((*light, probability));
+                }
+                LightBvhNode::Branch { left, right, .. } => {
+                    let left_importance = left.bounds().importance(point, normal);
+                    let right_importance = right.bounds().importance(point, normal);
+                    let total = left_importance + right_importance;
+                    if total <= 0.0 {
+                        return None;
+                    }
+                    let p_left = left_importance / total;
+                    if u < p_left {
+                        u = (u / p_left).min(1.0 - Float0::EPSILON);
+                        probability *= p_left;
+                        node = left;
+                    } else {
+                        u = ((u - p_left) / (1.0 - p_left)).min(1.0 - Float0::EPSILON);
+                        probability *= 1.0 - p_left;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl LightBvhNode {
+    fn build(lights: &mut [(usize, LightBounds)]) -> Option<Self> {
+        match lights {
+            [] => None,
+            [(light, bounds)] => Some(Self::Leaf {
+                bounds: *bounds,
+                light: *light,
+            }),
+            _ => {
+                let bounds = lights
+                    .iter()
+                    .map(|(_, bounds)| *bounds)
+                    .reduce(|a, b| LightBounds::union(&a, &b))?;
+                let centroids = Aabb::from_points(
+                    &lights
+                        .iter()
+                        .map(|(_, bounds)| (bounds.bounds.min + bounds.bounds.max).scale(0.5))
+                        .collect::<Vec<_>>(),
+                )?;
+                let axis = centroids.longest_axis();
+                lights.sort_by(|(_, a), (_, b)| {
+                    a.bounds
+                        .centroid_axis(axis)
+                        .partial_cmp(&b.bounds.centroid_axis(axis))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                let (left, right) = lights.split_at_mut(lights.len() / 2);
+                Some(Self::Branch {
+                    bounds,
+                    left: Box::new(Self::build(left)?),
+                    right: Box::new(Self::build(right)?),
+                })
+            }
+        }
+    }
+
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Self::Leaf { bounds, .. } | Self::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point_light(x: Float0, power: Float0) -> LightBounds {
+        let position = Float3::new([x, 0.0, 0.0]);
+        LightBounds::omnidirectional(Aabb::new(position, position), power)
+    }
+
+    #[test]
+    fn prefers_close_and_facing_lights() {
+        let bvh = LightBvh::build(
+            (0..8)
+                .map(|i| (i, point_light(i as Float0 * 10.0, 1.0)))
+                .collect(),
+        );
+        let point = Float3::new([0.5, 1.0, 0.0]);
+
+        // Probabilities of every light add up and the nearest one dominates.
+        let count = 10_000;
+        let mut chosen = [0.0; 8];
+        for i in 0..count {
+            let u = (i as Float0 + 0.5) / count as Float0;
+            let (light, probability) = bvh.sample(point, None, u).unwrap();
+            chosen[light] += 1.0 / count as Float0;
+            assert!(probability > 0.0 && probability <= 1.0);
+        }
+        assert!(chosen[0] > 0.8, "{chosen:?}");
+        assert!((chosen.iter().sum::<Float0>() - 1.0).abs() < 1e-9);
+
+        // A panel facing away from the point can't light it.
+        let panel = LightBounds::facing(
+            Aabb::new(Float3::new([-1.0, 2.0, -1.0]), Float3::new([1.0, 2.0, 1.0])),
+            1.0,
+            Float3::new([0.0, 1.0, 0.0]),
+        );
+        assert_eq!(panel.importance(Float3::new([0.0, 0.0, 0.0]), None), 0.0);
+        assert!(panel.importance(Float3::new([0.0, 4.0, 0.0]), None) > 0.0);
+
+        let (axis, cos) = cone_union(
+            Float3::new([1.0, 0.0, 0.0]),
+            1.0,
+            Float3::new([0.0, 1.0, 0.0]),
+            1.0,
+        );
+        assert!((cos - (PI / 4.0).cos()).abs() < 1e-9);
+        assert!((axis - Float3::new([1.0, 1.0, 0.0]).normalize()).length() < 1e-9);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{
+    material::generate_coordinate_system,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::{SampledWavelengths, Spectrum},
+    utils::vector::Float3,
+};
+
+use super::{Light, LightSample};
+
+// Parallel light from infinitely far away, shining along `direction`. The
+// emission is the irradiance it delivers to a surface facing it. With an
+// `angle`, the angular diameter of the source, it is a disk in the sky that
+// casts soft shadows instead.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    direction: Float3,
+    cos_radius: Float0,
+    emission: Spectrum,
+    scene_radius: Float0,
+}
+
+impl DirectionalLight {
+    // `angle` in degrees.
+    pub fn new(direction: Float3, angle: Float0, emission: Spectrum) -> Self {
+        Self {
+            direction: direction.normalize(),
+            cos_radius: (angle / 2.0).to_radians().cos(),
+            emission,
+            // Until the scene says how big it is.
+            scene_radius: 1.0,
+        }
+    }
+
+    fn solid_angle(&self) -> Float0 {
+        2.0 * PI * (1.0 - self.cos_radius)
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self) -> Float3 {
+        self.emission
+            .rgb()
+            .scale(PI * self.scene_radius * self.scene_radius)
+    }
+
+    fn position(&self) -> Float3 {
+        -self.direction
+    }
+
+    fn sample(
+        &self,
+        _point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let to_light = -self.direction;
+        let solid_angle = self.solid_angle();
+        if solid_angle <= 0.0 {
+            return LightSample {
+                direction: to_light,
+                distance: Float0::INFINITY,
+                radiance: self.emission.evaluate(wavelengths),
+                pdf: 1.0,
+                delta: true,
+            };
+        }
+
+        let u = sampler.get_2d();
+        let cos_theta = 1.0 - u.x() * (1.0 - self.cos_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y();
+        let (tangent, bitangent) = generate_coordinate_system(&to_light);
+        LightSample {
+            direction: to_light.scale(cos_theta)
+                + tangent.scale(sin_theta * phi.cos())
+                + bitangent.scale(sin_theta * phi.sin()),
+            distance: Float0::INFINITY,
+            radiance: self
+                .emission
+                .evaluate(wavelengths)
+                .scale(solid_angle.recip()),
+            pdf: solid_angle.recip(),
+            delta: false,
+        }
+    }
+
+    fn color(&self) -> Float3 {
+        self.emission.rgb()
+    }
+
+    fn set_scene_radius(&mut self, radius: Float0) {
+        self.scene_radius = radius;
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{
+    material::generate_coordinate_system,
+    object::aabb::Aabb,
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::{SampledWavelengths, Spectrum},
+    utils::vector::{Float2, Float3},
+};
+
+use super::{
+    EmissionSample, Light, LightSample, bvh::LightBounds, flat_emission, flat_emission_pdf,
+};
+
+// A disk emitting `emission` radiance from the side its `normal` faces, or
+// from both sides when two-sided, sampled uniformly by area with the pdf
+// converted to solid angle.
+#[derive(Debug, Clone, Copy)]
+pub struct DiskLight {
+    center: Float3,
+    normal: Float3,
+    radius: Float0,
+    emission: Spectrum,
+    two_sided: bool,
+}
+
+impl DiskLight {
+    pub fn new(center: Float3, normal: Float3, radius: Float0, emission: Spectrum) -> Self {
+        Self {
+            center,
+            normal: normal.normalize(),
+            radius,
+            emission,
+            two_sided: false,
+        }
+    }
+
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    fn area(&self) -> Float0 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Light for DiskLight {
+    fn illuminate(&self) -> Float3 {
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        self.emission.rgb().scale(sides * PI * self.area())
+    }
+
+    fn position(&self) -> Float3 {
+        self.center
+    }
+
+    fn sample(
+        &self,
+        point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let u = sampler.get_2d();
+        let radius = self.radius * u.x().sqrt();
+        let phi = 2.0 * PI * u.y();
+        let (tangent, bitangent) = generate_coordinate_system(&self.normal);
+        let sample_point =
+            self.center + tangent.scale(radius * phi.cos()) + bitangent.scale(radius * phi.sin());
+
+        let to_light = sample_point - point;
+        let distance = to_light.length();
+        let direction = to_light.normalize();
+        let mut cos_light = self.normal.dot(&-direction);
+        if self.two_sided {
+            cos_light = cos_light.abs();
+        }
+        let area = self.area();
+
+        if area <= 0.0 || cos_light <= 0.0 {
+            return LightSample {
+                direction,
+                distance,
+                radiance: Float3::new([0.0, 0.0, 0.0]),
+                pdf: 1.0,
+                delta: false,
+            };
+        }
+
+        LightSample {
+            direction,
+            distance,
+            radiance: self.emission.evaluate(wavelengths),
+            pdf: distance * distance / (cos_light * area),
+            delta: false,
+        }
+    }
+
+    fn color(&self) -> Float3 {
+        self.emission.rgb()
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Per axis, the disk reaches out by the radius times the sine of
+        // the angle between that axis and the normal.
+        let extent = Float3::new(
+            self.normal
+                .0
+                .map(|n| self.radius * (1.0 - n * n).max(0.0).sqrt()),
+        );
+        Some(LightBounds {
+            two_sided: self.two_sided,
+            ..LightBounds::facing(
+                Aabb::new(self.center - extent, self.center + extent),
+                self.intensity(),
+                self.normal,
+            )
+        })
+    }
+
+    fn sample_emission(
+        &self,
+        u_point: Float2,
+        u_direction: Float2,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<EmissionSample> {
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+        let radius = self.radius * u_point.x().sqrt();
+        let phi = 2.0 * PI * u_point.y();
+        let (tangent, bitangent) = generate_coordinate_system(&self.normal);
+        let origin =
+            self.center + tangent.scale(radius * phi.cos()) + bitangent.scale(radius * phi.sin());
+        let (direction, normal, pdf_direction) =
+            flat_emission(self.normal, self.two_sided, u_direction);
+        Some(EmissionSample {
+            ray: Ray::new(origin, direction),
+            normal: Some(normal),
+            radiance: self.emission.evaluate(wavelengths),
+            pdf_position: 1.0 / area,
+            pdf_direction,
+        })
+    }
+
+    fn emission_pdf(&self, _point: Float3, direction: Float3) -> (Float0, Float0) {
+        (
+            1.0 / self.area(),
+            flat_emission_pdf(self.normal, self.two_sided, direction),
+        )
+    }
+}
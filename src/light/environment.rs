@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use crate::{
+    sampler::Sampler,
+    scene::{Float0, PI},
+    skybox::environment::EnvironmentMap,
+    spectrum::{self, SampledWavelengths},
+    utils::vector::Float3,
+};
+
+use super::{Light, LightSample};
+
+// Importance-samples the skybox's environment map for next-event estimation.
+#[derive(Debug, Clone)]
+pub struct EnvironmentLight {
+    map: Arc<EnvironmentMap>,
+    scene_radius: Float0,
+}
+
+impl EnvironmentLight {
+    pub fn new(map: Arc<EnvironmentMap>) -> Self {
+        Self {
+            map,
+            // Until the scene says how big it is.
+            scene_radius: 1.0,
+        }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn illuminate(&self) -> Float3 {
+        // Radiance from the whole sphere onto a disk the size of the scene.
+        self.map
+            .average()
+            .scale(4.0 * PI * PI * self.scene_radius * self.scene_radius)
+    }
+
+    fn position(&self) -> Float3 {
+        Float3::new([0.0, 0.0, 0.0])
+    }
+
+    fn sample(
+        &self,
+        _point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let (direction, radiance, pdf) = self.map.sample(sampler.get_2d());
+        LightSample {
+            direction,
+            distance: Float0::INFINITY,
+            radiance: spectrum::uplift(radiance, wavelengths),
+            pdf,
+            delta: false,
+        }
+    }
+
+    fn color(&self) -> Float3 {
+        self.map.average()
+    }
+
+    fn found_by_scattering(&self) -> bool {
+        true
+    }
+
+    fn set_scene_radius(&mut self, radius: Float0) {
+        self.scene_radius = radius;
+    }
+}
@@ -0,0 +1,318 @@
+use std::{fs, path::Path, sync::Arc};
+
+use crate::{
+    scene::{Float0, PI},
+    utils::{matrix::Float3x3, vector::Float3},
+};
+
+// A luminaire's measured intensity distribution from an IES LM-63 file,
+// normalized so its brightest direction is 1. Type C photometry: vertical
+// angles from 0 straight down (-y) to 180 straight up, horizontal angles
+// counter-clockwise seen from above, from 0 along +x to 90 along -z.
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    vertical: Vec<Float0>,
+    horizontal: Vec<Float0>,
+    // One row of vertical samples per horizontal angle.
+    values: Vec<Float0>,
+    // Largest intensity in the file, in candela.
+    pub peak_candela: Float0,
+    // Mean of the normalized intensity over the sphere.
+    mean: Float0,
+}
+
+impl IesProfile {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        // Older files are often Latin-1 rather than UTF-8, and only the
+        // numbers matter.
+        let text = String::from_utf8_lossy(&text);
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| "missing TILT line".to_string())?
+                .trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                match tilt.trim() {
+                    "NONE" | "INCLUDE" => break,
+                    _ => return Err("external TILT files are not supported".to_string()),
+                }
+            }
+        }
+        let tilt_included = text.contains("TILT=INCLUDE");
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<Float0>()
+                    .map_err(|_| format!("`{token}` is not a number"))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err("the file ends early".to_string()))
+        };
+
+        if tilt_included {
+            // Lamp-to-luminaire geometry, then the tilt angles and their
+            // multipliers, which only matter for tilted lamps.
+            next()?;
+            let count = next()? as usize;
+            for _ in 0..2 * count {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()?;
+        let horizontal_count = next()?;
+        let photometric_type = next()?;
+        if vertical_count < 1.0 || horizontal_count < 1.0 {
+            return Err("the file has no angles".to_string());
+        }
+        if photometric_type != 1.0 {
+            return Err("only type C photometry is supported".to_string());
+        }
+        // Units, width, length, height, ballast factor, a reserved value and
+        // input watts.
+        let _units = next()?;
+        for _ in 0..3 {
+            next()?;
+        }
+        let ballast_factor = next()?;
+        let _reserved = next()?;
+        let _watts = next()?;
+
+        let (vertical_count, horizontal_count) =
+            (vertical_count as usize, horizontal_count as usize);
+        let vertical = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let horizontal = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut values = (0..vertical_count * horizontal_count)
+            .map(|_| next().map(|value| value * multiplier * ballast_factor))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !is_increasing(&vertical) || !is_increasing(&horizontal) {
+            return Err("angles must be increasing".to_string());
+        }
+
+        let peak_candela = values.iter().copied().fold(0.0, Float0::max);
+        if peak_candela <= 0.0 {
+            return Err("the luminaire emits no light".to_string());
+        }
+        for value in values.iter_mut() {
+            *value = value.max(0.0) / peak_candela;
+        }
+        let mut profile = Self {
+            vertical,
+            horizontal,
+            values,
+            peak_candela,
+            mean: 0.0,
+        };
+
+        let steps = 128;
+        let mut total = 0.0;
+        let mut weight = 0.0;
+        for i in 0..steps {
+            let theta = (i as Float0 + 0.5) / steps as Float0 * PI;
+            for j in 0..2 * steps {
+                let phi = (j as Float0 + 0.5) / (2 * steps) as Float0 * 2.0 * PI;
+                let direction = Float3::new([
+                    theta.sin() * phi.cos(),
+                    -theta.cos(),
+                    -theta.sin() * phi.sin(),
+                ]);
+                total += profile.evaluate(direction) * theta.sin();
+                weight += theta.sin();
+            }
+        }
+        profile.mean = total / weight;
+        Ok(profile)
+    }
+
+    // Relative intensity towards `direction` in the luminaire's frame.
+    pub fn evaluate(&self, direction: Float3) -> Float0 {
+        let direction = direction.normalize();
+        let vertical = (-direction.y()).clamp(-1.0, 1.0).acos().to_degrees();
+        let mut horizontal = (-direction.z())
+            .atan2(direction.x())
+            .to_degrees()
+            .rem_euclid(360.0);
+
+        // The last horizontal angle says which symmetry the file assumes.
+        let last = *self.horizontal.last().expect("at least one angle");
+        if self.horizontal.len() > 1 {
+            if last <= 90.0 {
+                horizontal %= 180.0;
+                if horizontal > 90.0 {
+                    horizontal = 180.0 - horizontal;
+                }
+            } else if last <= 180.0 && horizontal > 180.0 {
+                horizontal = 360.0 - horizontal;
+            }
+        }
+
+        let Some((v0, v1, tv)) = bracket(&self.vertical, vertical) else {
+            return 0.0;
+        };
+        let (h0, h1, th) = bracket(&self.horizontal, horizontal).unwrap_or_else(|| {
+            let index = if horizontal < self.horizontal[0] {
+                0
+            } else {
+                self.horizontal.len() - 1
+            };
+            (index, index, 0.0)
+        });
+        let rows = self.vertical.len();
+        let at = |h: usize, v: usize| self.values[h * rows + v];
+        let lower = at(h0, v0) * (1.0 - tv) + at(h0, v1) * tv;
+        let upper = at(h1, v0) * (1.0 - tv) + at(h1, v1) * tv;
+        lower * (1.0 - th) + upper * th
+    }
+
+    pub fn mean(&self) -> Float0 {
+        self.mean
+    }
+}
+
+// A profile placed in the scene. The orientation takes directions in the
+// luminaire's frame to the world.
+#[derive(Debug, Clone)]
+pub struct OrientedProfile {
+    profile: Arc<IesProfile>,
+    to_local: Float3x3,
+}
+
+impl OrientedProfile {
+    pub fn new(profile: Arc<IesProfile>, orientation: Float3x3) -> Self {
+        // The inverse of a rotation is its transpose.
+        Self {
+            profile,
+            to_local: Float3x3::new_from_columns(orientation.0),
+        }
+    }
+
+    // Relative intensity of light leaving along the world `direction`.
+    pub fn evaluate(&self, direction: Float3) -> Float0 {
+        self.profile
+            .evaluate(self.to_local.multiply_by_vector(&direction))
+    }
+
+    pub fn mean(&self) -> Float0 {
+        self.profile.mean()
+    }
+}
+
+fn is_increasing(angles: &[Float0]) -> bool {
+    angles.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+// Indices of the samples around `angle` and the fraction between them, or
+// None outside the measured range.
+fn bracket(angles: &[Float0], angle: Float0) -> Option<(usize, usize, Float0)> {
+    let first = angles[0];
+    let last = *angles.last()?;
+    if angles.len() == 1 {
+        return Some((0, 0, 0.0));
+    }
+    if angle < first - 1e-9 || angle > last + 1e-9 {
+        return None;
+    }
+    let upper = angles
+        .partition_point(|value| *value < angle)
+        .clamp(1, angles.len() - 1);
+    let lower = upper - 1;
+    let fraction = ((angle - angles[lower]) / (angles[upper] - angles[lower])).clamp(0.0, 1.0);
+    Some((lower, upper, fraction))
+}
+
+// Rotation by `degrees` about x, then y, then z.
+pub fn rotation(degrees: Float3) -> Float3x3 {
+    let [x, y, z] = degrees.0.map(Float0::to_radians);
+    let rotation_x = Float3x3::new([
+        [1.0, 0.0, 0.0],
+        [0.0, x.cos(), -x.sin()],
+        [0.0, x.sin(), x.cos()],
+    ]);
+    let rotation_y = Float3x3::new([
+        [y.cos(), 0.0, y.sin()],
+        [0.0, 1.0, 0.0],
+        [-y.sin(), 0.0, y.cos()],
+    ]);
+    let rotation_z = Float3x3::new([
+        [z.cos(), -z.sin(), 0.0],
+        [z.sin(), z.cos(), 0.0],
+        [0.0, 0.0, 1.0],
+    ]);
+    rotation_z * (rotation_y * rotation_x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A downlight measured in one quadrant: a wide beam along the x axis
+    // and a narrow one along z.
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] synthetic
+[MANUFAC] none
+TILT=INCLUDE
+1
+2
+0 90
+1.0 0.5
+1 1000 2.0 3 2 1 2 0.1 0.1 0.0
+1.0 1.0 50
+0 45 90
+0, 90
+100 80 0
+100 20 0
+";
+
+    #[test]
+    fn parses_and_interpolates_type_c_profiles() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.peak_candela, 200.0);
+
+        let towards = |vertical: Float0, horizontal: Float0| {
+            let (v, h) = (vertical.to_radians(), horizontal.to_radians());
+            profile.evaluate(Float3::new([
+                v.sin() * h.cos(),
+                -v.cos(),
+                -v.sin() * h.sin(),
+            ]))
+        };
+        assert!((towards(0.0, 0.0) - 1.0).abs() < 1e-9);
+        assert!((towards(45.0, 0.0) - 0.8).abs() < 1e-9);
+        assert!((towards(45.0, 90.0) - 0.2).abs() < 1e-9);
+        assert!((towards(45.0, 45.0) - 0.5).abs() < 1e-9);
+        // The other quadrants mirror the measured one.
+        assert!((towards(45.0, 180.0) - 0.8).abs() < 1e-9);
+        assert!((towards(45.0, 270.0) - 0.2).abs() < 1e-9);
+        assert!((towards(22.5, 315.0) - towards(22.5, 45.0)).abs() < 1e-9);
+        // Nothing is measured above the horizon.
+        assert_eq!(towards(120.0, 0.0), 0.0);
+        assert!(profile.mean() > 0.05 && profile.mean() < 0.5);
+
+        // Turning the frame moves the beam.
+        let sideways = rotation(Float3::new([0.0, 0.0, 90.0]));
+        let down = Float3::new([0.0, -1.0, 0.0]);
+        assert!(
+            (sideways.multiply_by_vector(&down) - Float3::new([1.0, 0.0, 0.0])).length() < 1e-9
+        );
+
+        assert!(IesProfile::parse(&DOWNLIGHT.replace("3 2 1 2", "3 2 2 2")).is_err());
+        assert!(IesProfile::parse("IESNA:LM-63-2002\nTILT=lamp.tlt\n").is_err());
+    }
+}
@@ -0,0 +1,23 @@
+// Which of the scene's objects a light reaches and which ones don't block
+// it, by index into the scene's objects. Every object by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LightLinks {
+    // When given, only these objects are lit.
+    pub include: Option<Vec<usize>>,
+    pub exclude: Vec<usize>,
+    // Objects the light shines through without casting a shadow.
+    pub shadow_exclude: Vec<usize>,
+}
+
+impl LightLinks {
+    pub fn illuminates(&self, object: usize) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.contains(&object))
+            && !self.exclude.contains(&object)
+    }
+
+    pub fn is_shadowed_by(&self, object: usize) -> bool {
+        !self.shadow_exclude.contains(&object)
+    }
+}
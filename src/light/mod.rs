@@ -1,16 +1,37 @@
 use std::str::FromStr;
 
 use crate::{
-    scene::{Float0, RNGType},
-    utils::vector::Float3,
+    material::generate_coordinate_system,
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::SampledWavelengths,
+    utils::vector::{Float2, Float3},
 };
 
+use bvh::LightBounds;
+
 pub mod arealight;
+pub mod bvh;
+pub mod directionallight;
+pub mod disklight;
+pub mod environment;
+pub mod ies;
+pub mod linking;
 pub mod pointlight;
+pub mod sampling;
+pub mod spherelight;
+pub mod spotlight;
+pub mod sun;
+pub mod units;
 
 pub enum LightType {
     PointLight,
+    SpotLight,
+    DirectionalLight,
     AreaLight,
+    DiskLight,
+    SphereLight,
     ObjectLight,
 }
 
@@ -20,7 +41,11 @@ impl FromStr for LightType {
     fn from_str(s: &str) -> Result<LightType, ()> {
         match s {
             "point" => Ok(Self::PointLight),
+            "spot" => Ok(Self::SpotLight),
+            "directional" | "distant" => Ok(Self::DirectionalLight),
             "area" => Ok(Self::AreaLight),
+            "disk" => Ok(Self::DiskLight),
+            "sphere" => Ok(Self::SphereLight),
             "object" => Ok(Self::ObjectLight),
             _ => Err(()),
         }
@@ -29,10 +54,52 @@ impl FromStr for LightType {
 
 pub trait Light: Sync + std::fmt::Debug {
     fn position(&self) -> Float3;
-    fn sample(&self, point: Float3, rand_state: &mut RNGType) -> LightSample;
+    // The radiance is RGB, or spectral samples at `wavelengths` when given.
+    fn sample(
+        &self,
+        point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample;
+    // Total power the light emits per colour channel: radiance integrated
+    // over its area and the directions it shines in. Lights at infinity
+    // count what falls on a disk the size of the scene.
     fn illuminate(&self) -> Float3;
-    fn intensity(&self) -> Float0;
+    // The power as one number, for picking lights by power.
+    fn intensity(&self) -> Float0 {
+        let power = self.illuminate();
+        (power.x() + power.y() + power.z()) / 3.0
+    }
     fn color(&self) -> Float3;
+    // Whether scattered rays can find the light too, so its samples are
+    // weighed against BSDF sampling. Lights that aren't part of the scene's
+    // geometry or sky are only reached through their own samples.
+    fn found_by_scattering(&self) -> bool {
+        false
+    }
+    // Where the light is and which way it shines, for the light BVH. None
+    // for lights at infinity.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+    // Radius of a sphere around the scene, which lights at infinity need
+    // to know their power.
+    fn set_scene_radius(&mut self, _radius: Float0) {}
+    // A ray leaving the light, for tracing paths from the lights. None for
+    // lights at infinity, which the integrator aims at the scene itself.
+    fn sample_emission(
+        &self,
+        _u_point: Float2,
+        _u_direction: Float2,
+        _wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<EmissionSample> {
+        None
+    }
+    // The densities `sample_emission` has of leaving from `point` towards
+    // `direction`: per unit area and per unit solid angle.
+    fn emission_pdf(&self, _point: Float3, _direction: Float3) -> (Float0, Float0) {
+        (0.0, 0.0)
+    }
     // fn clone_box(&self) -> Box<dyn Light>;
 }
 
@@ -44,3 +111,57 @@ pub struct LightSample {
     pub pdf: Float0,
     pub delta: bool,
 }
+
+// The radiance is along the ray. The normal is None for point-like lights,
+// whose position pdf is 1.
+#[derive(Debug, Clone, Copy)]
+pub struct EmissionSample {
+    pub ray: Ray,
+    pub normal: Option<Float3>,
+    pub radiance: Float3,
+    pub pdf_position: Float0,
+    pub pdf_direction: Float0,
+}
+
+fn uniform_sphere(u: Float2) -> Float3 {
+    let z = 1.0 - 2.0 * u.x();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.y();
+    Float3::new([r * phi.cos(), r * phi.sin(), z])
+}
+
+// A cosine-weighted direction around `normal`, with its cosine.
+fn cosine_hemisphere(normal: Float3, u: Float2) -> (Float3, Float0) {
+    let (tangent, bitangent) = generate_coordinate_system(&normal);
+    let r = u.x().sqrt();
+    let phi = 2.0 * PI * u.y();
+    let cos_theta = (1.0 - u.x()).max(0.0).sqrt();
+    let direction =
+        tangent.scale(r * phi.cos()) + bitangent.scale(r * phi.sin()) + normal.scale(cos_theta);
+    (direction, cos_theta)
+}
+
+// Emission from a flat light with `normal`, on its front or, when two-sided,
+// on either side picked by the first coordinate.
+fn flat_emission(normal: Float3, two_sided: bool, u: Float2) -> (Float3, Float3, Float0) {
+    if !two_sided {
+        let (direction, cos_theta) = cosine_hemisphere(normal, u);
+        return (direction, normal, cos_theta / PI);
+    }
+    let (side, u_x) = if u.x() < 0.5 {
+        (normal, 2.0 * u.x())
+    } else {
+        (-normal, 2.0 * u.x() - 1.0)
+    };
+    let (direction, cos_theta) = cosine_hemisphere(side, Float2::new([u_x, u.y()]));
+    (direction, side, 0.5 * cos_theta / PI)
+}
+
+fn flat_emission_pdf(normal: Float3, two_sided: bool, direction: Float3) -> Float0 {
+    let cos_theta = normal.dot(&direction);
+    if two_sided {
+        0.5 * cos_theta.abs() / PI
+    } else {
+        cos_theta.max(0.0) / PI
+    }
+}
@@ -1,47 +1,107 @@
 use crate::{
-    scene::{Float0, RNGType},
-    utils::vector::Float3,
+    object::aabb::Aabb,
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::{SampledWavelengths, Spectrum},
+    utils::vector::{Float2, Float3},
 };
 
-use super::{Light, LightSample};
-#[derive(Debug, Clone, Copy)]
+use super::{
+    EmissionSample, Light, LightSample, bvh::LightBounds, ies::OrientedProfile, uniform_sphere,
+};
+#[derive(Debug, Clone)]
 pub struct PointLight {
     position: Float3,
-    color: Float3,
+    emission: Spectrum,
+    profile: Option<OrientedProfile>,
 }
 
 impl PointLight {
-    pub fn new(position: Float3, color: Float3) -> Self {
-        PointLight { position, color }
+    pub fn new(position: Float3, emission: Spectrum) -> Self {
+        PointLight {
+            position,
+            emission,
+            profile: None,
+        }
+    }
+
+    // Shapes the emission by a measured distribution, with `emission` the
+    // intensity in its brightest direction.
+    pub fn with_profile(mut self, profile: OrientedProfile) -> Self {
+        self.profile = Some(profile);
+        self
     }
 }
 
 impl Light for PointLight {
     fn illuminate(&self) -> Float3 {
-        self.color
+        self.emission
+            .rgb()
+            .scale(4.0 * PI * self.profile.as_ref().map_or(1.0, OrientedProfile::mean))
     }
 
     fn position(&self) -> Float3 {
         self.position
     }
 
-    fn sample(&self, point: Float3, _rand_state: &mut RNGType) -> LightSample {
+    fn sample(
+        &self,
+        point: Float3,
+        _sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
         let to_light = self.position - point;
         let distance = to_light.length();
+        let direction = to_light.normalize();
+        let profile = self
+            .profile
+            .as_ref()
+            .map_or(1.0, |profile| profile.evaluate(-direction));
         LightSample {
-            direction: to_light.normalize(),
+            direction,
             distance,
-            radiance: self.color.scale(1.0 / (distance * distance)),
+            radiance: self
+                .emission
+                .evaluate(wavelengths)
+                .scale(profile / (distance * distance)),
             pdf: 1.0,
             delta: true,
         }
     }
 
-    fn intensity(&self) -> Float0 {
-        self.color.length()
+    fn color(&self) -> Float3 {
+        self.emission.rgb()
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::omnidirectional(
+            Aabb::new(self.position, self.position),
+            self.intensity(),
+        ))
     }
 
-    fn color(&self) -> Float3 {
-        self.color
+    fn sample_emission(
+        &self,
+        _u_point: Float2,
+        u_direction: Float2,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<EmissionSample> {
+        let direction = uniform_sphere(u_direction);
+        let profile = self
+            .profile
+            .as_ref()
+            .map_or(1.0, |profile| profile.evaluate(direction));
+        Some(EmissionSample {
+            ray: Ray::new(self.position, direction),
+            normal: None,
+            radiance: self.emission.evaluate(wavelengths).scale(profile),
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (4.0 * PI),
+        })
+    }
+
+    fn emission_pdf(&self, _point: Float3, _direction: Float3) -> (Float0, Float0) {
+        (1.0, 1.0 / (4.0 * PI))
     }
 }
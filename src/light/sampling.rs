@@ -0,0 +1,195 @@
+use std::str::FromStr;
+
+use crate::{scene::Float0, utils::distribution::Distribution1D, utils::vector::Float3};
+
+use super::{Light, bvh::LightBvh};
+
+// How next-event estimation picks lights: every light at every vertex, or
+// one light chosen in proportion to its power or by a light BVH that also
+// weighs distance and orientation relative to the shading point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LightSampling {
+    #[default]
+    All,
+    Power,
+    Bvh,
+}
+
+impl FromStr for LightSampling {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<LightSampling, ()> {
+        match s {
+            "all" => Ok(Self::All),
+            "power" => Ok(Self::Power),
+            "bvh" => Ok(Self::Bvh),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LightSampler {
+    strategy: LightSampling,
+    light_count: usize,
+    power: Distribution1D,
+    bvh: LightBvh,
+    // Lights at infinity, which the BVH can't bound; they are picked
+    // uniformly alongside it.
+    infinite: Vec<usize>,
+}
+
+impl LightSampler {
+    pub fn new(strategy: LightSampling, lights: &[Box<dyn Light>]) -> Self {
+        let powers: Vec<Float0> = lights
+            .iter()
+            .map(|light| light.intensity().max(0.0))
+            .collect();
+        let mut bounded = Vec::new();
+        let mut infinite = Vec::new();
+        for (index, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) => bounded.push((index, bounds)),
+                None => infinite.push(index),
+            }
+        }
+        Self {
+            strategy,
+            light_count: lights.len(),
+            power: Distribution1D::new(&powers, 0.0, 1.0),
+            bvh: LightBvh::build(bounded),
+            infinite,
+        }
+    }
+
+    pub fn strategy(&self) -> LightSampling {
+        self.strategy
+    }
+
+    // Picks the light to sample from `point`, with `normal` when it lies on
+    // a surface, returning its index and the probability of picking it.
+    // Always None when sampling every light.
+    pub fn sample(
+        &self,
+        point: Float3,
+        normal: Option<Float3>,
+        u: Float0,
+    ) -> Option<(usize, Float0)> {
+        if self.light_count == 0 {
+            return None;
+        }
+        match self.strategy {
+            LightSampling::All => None,
+            LightSampling::Power => {
+                let (_, pdf, index) = self.power.sample_continuous(u);
+                (pdf > 0.0).then_some((index, pdf / self.light_count as Float0))
+            }
+            LightSampling::Bvh => {
+                let p_infinite = self.infinite_probability();
+                if u < p_infinite {
+                    let slot = ((u / p_infinite * self.infinite.len() as Float0) as usize)
+                        .min(self.infinite.len() - 1);
+                    Some((
+                        self.infinite[slot],
+                        p_infinite / self.infinite.len() as Float0,
+                    ))
+                } else {
+                    let u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - Float0::EPSILON);
+                    self.bvh
+                        .sample(point, normal, u)
+                        .map(|(index, probability)| (index, probability * (1.0 - p_infinite)))
+                }
+            }
+        }
+    }
+
+    // Probability of picking a light at infinity, which doesn't depend on
+    // the shading point.
+    pub fn infinite_pmf(&self, light: usize) -> Float0 {
+        match self.strategy {
+            LightSampling::All => 1.0,
+            LightSampling::Power => {
+                self.power
+                    .pdf((light as Float0 + 0.5) / self.light_count as Float0)
+                    / self.light_count as Float0
+            }
+            LightSampling::Bvh => {
+                if self.infinite.contains(&light) {
+                    self.infinite_probability() / self.infinite.len() as Float0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    fn infinite_probability(&self) -> Float0 {
+        match (self.infinite.len(), self.bvh.is_empty()) {
+            (0, _) => 0.0,
+            (_, true) => 1.0,
+            (count, false) => count as Float0 / (count + 1) as Float0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        light::{environment::EnvironmentLight, pointlight::PointLight},
+        output::LinearImage,
+        skybox::environment::{EnvironmentMap, Projection},
+        spectrum::Spectrum,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn picks_lights_with_consistent_probabilities() {
+        let point = |x: Float0, power: Float0| -> Box<dyn Light> {
+            Box::new(PointLight::new(
+                Float3::new([x, 0.0, 0.0]),
+                Spectrum::Rgb(Float3::new([power; 3])),
+            ))
+        };
+        let sky = LinearImage {
+            width: 2,
+            height: 1,
+            pixels: vec![Float3::new([1.0, 1.0, 1.0]); 2],
+        };
+        let lights = vec![
+            point(0.0, 1.0),
+            point(5.0, 3.0),
+            point(-5.0, 0.0),
+            Box::new(EnvironmentLight::new(Arc::new(EnvironmentMap::new(
+                Projection::Equirectangular(sky),
+                0.0,
+                Float3::new([1.0, 1.0, 1.0]),
+            )))) as Box<dyn Light>,
+        ];
+        let at = Float3::new([1.0, 1.0, 0.0]);
+
+        for strategy in [LightSampling::Power, LightSampling::Bvh] {
+            let sampler = LightSampler::new(strategy, &lights);
+            let count = 10_000;
+            let mut frequency = [0.0; 4];
+            for i in 0..count {
+                let u = (i as Float0 + 0.5) / count as Float0;
+                let (light, probability) = sampler.sample(at, None, u).unwrap();
+                frequency[light] += 1.0 / count as Float0;
+                if light == 3 {
+                    assert!((probability - sampler.infinite_pmf(3)).abs() < 1e-9);
+                }
+            }
+            // A dark light is never picked, and the environment as often as
+            // its probability says.
+            assert_eq!(frequency[2], 0.0, "{strategy:?}");
+            assert!((frequency[3] - sampler.infinite_pmf(3)).abs() < 1e-3);
+            assert!((frequency.iter().sum::<Float0>() - 1.0).abs() < 1e-9);
+        }
+        assert!(
+            LightSampler::new(LightSampling::All, &lights)
+                .sample(at, None, 0.5)
+                .is_none()
+        );
+    }
+}
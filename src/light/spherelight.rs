@@ -0,0 +1,169 @@
+use crate::{
+    material::generate_coordinate_system,
+    object::aabb::Aabb,
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::{SampledWavelengths, Spectrum},
+    utils::vector::{Float2, Float3},
+};
+
+use super::{
+    EmissionSample, Light, LightSample, bvh::LightBounds, cosine_hemisphere, flat_emission_pdf,
+    uniform_sphere,
+};
+
+// A sphere emitting `emission` radiance from its whole surface, sampled
+// uniformly over the cone of directions it covers from the shading point.
+#[derive(Debug, Clone, Copy)]
+pub struct SphereLight {
+    center: Float3,
+    radius: Float0,
+    emission: Spectrum,
+}
+
+impl SphereLight {
+    pub fn new(center: Float3, radius: Float0, emission: Spectrum) -> Self {
+        Self {
+            center,
+            radius,
+            emission,
+        }
+    }
+
+    fn area(&self) -> Float0 {
+        4.0 * PI * self.radius * self.radius
+    }
+}
+
+impl Light for SphereLight {
+    fn illuminate(&self) -> Float3 {
+        self.emission.rgb().scale(PI * self.area())
+    }
+
+    fn position(&self) -> Float3 {
+        self.center
+    }
+
+    fn sample(
+        &self,
+        point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let to_center = self.center - point;
+        let distance_squared = to_center.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // Inside the light nothing is lit from the outside of it.
+            return LightSample {
+                direction: Float3::new([0.0, 1.0, 0.0]),
+                distance: 0.0,
+                radiance: Float3::new([0.0, 0.0, 0.0]),
+                pdf: 0.0,
+                delta: false,
+            };
+        }
+
+        let axis = to_center.normalize();
+        let sin_max_squared = radius_squared / distance_squared;
+        // 1 - cos, computed without cancellation for small, distant spheres.
+        let one_minus_cos_max = sin_max_squared / (1.0 + (1.0 - sin_max_squared).sqrt());
+
+        let u = sampler.get_2d();
+        let one_minus_cos = u.x() * one_minus_cos_max;
+        let cos_theta = 1.0 - one_minus_cos;
+        let sin_theta = (one_minus_cos * (2.0 - one_minus_cos)).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y();
+        let (tangent, bitangent) = generate_coordinate_system(&axis);
+        let direction = axis.scale(cos_theta)
+            + tangent.scale(sin_theta * phi.cos())
+            + bitangent.scale(sin_theta * phi.sin());
+
+        // Nearest intersection with the sphere along the sampled direction.
+        let along = to_center.dot(&direction);
+        let discriminant = (radius_squared - (distance_squared - along * along)).max(0.0);
+        let distance = along - discriminant.sqrt();
+
+        LightSample {
+            direction,
+            distance,
+            radiance: self.emission.evaluate(wavelengths),
+            pdf: 1.0 / (2.0 * PI * one_minus_cos_max),
+            delta: false,
+        }
+    }
+
+    fn color(&self) -> Float3 {
+        self.emission.rgb()
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let extent = Float3::new([self.radius; 3]);
+        Some(LightBounds::omnidirectional(
+            Aabb::new(self.center - extent, self.center + extent),
+            self.intensity(),
+        ))
+    }
+
+    fn sample_emission(
+        &self,
+        u_point: Float2,
+        u_direction: Float2,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<EmissionSample> {
+        if self.radius <= 0.0 {
+            return None;
+        }
+        let normal = uniform_sphere(u_point);
+        let (direction, cos_theta) = cosine_hemisphere(normal, u_direction);
+        Some(EmissionSample {
+            ray: Ray::new(self.center + normal.scale(self.radius), direction),
+            normal: Some(normal),
+            radiance: self.emission.evaluate(wavelengths),
+            pdf_position: 1.0 / self.area(),
+            pdf_direction: cos_theta / PI,
+        })
+    }
+
+    fn emission_pdf(&self, point: Float3, direction: Float3) -> (Float0, Float0) {
+        let normal = (point - self.center).normalize();
+        (
+            1.0 / self.area(),
+            flat_emission_pdf(normal, false, direction),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pathtracer::get_rng, sampler::independent::IndependentSampler};
+
+    #[test]
+    fn sampling_integrates_the_visible_cap() {
+        let light = SphereLight::new(
+            Float3::new([0.0, 3.0, 0.0]),
+            1.0,
+            Spectrum::Rgb(Float3::new([1.0, 1.0, 1.0])),
+        );
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let point = Float3::new([0.0, 0.0, 0.0]);
+
+        // Irradiance on an upward-facing point below a sphere of unit
+        // radiance is pi (r / d)^2.
+        let count = 20_000;
+        let mut irradiance = 0.0;
+        for _ in 0..count {
+            let sample = light.sample(point, &mut sampler, None);
+            let hit = point + sample.direction.scale(sample.distance);
+            assert!(((hit - light.center).length() - 1.0).abs() < 1e-9);
+            irradiance += sample.radiance.x() * sample.direction.y() / sample.pdf;
+        }
+        irradiance /= count as Float0;
+        assert!((irradiance - PI / 9.0).abs() < 0.01, "{irradiance}");
+
+        let inside = light.sample(light.center, &mut sampler, None);
+        assert_eq!(inside.pdf, 0.0);
+    }
+}
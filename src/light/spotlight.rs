@@ -0,0 +1,225 @@
+use crate::{
+    material::generate_coordinate_system,
+    object::aabb::Aabb,
+    ray::Ray,
+    sampler::Sampler,
+    scene::{Float0, PI},
+    spectrum::{SampledWavelengths, Spectrum},
+    utils::vector::{Float2, Float3},
+};
+
+use super::{EmissionSample, Light, LightSample, bvh::LightBounds, ies::OrientedProfile};
+
+// A point light shining into a cone around `direction`. Full intensity
+// inside `inner_angle`, fading smoothly to nothing at `outer_angle`; both
+// are half-angles.
+#[derive(Debug, Clone)]
+pub struct SpotLight {
+    position: Float3,
+    direction: Float3,
+    cos_inner: Float0,
+    cos_outer: Float0,
+    emission: Spectrum,
+    profile: Option<OrientedProfile>,
+    // Solid angle the beam would fill at full intensity.
+    solid_angle: Float0,
+}
+
+impl SpotLight {
+    // Angles in degrees.
+    pub fn new(
+        position: Float3,
+        direction: Float3,
+        inner_angle: Float0,
+        outer_angle: Float0,
+        emission: Spectrum,
+    ) -> Self {
+        let cos_outer = outer_angle.to_radians().cos();
+        let cos_inner = inner_angle
+            .min(outer_angle)
+            .to_radians()
+            .cos()
+            .max(cos_outer);
+        Self {
+            position,
+            direction: direction.normalize(),
+            cos_inner,
+            cos_outer,
+            emission,
+            profile: None,
+            // The smoothstep averages to a half over the falloff.
+            solid_angle: 2.0 * PI * (1.0 - 0.5 * (cos_inner + cos_outer)),
+        }
+    }
+
+    // Shapes the beam by a measured distribution as well as the cone.
+    pub fn with_profile(mut self, profile: OrientedProfile) -> Self {
+        self.profile = Some(profile);
+        self.solid_angle = self.integrate_beam();
+        self
+    }
+
+    pub fn direction(&self) -> Float3 {
+        self.direction
+    }
+
+    fn falloff(&self, cos_theta: Float0) -> Float0 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // The falloff times the profile integrated over the outer cone.
+    fn integrate_beam(&self) -> Float0 {
+        let steps = 128;
+        let (tangent, bitangent) = generate_coordinate_system(&self.direction);
+        let theta_outer = self.cos_outer.clamp(-1.0, 1.0).acos();
+        let mut total = 0.0;
+        for i in 0..steps {
+            let theta = (i as Float0 + 0.5) / steps as Float0 * theta_outer;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            for j in 0..2 * steps {
+                let phi = (j as Float0 + 0.5) / (2 * steps) as Float0 * 2.0 * PI;
+                let direction = self.direction.scale(cos_theta)
+                    + tangent.scale(sin_theta * phi.cos())
+                    + bitangent.scale(sin_theta * phi.sin());
+                let profile = self
+                    .profile
+                    .as_ref()
+                    .map_or(1.0, |profile| profile.evaluate(direction));
+                total += self.falloff(cos_theta) * profile * sin_theta;
+            }
+        }
+        total * (theta_outer / steps as Float0) * (PI / steps as Float0)
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self) -> Float3 {
+        self.emission.rgb().scale(self.solid_angle)
+    }
+
+    fn position(&self) -> Float3 {
+        self.position
+    }
+
+    fn sample(
+        &self,
+        point: Float3,
+        _sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        let direction = to_light.normalize();
+        let falloff = self.falloff(self.direction.dot(&-direction))
+            * self
+                .profile
+                .as_ref()
+                .map_or(1.0, |profile| profile.evaluate(-direction));
+        LightSample {
+            direction,
+            distance,
+            radiance: self
+                .emission
+                .evaluate(wavelengths)
+                .scale(falloff / (distance * distance)),
+            pdf: 1.0,
+            delta: true,
+        }
+    }
+
+    fn color(&self) -> Float3 {
+        self.emission.rgb()
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let falloff_angle = self.cos_outer.acos() - self.cos_inner.acos();
+        Some(LightBounds {
+            cos_theta_o: self.cos_inner,
+            cos_theta_e: falloff_angle.cos(),
+            ..LightBounds::facing(
+                Aabb::new(self.position, self.position),
+                self.intensity(),
+                self.direction,
+            )
+        })
+    }
+
+    // Directions are uniform over the outer cone.
+    fn sample_emission(
+        &self,
+        _u_point: Float2,
+        u_direction: Float2,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<EmissionSample> {
+        let cos_theta = 1.0 - u_direction.x() * (1.0 - self.cos_outer);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u_direction.y();
+        let (tangent, bitangent) = generate_coordinate_system(&self.direction);
+        let direction = self.direction.scale(cos_theta)
+            + tangent.scale(sin_theta * phi.cos())
+            + bitangent.scale(sin_theta * phi.sin());
+        let falloff = self.falloff(cos_theta)
+            * self
+                .profile
+                .as_ref()
+                .map_or(1.0, |profile| profile.evaluate(direction));
+        Some(EmissionSample {
+            ray: Ray::new(self.position, direction),
+            normal: None,
+            radiance: self.emission.evaluate(wavelengths).scale(falloff),
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (2.0 * PI * (1.0 - self.cos_outer)),
+        })
+    }
+
+    fn emission_pdf(&self, _point: Float3, direction: Float3) -> (Float0, Float0) {
+        if self.direction.dot(&direction) < self.cos_outer {
+            return (1.0, 0.0);
+        }
+        (1.0, 1.0 / (2.0 * PI * (1.0 - self.cos_outer)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pathtracer::get_rng, sampler::independent::IndependentSampler};
+
+    #[test]
+    fn fades_between_the_cones() {
+        let light = SpotLight::new(
+            Float3::new([0.0, 2.0, 0.0]),
+            Float3::new([0.0, -1.0, 0.0]),
+            20.0,
+            40.0,
+            Spectrum::Rgb(Float3::new([4.0, 4.0, 4.0])),
+        );
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let at = |x: Float0, sampler: &mut IndependentSampler| {
+            light
+                .sample(Float3::new([x, 0.0, 0.0]), sampler, None)
+                .radiance
+                .x()
+        };
+        // Straight below, 2 units away.
+        assert!((at(0.0, &mut sampler) - 1.0).abs() < 1e-12);
+        // 30 degrees out, halfway between the cones, the smoothstep over
+        // cosines has fallen to 0.61.
+        let cos_theta = 30f64.to_radians().cos();
+        let halfway = at(2.0 * 30f64.to_radians().tan(), &mut sampler);
+        let falloff = halfway * (2.0 / cos_theta).powi(2) / 4.0;
+        assert!((falloff - 0.613).abs() < 1e-3, "{falloff}");
+        assert_eq!(at(2.0 * 45f64.to_radians().tan(), &mut sampler), 0.0);
+
+        // The power follows from the same falloff.
+        let power = light.illuminate().x();
+        assert!((power - 4.0 * light.integrate_beam()).abs() < 1e-3 * power);
+    }
+}
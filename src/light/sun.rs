@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use crate::{
+    sampler::Sampler,
+    scene::{Float0, PI},
+    skybox::sunsky::SunSky,
+    spectrum::{self, SampledWavelengths},
+    utils::vector::Float3,
+};
+
+use super::{Light, LightSample};
+
+// The sun of a physical sky, sampled over its disk for next-event
+// estimation. The sky itself is only found by scattered rays.
+#[derive(Debug, Clone)]
+pub struct SunLight {
+    sun_sky: Arc<SunSky>,
+    scene_radius: Float0,
+}
+
+impl SunLight {
+    pub fn new(sun_sky: Arc<SunSky>) -> Self {
+        Self {
+            sun_sky,
+            // Until the scene says how big it is.
+            scene_radius: 1.0,
+        }
+    }
+}
+
+impl Light for SunLight {
+    fn illuminate(&self) -> Float3 {
+        self.sun_sky
+            .sun_irradiance()
+            .scale(PI * self.scene_radius * self.scene_radius)
+    }
+
+    fn position(&self) -> Float3 {
+        self.sun_sky.sun_direction()
+    }
+
+    fn sample(
+        &self,
+        _point: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> LightSample {
+        let (direction, radiance, pdf) = self.sun_sky.sample_sun(sampler.get_2d());
+        LightSample {
+            direction,
+            distance: Float0::INFINITY,
+            radiance: spectrum::uplift(radiance, wavelengths),
+            pdf,
+            delta: false,
+        }
+    }
+
+    fn color(&self) -> Float3 {
+        self.sun_sky.sun_irradiance()
+    }
+
+    fn found_by_scattering(&self) -> bool {
+        true
+    }
+
+    fn set_scene_radius(&mut self, radius: Float0) {
+        self.scene_radius = radius;
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{color::ColorSpace, scene::Float0, utils::vector::Float3};
+
+// Radiance in the renderer is in kilonits (1000 cd/m²), the unit of the sun
+// and sky, so lamps given in physical units and daylight balance under a
+// single exposure.
+pub const NITS_PER_UNIT: Float0 = 1000.0;
+// Luminous efficacy of 555 nm light, for lamps rated in watts.
+pub const LUMENS_PER_WATT: Float0 = 683.0;
+
+// How bright a light is in physical units. Candela, nits and lux set the
+// emission of point-like, surface and directional lights respectively;
+// watts and lumens set the total power whatever the light's shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightUnit {
+    Watts(Float0),
+    Lumens(Float0),
+    Candela(Float0),
+    Nits(Float0),
+    Lux(Float0),
+}
+
+impl LightUnit {
+    pub const KEYS: [&'static str; 5] = ["watts", "lumens", "candela", "nits", "lux"];
+
+    pub fn from_key(key: &str, value: Float0) -> Option<Self> {
+        match key {
+            "watts" => Some(Self::Watts(value)),
+            "lumens" => Some(Self::Lumens(value)),
+            "candela" => Some(Self::Candela(value)),
+            "nits" => Some(Self::Nits(value)),
+            "lux" => Some(Self::Lux(value)),
+            _ => None,
+        }
+    }
+
+    // Total luminous power, for the units that give one.
+    pub fn lumens(self) -> Option<Float0> {
+        match self {
+            Self::Watts(watts) => Some(watts * LUMENS_PER_WATT),
+            Self::Lumens(lumens) => Some(lumens),
+            Self::Candela(_) | Self::Nits(_) | Self::Lux(_) => None,
+        }
+    }
+}
+
+// Luminance of a working-space colour, in the same units.
+pub fn luminance(color: Float3, color_space: ColorSpace) -> Float0 {
+    color_space.rgb_to_xyz().multiply_by_vector(&color).y()
+}
+
+// The `--exposure` in stops that makes the image match a camera set to
+// `ev100` (exposure value at ISO 100), by the saturation-based sensitivity
+// of ISO 2720: a pixel value of 1 is 1.2 * 2^ev100 nits.
+pub fn exposure_for_ev100(ev100: Float0) -> Float0 {
+    (NITS_PER_UNIT / (1.2 * (2.0 as Float0).powf(ev100))).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_units_and_exposure() {
+        assert_eq!(LightUnit::Watts(10.0).lumens(), Some(6830.0));
+        assert_eq!(
+            LightUnit::from_key("lumens", 800.0).unwrap().lumens(),
+            Some(800.0)
+        );
+        assert_eq!(LightUnit::Nits(1.0).lumens(), None);
+        assert!(LightUnit::from_key("foot_candles", 1.0).is_none());
+
+        let white = Float3::new([1.0, 1.0, 1.0]);
+        assert!((luminance(white, ColorSpace::LinearSrgb) - 1.0).abs() < 1e-3);
+
+        // Sunny 16: a white card in sunlight, about 32 kilonits, comes out
+        // near 1.
+        let exposure = exposure_for_ev100(15.0);
+        assert!((32.0 * (2.0 as Float0).powf(exposure) - 1.0).abs() < 0.25);
+    }
+}
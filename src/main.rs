@@ -1,89 +1,315 @@
 use pathtracer::{
+    film::Channel,
+    filter::{Filter, FilterMode, FilterType},
+    integrator::{IntegratorType, mlt::MetropolisSettings},
+    light::{sampling::LightSampling, units},
+    output::{self, ImageFormat, OutputSettings},
     pathtracer::PathTracer,
+    sampler::SamplerType,
     scene::{Float0, Scene},
-    utils::vector::Vector,
 };
 
-use png::{BitDepth, ColorType, Encoder, text_metadata::ITXtChunk};
 use toml::Value;
 
-use std::fs::{self, File};
-use std::{env, path::Path};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+struct OutputOptions {
+    file: Option<PathBuf>,
+    format: Option<ImageFormat>,
+    settings: OutputSettings,
+}
+
+impl OutputOptions {
+    // An explicit `--format` wins over the extension of `-o`, PNG is the fallback.
+    fn format(&self, path: &Path) -> ImageFormat {
+        self.format
+            .or_else(|| ImageFormat::from_path(path))
+            .unwrap_or_default()
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    let (multiplier, args_offset) = if args.len() > 1 && args[1].starts_with("--multiplier=") {
-        let multiplier_str: &str = &args[1]["--multiplier=".len()..];
-        let multiplier: usize = multiplier_str
-            .parse()
-            .expect("Multiplier must be a positive integer");
-        (multiplier, 2)
-    } else {
-        (1, 1)
+    let mut multiplier: usize = 1;
+    let mut sampler = SamplerType::default();
+    let mut integrator = IntegratorType::default();
+    let mut photons: Option<usize> = None;
+    let mut photon_radius: Option<Float0> = None;
+    let mut metropolis = MetropolisSettings::default();
+    let mut filter_type = FilterType::default();
+    let mut filter_radius: Option<Float0> = None;
+    let mut filter_mode = FilterMode::default();
+    let mut aovs: Vec<Channel> = Vec::new();
+    let mut light_aovs = false;
+    let mut denoise = true;
+    let mut prefilter_aux = false;
+    let mut spectral = false;
+    let mut light_sampling = LightSampling::default();
+    let mut output = OutputOptions {
+        file: None,
+        format: None,
+        settings: OutputSettings::default(),
     };
+    let mut args: Vec<String> = Vec::new();
+
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "-o" {
+            output.file = Some(argv.next().expect("-o needs an output file").into());
+        } else if let Some(file_str) = arg.strip_prefix("--output=") {
+            output.file = Some(file_str.into());
+        } else if let Some(format_str) = arg.strip_prefix("--format=") {
+            output.format = Some(format_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown image format `{format_str}` (png, exr, hdr, pfm)")
+            }));
+        } else if let Some(exposure_str) = arg.strip_prefix("--exposure=") {
+            output.settings.png.display.exposure = exposure_str
+                .parse()
+                .expect("Exposure must be a number of stops");
+        } else if let Some(ev100_str) = arg.strip_prefix("--ev100=") {
+            output.settings.png.display.exposure = units::exposure_for_ev100(
+                ev100_str.parse().expect("EV100 must be an exposure value"),
+            );
+        } else if let Some(tonemap_str) = arg.strip_prefix("--tonemap=") {
+            output.settings.png.display.tone_mapper = tonemap_str.parse().unwrap_or_else(|_| {
+                panic!(
+                    "Unknown tone mapper `{tonemap_str}` (clamp, reinhard, extended_reinhard, aces, agx)"
+                )
+            });
+        } else if let Some(white_str) = arg.strip_prefix("--white-point=") {
+            output.settings.png.display.white_point = white_str
+                .parse::<Float0>()
+                .ok()
+                .filter(|white| *white > 0.0 && white.is_finite())
+                .expect("White point must be a positive number");
+        } else if let Some(transfer_str) = arg.strip_prefix("--transfer=") {
+            output.settings.png.display.transfer = transfer_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown transfer function `{transfer_str}` (srgb, rec709, linear)")
+            });
+        } else if let Some(space_str) = arg.strip_prefix("--display-space=") {
+            output.settings.png.display.display_space = space_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown display color space `{space_str}` (rec709, rec2020, acescg)")
+            });
+        } else if arg == "--no-dither" {
+            output.settings.png.dither = false;
+        } else if let Some(bits_str) = arg.strip_prefix("--png-bits=") {
+            output.settings.png.bit_depth = bits_str
+                .parse()
+                .unwrap_or_else(|_| panic!("PNG bit depth must be 8 or 16, not `{bits_str}`"));
+        } else if let Some(alpha_str) = arg.strip_prefix("--png-alpha=") {
+            output.settings.png.alpha = alpha_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown PNG alpha `{alpha_str}` (straight, premultiplied)")
+            });
+        } else if let Some(precision_str) = arg.strip_prefix("--exr-precision=") {
+            output.settings.exr.precision = precision_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown EXR precision `{precision_str}` (half, float)")
+            });
+        } else if let Some(compression_str) = arg.strip_prefix("--exr-compression=") {
+            output.settings.exr.compression = compression_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown EXR compression `{compression_str}` (none, zip, piz)")
+            });
+        } else if let Some(multiplier_str) = arg.strip_prefix("--multiplier=") {
+            multiplier = multiplier_str
+                .parse()
+                .expect("Multiplier must be a positive integer");
+        } else if let Some(sampler_str) = arg.strip_prefix("--sampler=") {
+            sampler = sampler_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown sampler `{sampler_str}` (independent, stratified, halton, sobol, zsobol)")
+            });
+        } else if let Some(integrator_str) = arg.strip_prefix("--integrator=") {
+            integrator = integrator_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown integrator `{integrator_str}` (path, bdpt, sppm, mlt)")
+            });
+        } else if let Some(photons_str) = arg.strip_prefix("--photons=") {
+            photons = Some(
+                photons_str
+                    .parse::<NonZeroUsize>()
+                    .expect("Photon count must be a positive integer")
+                    .get(),
+            );
+        } else if let Some(radius_str) = arg.strip_prefix("--photon-radius=") {
+            photon_radius = Some(
+                radius_str
+                    .parse::<Float0>()
+                    .ok()
+                    .filter(|radius| *radius > 0.0 && radius.is_finite())
+                    .expect("Photon radius must be a positive number"),
+            );
+        } else if let Some(bootstrap_str) = arg.strip_prefix("--mlt-bootstrap=") {
+            metropolis.bootstrap_samples = bootstrap_str
+                .parse::<NonZeroUsize>()
+                .expect("Bootstrap sample count must be a positive integer")
+                .get();
+        } else if let Some(chains_str) = arg.strip_prefix("--mlt-chains=") {
+            metropolis.chains = chains_str
+                .parse::<NonZeroUsize>()
+                .expect("Chain count must be a positive integer")
+                .get();
+        } else if let Some(sigma_str) = arg.strip_prefix("--mlt-sigma=") {
+            metropolis.sigma = sigma_str
+                .parse::<Float0>()
+                .ok()
+                .filter(|sigma| *sigma > 0.0)
+                .expect("Mutation sigma must be a positive number");
+        } else if let Some(probability_str) = arg.strip_prefix("--mlt-large-step=") {
+            metropolis.large_step_probability = probability_str
+                .parse::<Float0>()
+                .ok()
+                .filter(|probability| (0.0..=1.0).contains(probability))
+                .expect("Large step probability must be a number between 0 and 1");
+        } else if let Some(filter_str) = arg.strip_prefix("--filter=") {
+            filter_type = filter_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown filter `{filter_str}` (box, tent, gaussian, mitchell, lanczos)")
+            });
+        } else if let Some(radius_str) = arg.strip_prefix("--filter-radius=") {
+            filter_radius = Some(
+                radius_str
+                    .parse::<Float0>()
+                    .ok()
+                    .filter(|radius| *radius > 0.0 && radius.is_finite())
+                    .expect("Filter radius must be a positive number"),
+            );
+        } else if let Some(mode_str) = arg.strip_prefix("--filter-mode=") {
+            filter_mode = mode_str
+                .parse()
+                .unwrap_or_else(|_| panic!("Unknown filter mode `{mode_str}` (importance, splat)"));
+        } else if arg == "--no-denoise" {
+            denoise = false;
+        } else if arg == "--prefilter-aux" {
+            prefilter_aux = true;
+        } else if arg == "--spectral" {
+            spectral = true;
+        } else if let Some(strategy_str) = arg.strip_prefix("--light-sampling=") {
+            light_sampling = strategy_str.parse().unwrap_or_else(|_| {
+                panic!("Unknown light sampling `{strategy_str}` (all, power, bvh)")
+            });
+        } else if let Some(aovs_str) = arg.strip_prefix("--aovs=") {
+            for aov in aovs_str.split(',').filter(|aov| !aov.is_empty()) {
+                if aov == "lights" {
+                    light_aovs = true;
+                } else {
+                    aovs.push(
+                        aov.parse()
+                            .unwrap_or_else(|_| panic!("Unknown AOV channel `{aov}`")),
+                    );
+                }
+            }
+        } else {
+            args.push(arg);
+        }
+    }
 
     let width: usize = 1280 * multiplier;
     let height: usize = 720 * multiplier;
     let sample_count: usize = 256 * multiplier;
 
-    let pathtracer = PathTracer::new(width, height, sample_count);
+    let filter = Filter::new(
+        filter_type,
+        filter_radius.unwrap_or_else(|| filter_type.default_radius()),
+    );
 
-    match args.len() - args_offset {
-        0 => {
-            trace_scene_file("scenes/scene.toml", "renders/scene.png", &pathtracer);
+    let pathtracer = PathTracer::new(width, height, sample_count)
+        .with_sampler(sampler)
+        .with_integrator(integrator)
+        .with_photons(photons, photon_radius)
+        .with_metropolis(metropolis)
+        .with_filter(filter, filter_mode)
+        .with_aovs(aovs, light_aovs)
+        .with_denoiser(denoise, prefilter_aux)
+        .with_spectral(spectral);
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] | ["render"] => {
+            let output_file = output_file(&output, Path::new("renders/scene"));
+            trace_scene_file(
+                "scenes/scene.toml",
+                &output_file,
+                &pathtracer,
+                light_sampling,
+                &output,
+            );
         }
-        1 if args[args_offset] == "--all" => {
-            trace_all_scenes(&pathtracer);
+        ["--all"] => {
+            trace_all_scenes(&pathtracer, light_sampling, &output);
         }
-        1 => {
-            let scene_file = &args[args_offset];
-            let output_file = format!("{}.png", scene_file.trim_end_matches(".toml"));
-            trace_scene_file(scene_file, &output_file, &pathtracer);
+        [scene_file] | ["render", scene_file] => {
+            let output_file = output_file(&output, &Path::new(scene_file).with_extension(""));
+            trace_scene_file(
+                scene_file,
+                &output_file,
+                &pathtracer,
+                light_sampling,
+                &output,
+            );
         }
         _ => {
-            println!("Usage: pathtracer [--multiplier=N] [scene_file.toml] or --all");
+            println!(
+                "Usage: pathtracer [--multiplier=N] [--sampler=NAME] [--integrator=path|bdpt|sppm|mlt] [--photons=N] [--photon-radius=R] [--mlt-bootstrap=N] [--mlt-chains=N] [--mlt-sigma=S] [--mlt-large-step=P] [--filter=NAME] [--filter-radius=R] [--filter-mode=MODE] [--aovs=LIST] [--no-denoise] [--prefilter-aux] [--spectral] [--light-sampling=all|power|bvh] [-o FILE] [--format=png|exr|hdr|pfm] [--exposure=EV] [--ev100=EV] [--tonemap=NAME] [--white-point=W] [--transfer=srgb|rec709|linear] [--display-space=rec709|rec2020|acescg] [--no-dither] [--png-bits=8|16] [--png-alpha=straight|premultiplied] [--exr-precision=half|float] [--exr-compression=none|zip|piz] [render] [scene_file.toml] or --all"
+            );
         }
     }
 }
 
-fn trace_scene_file(scene_file: &str, output_file: &str, pathtracer: &PathTracer) {
+fn output_file(output: &OutputOptions, default_stem: &Path) -> PathBuf {
+    output.file.clone().unwrap_or_else(|| {
+        default_stem.with_extension(output.format.unwrap_or_default().extension())
+    })
+}
+
+fn trace_scene_file(
+    scene_file: &str,
+    output_file: &Path,
+    pathtracer: &PathTracer,
+    light_sampling: LightSampling,
+    output: &OutputOptions,
+) {
     let toml_str: String = fs::read_to_string(scene_file).expect("Failed to read scene.toml");
     let value: Value = toml::from_str::<Value>(&toml_str).expect("Failed to parse TOML file");
-    let buffer: Vec<Vector<f64, 3>> = {
+    let (film, color_space) = {
         let scene = Scene::try_from_toml(&value)
-            .unwrap_or_else(|err| panic!("Failed to parse scene `{scene_file}`: {err}"));
-        pathtracer.trace(&scene, false)
+            .unwrap_or_else(|err| panic!("Failed to parse scene `{scene_file}`: {err}"))
+            .with_light_sampling(light_sampling);
+        pathtracer
+            .supports(&scene)
+            .unwrap_or_else(|err| panic!("Can't render `{scene_file}`: {err}"));
+        (pathtracer.render(&scene, false), scene.color_space)
     };
 
-    let mut encoder = Encoder::new(
-        File::create(output_file).unwrap(),
-        pathtracer.width as u32,
-        pathtracer.height as u32,
-    );
-    encoder.set_color(ColorType::Rgb);
-    encoder.set_depth(BitDepth::Eight);
-
-    let mut writer = encoder.write_header().unwrap();
-
-    let modbuffer = &buffer
-        .iter()
-        .flat_map(|color| {
-            let color = color.scale(255.0 as Float0);
-            vec![(color.0[0]) as u8, (color.0[1]) as u8, (color.0[2]) as u8]
-        })
-        .collect::<Vec<u8>>();
-
-    match writer.write_image_data(modbuffer) {
-        Ok(_) => println!("Image written to {output_file}"),
+    let channels: Vec<Channel> = film
+        .channels()
+        .filter(|channel| pathtracer.is_output(*channel))
+        .collect();
+    match output::write_film(
+        output_file,
+        output.format(output_file),
+        &film,
+        &channels,
+        &output.settings.with_working_space(color_space),
+        &toml_str,
+    ) {
+        Ok(files) => {
+            for file in files {
+                println!("Image written to {}", file.display());
+            }
+        }
         Err(e) => eprintln!("Error writing image: {e}"),
     }
-
-    let tail = ITXtChunk::new("scene", &toml_str);
-    writer.write_text_chunk(&tail).unwrap();
 }
 
-fn trace_all_scenes(pathtracer: &PathTracer) {
+fn trace_all_scenes(
+    pathtracer: &PathTracer,
+    light_sampling: LightSampling,
+    output: &OutputOptions,
+) {
     let scenes_dir = Path::new("./scenes");
 
     let entries = match fs::read_dir(scenes_dir) {
@@ -113,12 +339,12 @@ fn trace_all_scenes(pathtracer: &PathTracer) {
                 }
             };
 
-            let output_file = path.file_stem().map_or_else(
-                || "renders/output.png".to_string(),
-                |stem| format!("renders/{}.png", stem.to_string_lossy()),
-            );
+            let stem = path.file_stem().unwrap_or("output".as_ref());
+            let output_file = Path::new("renders")
+                .join(stem)
+                .with_extension(output.format.unwrap_or_default().extension());
 
-            trace_scene_file(scene_file, &output_file, pathtracer);
+            trace_scene_file(scene_file, &output_file, pathtracer, light_sampling, output);
         }
     }
 }
@@ -0,0 +1,108 @@
+use crate::{scene::Float0, utils::vector::Float3};
+
+// Index of refraction as a function of wavelength. Cauchy and Sellmeier take
+// the wavelength in micrometres, as the published coefficients do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    Constant(Float0),
+    Cauchy { a: Float0, b: Float0 },
+    Sellmeier { b: [Float0; 3], c: [Float0; 3] },
+}
+
+impl Ior {
+    // The sodium D line most refractive indices are quoted at, used when
+    // rendering in RGB.
+    pub const REFERENCE_WAVELENGTH: Float0 = 589.3;
+
+    // Schott N-BK7 crown glass.
+    pub const CROWN_GLASS: Ior = Ior::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+    // Schott N-SF11 dense flint glass, with roughly three times BK7's dispersion.
+    pub const FLINT_GLASS: Ior = Ior::Sellmeier {
+        b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+        c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+    };
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    pub fn at(&self, lambda: Float0) -> Float0 {
+        let micrometres = lambda * 1e-3;
+        let squared = micrometres * micrometres;
+        match self {
+            Self::Constant(ior) => *ior,
+            Self::Cauchy { a, b } => a + b / squared,
+            Self::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c)
+                    .map(|(b, c)| b * squared / (squared - c))
+                    .sum::<Float0>())
+            .sqrt(),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Self::Constant(_))
+    }
+}
+
+// Unpolarized Fresnel reflectance for light arriving at `cos_incident` to the
+// normal, where `eta` is the incident over the transmitted index.
+pub fn fresnel(cos_incident: Float0, eta: Float0) -> Float0 {
+    let cos_incident = cos_incident.clamp(0.0, 1.0);
+    let sin_transmitted_squared = eta * eta * (1.0 - cos_incident * cos_incident);
+    if sin_transmitted_squared >= 1.0 {
+        return 1.0;
+    }
+    let cos_transmitted = (1.0 - sin_transmitted_squared).sqrt();
+    let parallel = (cos_incident - eta * cos_transmitted) / (cos_incident + eta * cos_transmitted);
+    let perpendicular =
+        (eta * cos_incident - cos_transmitted) / (eta * cos_incident + cos_transmitted);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// `direction` and `normal` are unit vectors with the normal facing the
+// incoming ray. Returns None on total internal reflection.
+pub fn refract(direction: &Float3, normal: &Float3, eta: Float0) -> Option<Float3> {
+    let cos_incident = -direction.dot(normal);
+    let sin_transmitted_squared = eta * eta * (1.0 - cos_incident * cos_incident);
+    if sin_transmitted_squared >= 1.0 {
+        return None;
+    }
+    let cos_transmitted = (1.0 - sin_transmitted_squared).sqrt();
+    Some(direction.scale(eta) + normal.scale(eta * cos_incident - cos_transmitted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glasses_match_catalogue_indices() {
+        assert!((Ior::CROWN_GLASS.at(587.6) - 1.5168).abs() < 1e-4);
+        assert!((Ior::FLINT_GLASS.at(587.6) - 1.7847).abs() < 1e-4);
+        assert!((Ior::DIAMOND.at(Ior::REFERENCE_WAVELENGTH) - 2.417).abs() < 1e-3);
+        // Normal dispersion: blue bends more than red.
+        assert!(Ior::CROWN_GLASS.at(450.0) > Ior::CROWN_GLASS.at(650.0));
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.005 };
+        assert!((cauchy.at(500.0) - 1.52).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fresnel_and_refraction_obey_snell() {
+        // 4% reflectance at normal incidence on glass, total internal
+        // reflection beyond the critical angle on the way out.
+        assert!((fresnel(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel(0.5, 1.5), 1.0);
+
+        let normal = Float3::new([0.0, 1.0, 0.0]);
+        let direction = Float3::new([0.6, -0.8, 0.0]);
+        let refracted = refract(&direction, &normal, 1.0 / 1.5).unwrap();
+        assert!((refracted.length() - 1.0).abs() < 1e-9);
+        assert!((refracted.x() - 0.6 / 1.5).abs() < 1e-9);
+        assert!(refract(&Float3::new([0.8, -0.6, 0.0]), &normal, 1.5).is_none());
+    }
+}
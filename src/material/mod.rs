@@ -1,23 +1,36 @@
 use crate::{
     object::HitRecord,
     ray::Ray,
-    scene::{Float0, PI, RNGType},
+    sampler::Sampler,
+    scene::{Float0, PI},
     utils::{
         matrix::Float3x3,
         vector::{Float2, Float3},
     },
 };
 
-use rand::prelude::*;
+pub mod dielectric;
+pub mod subsurface;
+
+use dielectric::Ior;
+use subsurface::Subsurface;
 
 #[derive(Debug)]
 pub struct Material {
     pub albedo: Float3,
     pub reflectivity: Float0,
     pub checkered: bool,
+    pub ior: Option<Ior>,
+    pub subsurface: Option<Subsurface>,
+    // Seen by the camera, only records the shadows and reflections other
+    // objects cast onto it, for compositing over a photograph.
+    pub shadow_catcher: bool,
+    // An index-matched boundary that only marks where a medium starts; rays
+    // pass straight through it.
+    pub interface: bool,
 }
 
-fn generate_coordinate_system(normal: &Float3) -> (Float3, Float3) {
+pub(crate) fn generate_coordinate_system(normal: &Float3) -> (Float3, Float3) {
     let w = *normal;
     let a = if w.x().abs() > 0.9 {
         Float3::new([0.0, 1.0, 0.0])
@@ -31,12 +44,13 @@ fn generate_coordinate_system(normal: &Float3) -> (Float3, Float3) {
 }
 
 #[allow(dead_code)]
-fn random_unit_vector(rand_state: &mut RNGType) -> (Float3, Float0) {
+fn random_unit_vector(sampler: &mut dyn Sampler) -> (Float3, Float0) {
     fn pdf() -> Float0 {
         1.0 / (4.0 * PI as Float0)
     }
-    let theta: Float0 = rand_state.random_range(0.0..(PI as Float0));
-    let phi: Float0 = rand_state.random_range(0.0..(2.0 * PI as Float0));
+    let u = sampler.get_2d();
+    let theta: Float0 = u.x() * PI as Float0;
+    let phi: Float0 = u.y() * 2.0 * PI as Float0;
     (
         Float3::new([
             theta.sin() * phi.cos(),
@@ -47,6 +61,7 @@ fn random_unit_vector(rand_state: &mut RNGType) -> (Float3, Float0) {
     )
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SamplingFunctions {
     RandomUnitVector,
     CosineWeightedSample1,
@@ -54,13 +69,13 @@ pub enum SamplingFunctions {
 }
 
 #[allow(dead_code)]
-fn cosine_weighted_sample_1(normal: &Float3, rand_state: &mut RNGType) -> (Float3, Float0) {
+fn cosine_weighted_sample_1(normal: &Float3, sampler: &mut dyn Sampler) -> (Float3, Float0) {
     fn pdf(cos_theta: Float0) -> Float0 {
         cos_theta / PI as Float0
     }
     let (v, u) = generate_coordinate_system(normal);
-    let r1: Float0 = rand_state.random_range(0.0..1.0);
-    let r2: Float0 = rand_state.random_range(0.0..1.0);
+    let sample = sampler.get_2d();
+    let (r1, r2) = (sample.x(), sample.y());
 
     let phi = 2.0 * PI as Float0 * r1;
     let r = r2.sqrt();
@@ -77,13 +92,13 @@ fn cosine_weighted_sample_1(normal: &Float3, rand_state: &mut RNGType) -> (Float
 }
 
 #[allow(dead_code)]
-fn cosine_weighted_sample_2(normal: &Float3, rand_state: &mut RNGType) -> (Float3, Float0) {
+fn cosine_weighted_sample_2(normal: &Float3, sampler: &mut dyn Sampler) -> (Float3, Float0) {
     fn pdf(cos_theta: Float0) -> Float0 {
         cos_theta / PI as Float0
     }
     let (v, u) = generate_coordinate_system(normal);
-    let r1: Float0 = rand_state.random_range(0.0..1.0);
-    let r2: Float0 = rand_state.random_range(0.0..1.0);
+    let sample = sampler.get_2d();
+    let (r1, r2) = (sample.x(), sample.y());
     let cos_theta = r1.sqrt();
     let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
     let phi = r2 * 2.0 * PI as Float0;
@@ -114,16 +129,16 @@ impl Material {
     pub fn scatter(
         &self,
         hit_record: &HitRecord,
-        rand_state: &mut RNGType,
+        sampler: &mut dyn Sampler,
         sampletype: &SamplingFunctions,
     ) -> (Ray, Float0) {
         let mut random = match sampletype {
-            SamplingFunctions::RandomUnitVector => random_unit_vector(rand_state),
+            SamplingFunctions::RandomUnitVector => random_unit_vector(sampler),
             SamplingFunctions::CosineWeightedSample1 => {
-                cosine_weighted_sample_1(&hit_record.normal, rand_state)
+                cosine_weighted_sample_1(&hit_record.normal, sampler)
             }
             SamplingFunctions::CosineWeightedSample2 => {
-                cosine_weighted_sample_2(&hit_record.normal, rand_state)
+                cosine_weighted_sample_2(&hit_record.normal, sampler)
             }
         };
 
@@ -171,6 +186,10 @@ impl Material {
             albedo: Float3::new([1.0, 1.0, 1.0]),
             reflectivity: 1.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
@@ -179,6 +198,10 @@ impl Material {
             albedo: Float3::new([1.0, 0.0, 0.0]),
             reflectivity: 0.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
@@ -187,6 +210,10 @@ impl Material {
             albedo: Float3::new([0.0, 1.0, 0.0]),
             reflectivity: 0.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
@@ -195,6 +222,10 @@ impl Material {
             albedo: Float3::new([0.0, 0.0, 1.0]),
             reflectivity: 0.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
@@ -203,6 +234,10 @@ impl Material {
             albedo: Float3::new([1.0, 1.0, 1.0]),
             reflectivity: 0.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
@@ -211,6 +246,10 @@ impl Material {
             albedo: Float3::new([1.0, 1.0, 1.0]),
             reflectivity: 0.0,
             checkered: true,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
@@ -219,13 +258,51 @@ impl Material {
             albedo: Float3::new([0.0, 0.0, 0.0]),
             reflectivity: 0.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
+        }
+    }
+
+    // Clear glass; `albedo` tints the transmitted light.
+    pub fn dielectric(ior: Ior) -> Material {
+        Material {
+            albedo: Float3::new([1.0, 1.0, 1.0]),
+            reflectivity: 0.0,
+            checkered: false,
+            ior: Some(ior),
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 
+    pub fn interface() -> Material {
+        Material {
+            interface: true,
+            ..Material::dielectric(Ior::Constant(1.0))
+        }
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.interface
+    }
+
     pub(crate) fn try_from_toml(object: &toml::Value) -> Result<Material, String> {
+        if let Some(table) = object.as_table() {
+            return match table.get("type").and_then(toml::Value::as_str) {
+                Some("dielectric" | "glass") => Self::dielectric_from_toml(table),
+                Some("subsurface" | "sss") => Self::subsurface_from_toml(table),
+                Some("shadow_catcher") => Self::shadow_catcher_from_toml(table),
+                material_type => Err(format!(
+                    "material.type must be `dielectric`, `subsurface` or `shadow_catcher`, got {material_type:?}"
+                )),
+            };
+        }
         let material = object
             .as_str()
-            .ok_or_else(|| format!("material must be a string, got {object:?}"))?;
+            .ok_or_else(|| format!("material must be a string or table, got {object:?}"))?;
         let material = match material {
             "reflective" => Material::reflective(),
             "red" => Material::red(),
@@ -234,20 +311,134 @@ impl Material {
             "white" => Material::white(),
             "checkered" => Material::checkered(),
             "black" => Material::black(),
+            "glass" => Material::dielectric(Ior::CROWN_GLASS),
+            "flint" => Material::dielectric(Ior::FLINT_GLASS),
+            "diamond" => Material::dielectric(Ior::DIAMOND),
+            "shadow_catcher" => Material::shadow_catcher(),
+            "interface" => Material::interface(),
             _ => return Err(format!("unknown material `{material}`")),
         };
         Ok(material)
     }
 
+    // `{ type = "dielectric", ior = 1.5 }`, with `cauchy = [a, b]` or
+    // `sellmeier = { b = [..], c = [..] }` in place of a constant `ior`, and
+    // an optional `color` tint. `interface = true` makes it an invisible
+    // medium boundary instead, which can't bend light.
+    fn dielectric_from_toml(table: &toml::Table) -> Result<Material, String> {
+        let interface = match table.get("interface") {
+            Some(value) => value
+                .as_bool()
+                .ok_or_else(|| "material.interface must be a boolean".to_string())?,
+            None => false,
+        };
+        if interface {
+            let index_matched = table.keys().all(|key| {
+                matches!(key.as_str(), "type" | "interface")
+                    || (key == "ior" && table[key].as_float() == Some(1.0))
+            });
+            if !index_matched {
+                return Err("material.interface only allows `ior = 1.0` besides `type`".to_string());
+            }
+            return Ok(Material::interface());
+        }
+        let ior = if let Some(ior) = table.get("ior") {
+            Ior::Constant(number(ior, "material.ior")?)
+        } else if let Some(cauchy) = table.get("cauchy") {
+            let [a, b] = numbers(cauchy, "material.cauchy")?;
+            Ior::Cauchy { a, b }
+        } else if let Some(sellmeier) = table.get("sellmeier") {
+            let coefficients = |key: &str| {
+                let path = format!("material.sellmeier.{key}");
+                sellmeier
+                    .get(key)
+                    .ok_or_else(|| format!("{path} is required"))
+                    .and_then(|value| numbers(value, &path))
+            };
+            Ior::Sellmeier {
+                b: coefficients("b")?,
+                c: coefficients("c")?,
+            }
+        } else {
+            Ior::CROWN_GLASS
+        };
+        let mut material = Material::dielectric(ior);
+        if let Some(color) = table.get("color") {
+            material.albedo = Float3::new(numbers(color, "material.color")?);
+        }
+        Ok(material)
+    }
+
+    // `{ type = "subsurface", color = [..], mean_free_path = [..] }`, the
+    // surface colour and the distance light travels inside per channel.
+    fn subsurface_from_toml(table: &toml::Table) -> Result<Material, String> {
+        let mean_free_path = table
+            .get("mean_free_path")
+            .ok_or_else(|| "material.mean_free_path is required".to_string())
+            .and_then(|value| numbers(value, "material.mean_free_path"))?;
+        if mean_free_path.iter().any(|distance| *distance <= 0.0) {
+            return Err("material.mean_free_path must be positive".to_string());
+        }
+        let mut material = Material::white();
+        if let Some(color) = table.get("color") {
+            material.albedo = Float3::new(numbers(color, "material.color")?);
+        }
+        material.subsurface = Some(Subsurface {
+            mean_free_path: Float3::new(mean_free_path),
+        });
+        Ok(material)
+    }
+
+    // `{ type = "shadow_catcher", color = [..] }`; the colour only tints the
+    // light the catcher bounces onto other objects.
+    fn shadow_catcher_from_toml(table: &toml::Table) -> Result<Material, String> {
+        let mut material = Material::shadow_catcher();
+        if let Some(color) = table.get("color") {
+            material.albedo = Float3::new(numbers(color, "material.color")?);
+        }
+        Ok(material)
+    }
+
+    // A diffuse ground that stands in for the surface of a photograph.
+    pub fn shadow_catcher() -> Material {
+        Material {
+            shadow_catcher: true,
+            ..Material::white()
+        }
+    }
+
     pub fn from_color(color: crate::utils::vector::Float3) -> Material {
         Material {
             albedo: color,
             reflectivity: 0.0,
             checkered: false,
+            ior: None,
+            subsurface: None,
+            shadow_catcher: false,
+            interface: false,
         }
     }
 }
 
+fn number(value: &toml::Value, path: &str) -> Result<Float0, String> {
+    value
+        .as_float()
+        .or_else(|| value.as_integer().map(|integer| integer as Float0))
+        .ok_or_else(|| format!("{path} must be a number"))
+}
+
+fn numbers<const N: usize>(value: &toml::Value, path: &str) -> Result<[Float0; N], String> {
+    let values = value
+        .as_array()
+        .filter(|values| values.len() == N)
+        .ok_or_else(|| format!("{path} must be an array of {N} numbers"))?;
+    let mut out = [0.0; N];
+    for (index, value) in values.iter().enumerate() {
+        out[index] = number(value, &format!("{path}[{index}]"))?;
+    }
+    Ok(out)
+}
+
 impl Default for Material {
     fn default() -> Self {
         Material::white()
@@ -256,7 +447,7 @@ impl Default for Material {
 
 #[cfg(test)]
 mod tests {
-    use crate::pathtracer::get_rng;
+    use crate::{pathtracer::get_rng, sampler::independent::IndependentSampler};
 
     use super::*;
 
@@ -274,10 +465,10 @@ mod tests {
 
     #[test]
     fn test_cosine_weighted_sample_1_distribution() {
-        let mut rng = get_rng();
+        let mut sampler = IndependentSampler::from_rng(get_rng());
         let normal = Float3::new([0.0, 1.0, 0.0]);
         let samples: Vec<Float3> = (0..1000)
-            .map(|_| cosine_weighted_sample_1(&normal, &mut rng).0)
+            .map(|_| cosine_weighted_sample_1(&normal, &mut sampler).0)
             .collect();
 
         let average_cosine: Float0 =
@@ -292,10 +483,10 @@ mod tests {
 
     #[test]
     fn test_cosine_weighted_sample_2_distribution() {
-        let mut rng = get_rng();
+        let mut sampler = IndependentSampler::from_rng(get_rng());
         let normal = Float3::new([0.0, 1.0, 0.0]);
         let samples: Vec<Float3> = (0..1000)
-            .map(|_| cosine_weighted_sample_2(&normal, &mut rng).0)
+            .map(|_| cosine_weighted_sample_2(&normal, &mut sampler).0)
             .collect();
 
         let average_cosine: Float0 =
@@ -307,4 +498,62 @@ mod tests {
             "Average cosine: {average_cosine}"
         );
     }
+
+    #[test]
+    fn parses_dielectric_materials() {
+        let material = |toml: &str| {
+            let value: toml::Table = toml::from_str(&format!("material = {toml}")).unwrap();
+            Material::try_from_toml(&value["material"])
+        };
+
+        assert_eq!(material("\"flint\"").unwrap().ior, Some(Ior::FLINT_GLASS));
+        assert_eq!(
+            material("{ type = \"dielectric\", ior = 1.33 }")
+                .unwrap()
+                .ior,
+            Some(Ior::Constant(1.33))
+        );
+        let tinted =
+            material("{ type = \"dielectric\", cauchy = [1.5, 0.004], color = [1, 0.9, 0.8] }")
+                .unwrap();
+        assert_eq!(tinted.ior, Some(Ior::Cauchy { a: 1.5, b: 0.004 }));
+        assert_eq!(tinted.albedo.0, [1.0, 0.9, 0.8]);
+        let sellmeier = material(
+            "{ type = \"dielectric\", sellmeier = { b = [1.0, 0.2, 1.0], c = [0.006, 0.02, 100] } }",
+        )
+        .unwrap();
+        assert!(sellmeier.ior.unwrap().is_dispersive());
+        assert!(material("{ type = \"dielectric\", sellmeier = { b = [1.0] } }").is_err());
+
+        assert!(
+            !material("{ type = \"dielectric\", ior = 1.0 }")
+                .unwrap()
+                .is_interface()
+        );
+        assert!(
+            material("{ type = \"dielectric\", interface = true }")
+                .unwrap()
+                .is_interface()
+        );
+        assert!(material("\"interface\"").unwrap().is_interface());
+        assert!(material("{ type = \"dielectric\", ior = 1.5, interface = true }").is_err());
+    }
+
+    #[test]
+    fn parses_subsurface_materials() {
+        let material = |toml: &str| {
+            let value: toml::Table = toml::from_str(&format!("material = {toml}")).unwrap();
+            Material::try_from_toml(&value["material"])
+        };
+
+        let skin = material(
+            "{ type = \"subsurface\", color = [0.8, 0.5, 0.4], mean_free_path = [0.3, 0.1, 0.05] }",
+        )
+        .unwrap();
+        assert_eq!(skin.albedo.0, [0.8, 0.5, 0.4]);
+        assert_eq!(skin.subsurface.unwrap().mean_free_path.0, [0.3, 0.1, 0.05]);
+        assert!(material("{ type = \"sss\", mean_free_path = [0.1, 0.0, 0.1] }").is_err());
+        assert!(material("{ type = \"subsurface\" }").is_err());
+        assert!(material("{ type = \"metal\" }").is_err());
+    }
 }
@@ -0,0 +1,154 @@
+use crate::{
+    medium::{Medium, homogeneous::HomogeneousMedium},
+    object::{HitRecord, Hittable},
+    ray::Ray,
+    sampler::Sampler,
+    scene::Float0,
+    spectrum::SampledWavelengths,
+    utils::vector::Float3,
+};
+
+use super::cosine_weighted_sample_2;
+
+// Walks longer than this are treated as absorbed.
+const MAX_STEPS: usize = 256;
+
+// Random-walk subsurface scattering. Light enters the surface diffusely,
+// scatters isotropically through a homogeneous medium filling the object and
+// leaves diffusely where the walk next crosses the surface. `mean_free_path`
+// is the average distance between scattering events per channel, in scene
+// units; the scattering albedo is chosen so the surface reflects roughly the
+// material's colour overall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Subsurface {
+    pub mean_free_path: Float3,
+}
+
+// Where a walk came back out: the point, the outward normal and the weight
+// to multiply the path throughput with.
+#[derive(Debug, Clone, Copy)]
+pub struct SubsurfaceExit {
+    pub point: Float3,
+    pub normal: Float3,
+    pub weight: Float3,
+}
+
+impl Subsurface {
+    // Absorption and scattering coefficients for a surface colour `albedo`.
+    pub fn coefficients(&self, albedo: Float3) -> (Float3, Float3) {
+        let mut sigma_a = [0.0; 3];
+        let mut sigma_s = [0.0; 3];
+        for channel in 0..3 {
+            let sigma_t = self.mean_free_path.0[channel].max(1e-6).recip();
+            let single_scattering = single_scattering_albedo(albedo.0[channel]);
+            sigma_s[channel] = sigma_t * single_scattering;
+            sigma_a[channel] = sigma_t - sigma_s[channel];
+        }
+        (Float3::new(sigma_a), Float3::new(sigma_s))
+    }
+
+    // Walks from `entry`, a front-face hit on `object`, until the path leaves
+    // the object again. None when the walk is absorbed or escapes an object
+    // that isn't closed.
+    pub fn random_walk(
+        &self,
+        object: &dyn Hittable,
+        entry: &HitRecord,
+        albedo: Float3,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Option<SubsurfaceExit> {
+        let (sigma_a, sigma_s) = self.coefficients(albedo);
+        let medium = HomogeneousMedium::new(sigma_a, sigma_s, 0.0);
+
+        let (direction, _) = cosine_weighted_sample_2(&-entry.normal, sampler);
+        let mut ray = Ray::new(entry.point, direction);
+        let mut weight = Float3::new([1.0, 1.0, 1.0]);
+        for _ in 0..MAX_STEPS {
+            // A tighter epsilon than elsewhere, as mean free paths can be
+            // far shorter than the usual 0.001 offset.
+            let exit = object.hit(&ray, 1e-6, Float0::INFINITY)?;
+            let sample = medium.sample(&ray, exit.t, sampler, wavelengths);
+            weight *= sample.weight;
+            if weight.length_squared() == 0.0 {
+                return None;
+            }
+            match sample.scatter {
+                Some(t) => {
+                    let (next, _) = medium.phase().sample(&ray.direction, sampler.get_2d());
+                    ray = Ray::new(ray.at(t), next);
+                }
+                None => {
+                    let normal = if exit.front_face {
+                        exit.normal
+                    } else {
+                        -exit.normal
+                    };
+                    return Some(SubsurfaceExit {
+                        point: exit.point,
+                        normal,
+                        weight,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+// Inverts the multiple-scattering albedo of a semi-infinite medium with
+// isotropic scattering, fitted by Chiang et al., "Practical and Controllable
+// Subsurface Scattering for Production Path Tracing".
+fn single_scattering_albedo(albedo: Float0) -> Float0 {
+    let albedo = albedo.clamp(0.0, 1.0);
+    let root = (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+    let term = 4.09712 + 4.20863 * albedo - root;
+    (1.0 - term * term).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Material, object::sphere::Sphere, pathtracer::get_rng,
+        sampler::independent::IndependentSampler,
+    };
+
+    #[test]
+    fn walks_reflect_about_the_surface_colour() {
+        assert!(single_scattering_albedo(0.0) < 1e-5);
+        assert!((single_scattering_albedo(1.0) - 1.0).abs() < 1e-5);
+
+        // A sphere much larger than the mean free path behaves like a
+        // semi-infinite slab, which the albedo inversion is fitted to.
+        let sphere = Sphere::new(Float3::new([0.0, 0.0, 0.0]), 1.0, Material::white());
+        let subsurface = Subsurface {
+            mean_free_path: Float3::new([0.01, 0.01, 0.01]),
+        };
+        let ray = Ray::new(Float3::new([0.0, 0.0, 3.0]), Float3::new([0.0, 0.0, -1.0]));
+        let entry = sphere.hit(&ray, 0.001, Float0::INFINITY).unwrap();
+        let albedo = Float3::new([0.2, 0.5, 0.8]);
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+
+        let count = 20_000;
+        let mut reflected = Float3::new([0.0, 0.0, 0.0]);
+        let mut spread = 0.0;
+        for _ in 0..count {
+            if let Some(exit) = subsurface.random_walk(&sphere, &entry, albedo, &mut sampler, None)
+            {
+                assert!((exit.point.length() - 1.0).abs() < 1e-6);
+                assert!(exit.normal.dot(&exit.point) > 0.0);
+                reflected += exit.weight.scale(1.0 / count as Float0);
+                spread += (exit.point - entry.point).length() / count as Float0;
+            }
+        }
+        for channel in 0..3 {
+            assert!(
+                (reflected.0[channel] - albedo.0[channel]).abs() < 0.05,
+                "{reflected:?}"
+            );
+        }
+        // Light comes out a few mean free paths from where it went in.
+        assert!(spread > 0.01 && spread < 0.1, "{spread}");
+    }
+}
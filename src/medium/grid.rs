@@ -0,0 +1,205 @@
+use std::{fs, path::Path};
+
+use crate::{scene::Float0, utils::vector::Float3};
+
+// A scalar voxel grid, x varying fastest, sampled with trilinear
+// interpolation between voxel centres over the unit cube.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub resolution: [usize; 3],
+    values: Vec<f32>,
+    // World-space bounds stored in the file, when the format has them.
+    pub bounds: Option<(Float3, Float3)>,
+}
+
+impl DensityGrid {
+    pub fn new(resolution: [usize; 3], values: Vec<f32>) -> Result<Self, String> {
+        if resolution.contains(&0) {
+            return Err("grid resolution must not be zero".to_string());
+        }
+        let count = resolution.iter().product::<usize>();
+        if values.len() != count {
+            return Err(format!(
+                "grid has {} values, expected {count} for {}x{}x{}",
+                values.len(),
+                resolution[0],
+                resolution[1],
+                resolution[2]
+            ));
+        }
+        Ok(Self {
+            resolution,
+            values,
+            bounds: None,
+        })
+    }
+
+    // Mitsuba `.vol` files carry their own size and bounds; anything else is
+    // read as headerless little-endian 32-bit floats of the given resolution.
+    pub fn read(path: &Path, resolution: Option<[usize; 3]>) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        if extension.as_deref() == Some("nvdb") {
+            return Err(format!(
+                "{}: NanoVDB grids are not supported, convert them to .vol or raw floats",
+                path.display()
+            ));
+        }
+        let data = fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let grid = if data.starts_with(b"VOL") {
+            read_vol(&data)
+        } else {
+            let resolution = resolution
+                .ok_or_else(|| "raw grids need a `resolution = [x, y, z]`".to_string())?;
+            Self::new(resolution, floats(&data))
+        };
+        grid.map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn value(&self, x: usize, y: usize, z: usize) -> Float0 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(z * ny + y) * nx + x] as Float0
+    }
+
+    // `point` in [0, 1]^3; values outside the grid clamp to its faces.
+    pub fn lookup(&self, point: Float3) -> Float0 {
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let size = self.resolution[axis];
+            let voxel = (point.0[axis] * size as Float0 - 0.5).clamp(0.0, (size - 1) as Float0);
+            lower[axis] = voxel.floor() as usize;
+            upper[axis] = (lower[axis] + 1).min(size - 1);
+            fraction[axis] = voxel - lower[axis] as Float0;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                if corner >> axis & 1 == 1 {
+                    index[axis] = upper[axis];
+                    weight *= fraction[axis];
+                } else {
+                    index[axis] = lower[axis];
+                    weight *= 1.0 - fraction[axis];
+                }
+            }
+            if weight > 0.0 {
+                value += weight * self.value(index[0], index[1], index[2]);
+            }
+        }
+        value
+    }
+
+    // Largest voxel value that can influence lookups inside the box
+    // `[min, max]` of the unit cube.
+    pub fn max_in(&self, min: Float3, max: Float3) -> Float0 {
+        let range = |axis: usize| {
+            let size = self.resolution[axis] as Float0;
+            let last = self.resolution[axis] - 1;
+            let first = ((min.0[axis] * size - 0.5).floor().max(0.0) as usize).min(last);
+            let end = ((max.0[axis] * size - 0.5).ceil().max(0.0) as usize).min(last);
+            first..=end
+        };
+        let mut result = Float0::NEG_INFINITY;
+        for z in range(2) {
+            for y in range(1) {
+                for x in range(0) {
+                    result = result.max(self.value(x, y, z));
+                }
+            }
+        }
+        result
+    }
+}
+
+fn floats(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+// `VOL`, version byte 3, then little-endian i32 encoding (1 for float32),
+// resolution and channel count, six f32 bounds and the voxel data with the
+// channels interleaved. Only the first channel is kept.
+fn read_vol(data: &[u8]) -> Result<DensityGrid, String> {
+    let header = data
+        .get(..48)
+        .ok_or_else(|| "truncated VOL header".to_string())?;
+    if header[3] != 3 {
+        return Err(format!("unsupported VOL version {}", header[3]));
+    }
+    let integer = |offset: usize| {
+        i32::from_le_bytes([
+            header[offset],
+            header[offset + 1],
+            header[offset + 2],
+            header[offset + 3],
+        ])
+    };
+    if integer(4) != 1 {
+        return Err("only float32 VOL grids are supported".to_string());
+    }
+    let dimensions = [integer(8), integer(12), integer(16), integer(20)];
+    if dimensions.iter().any(|dimension| *dimension <= 0) {
+        return Err("invalid VOL dimensions".to_string());
+    }
+    let [nx, ny, nz, channels] = dimensions.map(|dimension| dimension as usize);
+    let bounds = floats(&header[24..48]);
+
+    let count = nx * ny * nz * channels;
+    let values = floats(
+        data.get(48..48 + count * 4)
+            .ok_or_else(|| "truncated VOL data".to_string())?,
+    )
+    .into_iter()
+    .step_by(channels)
+    .collect();
+
+    let mut grid = DensityGrid::new([nx, ny, nz], values)?;
+    let corner = |offset: usize| Float3::new([0, 1, 2].map(|axis| bounds[offset + axis] as Float0));
+    grid.bounds = Some((corner(0), corner(3)));
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_vol_files_and_interpolates() {
+        let mut data = b"VOL\x03".to_vec();
+        for value in [1i32, 1, 2, 1, 2] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [-1.0f32, 0.0, 0.0, 1.0, 1.0, 2.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        // Two channels per voxel; the second is ignored.
+        for value in [0.2f32, 9.0, 1.0, 9.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.vol", std::process::id()));
+        fs::write(&file, data).unwrap();
+        let grid = DensityGrid::read(&file, None).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(grid.resolution, [1, 2, 1]);
+        let (min, max) = grid.bounds.unwrap();
+        assert_eq!((min.0, max.0), ([-1.0, 0.0, 0.0], [1.0, 1.0, 2.0]));
+        let at = |y: Float0| grid.lookup(Float3::new([0.5, y, 0.5]));
+        assert!((at(0.1) - 0.2).abs() < 1e-6);
+        assert!((at(0.5) - 0.6).abs() < 1e-6);
+        assert!((at(0.75) - 1.0).abs() < 1e-6);
+        assert_eq!(
+            grid.max_in(Float3::new([0.0, 0.0, 0.0]), Float3::new([1.0, 0.2, 1.0])),
+            0.2_f32 as Float0
+        );
+        assert!(DensityGrid::read(Path::new("missing.raw"), None).is_err());
+    }
+}
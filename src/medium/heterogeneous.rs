@@ -0,0 +1,355 @@
+use crate::{
+    ray::Ray,
+    sampler::Sampler,
+    scene::Float0,
+    spectrum::{self, SampledWavelengths, Spectrum},
+    utils::vector::Float3,
+};
+
+use super::{Medium, MediumSample, grid::DensityGrid, phase::HenyeyGreenstein};
+
+// Cells per axis of the coarse grid of density maxima used as majorants.
+const MAJORANT_CELLS: usize = 16;
+
+// A density grid stretched over the box `[min, max]`. The coefficients are
+// per unit of density; fire emits `emission`, optionally scaled by a second
+// grid, wherever the medium absorbs.
+#[derive(Debug)]
+pub struct HeterogeneousMedium {
+    density: DensityGrid,
+    min: Float3,
+    max: Float3,
+    sigma_a: Float3,
+    sigma_s: Float3,
+    emission: Option<(Spectrum, Option<DensityGrid>)>,
+    phase: HenyeyGreenstein,
+    majorants: DensityGrid,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        density: DensityGrid,
+        (min, max): (Float3, Float3),
+        sigma_a: Float3,
+        sigma_s: Float3,
+        g: Float0,
+    ) -> Self {
+        let resolution = density.resolution.map(|size| size.min(MAJORANT_CELLS));
+        let mut maxima = Vec::with_capacity(resolution.iter().product());
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let cell = [x, y, z];
+                    let corner = |offset: usize| {
+                        Float3::new([0, 1, 2].map(|axis| {
+                            (cell[axis] + offset) as Float0 / resolution[axis] as Float0
+                        }))
+                    };
+                    maxima.push(density.max_in(corner(0), corner(1)).max(0.0) as f32);
+                }
+            }
+        }
+        Self {
+            majorants: DensityGrid::new(resolution, maxima)
+                .expect("majorant grid matches its resolution"),
+            density,
+            min,
+            max,
+            sigma_a,
+            sigma_s,
+            emission: None,
+            phase: HenyeyGreenstein::new(g),
+        }
+    }
+
+    pub fn with_emission(mut self, emission: Spectrum, scale: Option<DensityGrid>) -> Self {
+        self.emission = Some((emission, scale));
+        self
+    }
+
+    fn local(&self, point: Float3) -> Float3 {
+        Float3::new(
+            [0, 1, 2].map(|axis| {
+                (point.0[axis] - self.min.0[axis]) / (self.max.0[axis] - self.min.0[axis])
+            }),
+        )
+    }
+
+    fn coefficients(&self, wavelengths: Option<&SampledWavelengths>) -> (Float3, Float3) {
+        (
+            spectrum::uplift(self.sigma_a, wavelengths),
+            spectrum::uplift(self.sigma_s, wavelengths),
+        )
+    }
+
+    // Walks the majorant cells the ray crosses between `t_min` and `t_max`
+    // with a 3D DDA, calling `visit` with each segment and the largest
+    // density inside it until it returns false.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        t_min: Float0,
+        t_max: Float0,
+        mut visit: impl FnMut(Float0, Float0, Float0) -> bool,
+    ) {
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+        for axis in 0..3 {
+            let inverse = ray.direction.0[axis].recip();
+            let mut t0 = (self.min.0[axis] - ray.origin.0[axis]) * inverse;
+            let mut t1 = (self.max.0[axis] - ray.origin.0[axis]) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from a ray lying in a slab plane leaves the bounds alone.
+            t_enter = t_enter.max(t0);
+            t_exit = t_exit.min(t1);
+        }
+        if t_enter >= t_exit {
+            return;
+        }
+
+        let resolution = self.majorants.resolution;
+        let entry = self.local(ray.at(t_enter));
+        let mut cell = [0; 3];
+        let mut step = [0isize; 3];
+        let mut next_crossing = [Float0::INFINITY; 3];
+        let mut crossing_interval = [Float0::INFINITY; 3];
+        for axis in 0..3 {
+            let size = resolution[axis] as Float0;
+            let position = entry.0[axis] * size;
+            cell[axis] = (position.max(0.0) as usize).min(resolution[axis] - 1);
+            let speed = ray.direction.0[axis] / (self.max.0[axis] - self.min.0[axis]) * size;
+            if speed > 0.0 {
+                step[axis] = 1;
+                next_crossing[axis] = t_enter + ((cell[axis] + 1) as Float0 - position) / speed;
+                crossing_interval[axis] = speed.recip();
+            } else if speed < 0.0 {
+                step[axis] = -1;
+                next_crossing[axis] = t_enter + (cell[axis] as Float0 - position) / speed;
+                crossing_interval[axis] = -speed.recip();
+            }
+        }
+
+        let mut t = t_enter;
+        loop {
+            let axis = (0..3)
+                .min_by(|a, b| next_crossing[*a].total_cmp(&next_crossing[*b]))
+                .unwrap();
+            let end = next_crossing[axis].min(t_exit);
+            let majorant = self.majorants.value(cell[0], cell[1], cell[2]);
+            if end > t && !visit(t, end, majorant) {
+                return;
+            }
+            if end >= t_exit {
+                return;
+            }
+            t = end;
+            let next = cell[axis] as isize + step[axis];
+            if next < 0 || next >= resolution[axis] as isize {
+                return;
+            }
+            cell[axis] = next as usize;
+            next_crossing[axis] += crossing_interval[axis];
+        }
+    }
+}
+
+fn max_channel(values: Float3) -> Float0 {
+    values.0.iter().copied().fold(0.0, Float0::max)
+}
+
+fn average(values: Float3) -> Float0 {
+    values.0.iter().sum::<Float0>() / 3.0
+}
+
+impl Medium for HeterogeneousMedium {
+    // Delta tracking against the majorant grid. Tentative collisions are
+    // real scattering or null events chosen by their average probability
+    // over the channels and weighted by the ratio to each channel's, and
+    // every collision adds the emission the medium would absorb there.
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_max: Float0,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> MediumSample {
+        let (sigma_a, sigma_s) = self.coefficients(wavelengths);
+        let sigma_t_max = max_channel(sigma_a + sigma_s);
+        let emission = self
+            .emission
+            .as_ref()
+            .map(|(spectrum, scale)| (spectrum.evaluate(wavelengths), scale));
+        let speed = ray.direction.length();
+
+        let mut result = MediumSample {
+            scatter: None,
+            weight: Float3::new([1.0, 1.0, 1.0]),
+            emission: Float3::new([0.0, 0.0, 0.0]),
+        };
+        self.traverse(ray, 0.0, t_max, |start, end, majorant| {
+            let sigma_majorant = majorant * sigma_t_max;
+            if sigma_majorant <= 0.0 {
+                return true;
+            }
+            let mut t = start;
+            loop {
+                t -= (1.0 - sampler.get_1d()).ln() / (sigma_majorant * speed);
+                if t >= end {
+                    return true;
+                }
+                let point = ray.at(t);
+                let local = self.local(point);
+                let density = self.density.lookup(local);
+                let absorption = sigma_a.scale(density);
+                let scattering = sigma_s.scale(density);
+                let null = Float3::new([sigma_majorant; 3]) - absorption - scattering;
+
+                if let Some((radiance, scale)) = &emission {
+                    let scale = scale.as_ref().map_or(1.0, |scale| scale.lookup(local));
+                    result.emission +=
+                        result.weight * absorption * radiance.scale(scale / sigma_majorant);
+                }
+
+                let scatter_probability = average(scattering);
+                let null_probability = average(null);
+                let total = scatter_probability + null_probability;
+                if total <= 0.0 {
+                    result.weight = Float3::new([0.0, 0.0, 0.0]);
+                    return false;
+                }
+                if sampler.get_1d() * total < scatter_probability {
+                    result.weight *=
+                        scattering.scale(total / (sigma_majorant * scatter_probability));
+                    result.scatter = Some(t);
+                    return false;
+                }
+                result.weight *= null.scale(total / (sigma_majorant * null_probability));
+            }
+        });
+        result
+    }
+
+    // Ratio tracking: every tentative collision scales the transmittance by
+    // the probability of it being a null collision.
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        t_max: Float0,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Float3 {
+        let (sigma_a, sigma_s) = self.coefficients(wavelengths);
+        let sigma_t = sigma_a + sigma_s;
+        let sigma_t_max = max_channel(sigma_t);
+        let speed = ray.direction.length();
+
+        let mut transmittance = Float3::new([1.0, 1.0, 1.0]);
+        self.traverse(ray, 0.0, t_max, |start, end, majorant| {
+            let sigma_majorant = majorant * sigma_t_max;
+            if sigma_majorant <= 0.0 {
+                return true;
+            }
+            let mut t = start;
+            loop {
+                t -= (1.0 - sampler.get_1d()).ln() / (sigma_majorant * speed);
+                if t >= end {
+                    return true;
+                }
+                let density = self.density.lookup(self.local(ray.at(t)));
+                let null = Float3::new([sigma_majorant; 3]) - sigma_t.scale(density);
+                transmittance *= null.scale(sigma_majorant.recip());
+
+                // Russian roulette once the shadow ray is mostly blocked.
+                let remaining = max_channel(transmittance);
+                if remaining < 0.05 {
+                    if sampler.get_1d() < 0.5 {
+                        transmittance = Float3::new([0.0, 0.0, 0.0]);
+                        return false;
+                    }
+                    transmittance = transmittance.scale(2.0);
+                }
+            }
+        });
+        transmittance
+    }
+
+    fn phase(&self) -> &HenyeyGreenstein {
+        &self.phase
+    }
+
+    fn albedo(&self) -> Float3 {
+        let sigma_t = self.sigma_a + self.sigma_s;
+        Float3::new([0, 1, 2].map(|channel| {
+            if sigma_t.0[channel] > 0.0 {
+                self.sigma_s.0[channel] / sigma_t.0[channel]
+            } else {
+                0.0
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pathtracer::get_rng, sampler::independent::IndependentSampler};
+
+    // Two voxels along x, so the density ramps from 0.2 to 1.0 between their
+    // centres and integrates to 0.6 across the unit box.
+    fn ramp(sigma_a: Float3, sigma_s: Float3) -> HeterogeneousMedium {
+        HeterogeneousMedium::new(
+            DensityGrid::new([2, 1, 1], vec![0.2, 1.0]).unwrap(),
+            (Float3::new([0.0, 0.0, 0.0]), Float3::new([1.0, 1.0, 1.0])),
+            sigma_a,
+            sigma_s,
+            0.0,
+        )
+    }
+
+    #[test]
+    fn tracking_matches_the_optical_depth() {
+        let medium = ramp(Float3::new([0.5, 1.0, 0.0]), Float3::new([0.5, 1.0, 2.0]))
+            .with_emission(Spectrum::Rgb(Float3::new([2.0, 2.0, 2.0])), None);
+        // Starts outside the box and ends beyond it.
+        let ray = Ray::new(Float3::new([-1.0, 0.5, 0.5]), Float3::new([2.0, 0.0, 0.0]));
+        let t_max = 2.0;
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+
+        let count = 200_000;
+        let mut passed = Float3::new([0.0, 0.0, 0.0]);
+        let mut scattered = Float3::new([0.0, 0.0, 0.0]);
+        let mut emitted = Float3::new([0.0, 0.0, 0.0]);
+        let mut transmittance = Float3::new([0.0, 0.0, 0.0]);
+        for _ in 0..count {
+            let sample = medium.sample(&ray, t_max, &mut sampler, None);
+            emitted += sample.emission.scale(1.0 / count as Float0);
+            match sample.scatter {
+                Some(t) => {
+                    assert!((0.5..=1.0).contains(&t));
+                    scattered += sample.weight.scale(1.0 / count as Float0);
+                }
+                None => passed += sample.weight.scale(1.0 / count as Float0),
+            }
+            transmittance += medium
+                .transmittance(&ray, t_max, &mut sampler, None)
+                .scale(1.0 / count as Float0);
+        }
+
+        let sigma_a = [0.5, 1.0, 0.0];
+        let sigma_t: [Float0; 3] = [1.0, 2.0, 2.0];
+        for channel in 0..3 {
+            let expected = (-sigma_t[channel] * 0.6).exp();
+            assert!((passed.0[channel] - expected).abs() < 0.01, "{passed:?}");
+            assert!((transmittance.0[channel] - expected).abs() < 0.01);
+            // Scattering, absorption and pass-through share out all the light.
+            let absorbed = emitted.0[channel] / 2.0;
+            let scattered_fraction = scattered.0[channel];
+            assert!((expected + absorbed + scattered_fraction - 1.0).abs() < 0.015);
+            if sigma_a[channel] == 0.0 {
+                assert_eq!(emitted.0[channel], 0.0);
+            }
+        }
+    }
+}
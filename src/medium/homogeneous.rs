@@ -0,0 +1,155 @@
+use crate::{
+    ray::Ray,
+    sampler::Sampler,
+    scene::Float0,
+    spectrum::{self, SampledWavelengths},
+    utils::vector::Float3,
+};
+
+use super::{Medium, MediumSample, phase::HenyeyGreenstein};
+
+#[derive(Debug, Clone)]
+pub struct HomogeneousMedium {
+    sigma_a: Float3,
+    sigma_s: Float3,
+    phase: HenyeyGreenstein,
+}
+
+impl HomogeneousMedium {
+    pub fn new(sigma_a: Float3, sigma_s: Float3, g: Float0) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(g),
+        }
+    }
+
+    fn coefficients(&self, wavelengths: Option<&SampledWavelengths>) -> (Float3, Float3) {
+        let sigma_s = spectrum::uplift(self.sigma_s, wavelengths);
+        (
+            spectrum::uplift(self.sigma_a, wavelengths) + sigma_s,
+            sigma_s,
+        )
+    }
+}
+
+fn exp(values: Float3) -> Float3 {
+    Float3::new(values.0.map(Float0::exp))
+}
+
+impl Medium for HomogeneousMedium {
+    // Free-flight sampling: the distance is sampled from the transmittance of
+    // one randomly chosen channel and weighted by the pdf averaged over all of
+    // them, so media whose extinction differs per channel stay unbiased.
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_max: Float0,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> MediumSample {
+        let (sigma_t, sigma_s) = self.coefficients(wavelengths);
+        let speed = ray.direction.length();
+        let channel = ((sampler.get_1d() * 3.0) as usize).min(2);
+        let distance = -(1.0 - sampler.get_1d()).ln() / sigma_t.0[channel];
+        let t = (distance / speed).min(t_max);
+        let scattered = t < t_max;
+
+        let transmittance = exp(sigma_t.scale(-t * speed));
+        let density = if scattered {
+            sigma_t * transmittance
+        } else {
+            transmittance
+        };
+        let pdf = density.0.iter().sum::<Float0>() / 3.0;
+        if pdf <= 0.0 {
+            return MediumSample {
+                scatter: None,
+                weight: Float3::new([0.0, 0.0, 0.0]),
+                emission: Float3::new([0.0, 0.0, 0.0]),
+            };
+        }
+        MediumSample {
+            scatter: scattered.then_some(t),
+            weight: if scattered {
+                (transmittance * sigma_s).scale(pdf.recip())
+            } else {
+                transmittance.scale(pdf.recip())
+            },
+            emission: Float3::new([0.0, 0.0, 0.0]),
+        }
+    }
+
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        t_max: Float0,
+        _sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Float3 {
+        let (sigma_t, _) = self.coefficients(wavelengths);
+        exp(sigma_t.scale(-t_max * ray.direction.length()))
+    }
+
+    fn phase(&self) -> &HenyeyGreenstein {
+        &self.phase
+    }
+
+    fn albedo(&self) -> Float3 {
+        let sigma_t = self.sigma_a + self.sigma_s;
+        Float3::new([0, 1, 2].map(|channel| {
+            if sigma_t.0[channel] > 0.0 {
+                self.sigma_s.0[channel] / sigma_t.0[channel]
+            } else {
+                0.0
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pathtracer::get_rng, sampler::independent::IndependentSampler};
+
+    #[test]
+    fn free_flight_sampling_is_unbiased() {
+        // The expected pass-through weight is the transmittance, the expected
+        // scattering weight the single-scattering integral, per channel.
+        let medium = HomogeneousMedium::new(
+            Float3::new([0.1, 0.5, 0.0]),
+            Float3::new([0.4, 0.5, 1.5]),
+            0.0,
+        );
+        let ray = Ray::new(Float3::new([0.0, 0.0, 0.0]), Float3::new([0.0, 0.0, 1.0]));
+        let t_max = 2.0;
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+
+        let count = 200_000;
+        let mut passed = Float3::new([0.0, 0.0, 0.0]);
+        let mut scattered = Float3::new([0.0, 0.0, 0.0]);
+        for _ in 0..count {
+            let sample = medium.sample(&ray, t_max, &mut sampler, None);
+            match sample.scatter {
+                Some(_) => scattered += sample.weight.scale(1.0 / count as Float0),
+                None => passed += sample.weight.scale(1.0 / count as Float0),
+            }
+        }
+
+        let sigma_t = [0.5, 1.0, 1.5];
+        let sigma_s = [0.4, 0.5, 1.5];
+        for channel in 0..3 {
+            let transmittance = (-sigma_t[channel] * t_max).exp();
+            let expected_scattered = sigma_s[channel] / sigma_t[channel] * (1.0 - transmittance);
+            assert!((passed.0[channel] - transmittance).abs() < 0.01);
+            assert!((scattered.0[channel] - expected_scattered).abs() < 0.01);
+        }
+        assert_eq!(
+            medium
+                .transmittance(&ray, t_max, &mut sampler, None)
+                .0
+                .map(|value| (value * 1e6).round()),
+            sigma_t.map(|sigma: Float0| ((-sigma * t_max).exp() * 1e6).round())
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::str::FromStr;
+
+use crate::{
+    ray::Ray, sampler::Sampler, scene::Float0, spectrum::SampledWavelengths, utils::vector::Float3,
+};
+
+pub mod grid;
+pub mod heterogeneous;
+pub mod homogeneous;
+pub mod phase;
+
+use phase::HenyeyGreenstein;
+
+pub enum MediumType {
+    Homogeneous,
+    Heterogeneous,
+}
+
+impl FromStr for MediumType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<MediumType, ()> {
+        match s {
+            "homogeneous" => Ok(Self::Homogeneous),
+            "heterogeneous" | "grid" => Ok(Self::Heterogeneous),
+            _ => Err(()),
+        }
+    }
+}
+
+// Participating media fill the inside of closed objects or the whole scene.
+// Coefficients are per unit of distance; like every other colour value along
+// a path they are RGB or spectral samples at `wavelengths`.
+pub trait Medium: Sync + std::fmt::Debug {
+    // Samples where light travelling along `ray` next interacts with the
+    // medium before `t_max`.
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_max: Float0,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> MediumSample;
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        t_max: Float0,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Float3;
+    fn phase(&self) -> &HenyeyGreenstein;
+    // Single-scattering albedo in RGB, for the denoiser's albedo guide.
+    fn albedo(&self) -> Float3;
+}
+
+// `scatter` is the ray parameter of a real scattering event, None when the
+// ray made it to `t_max`. `weight` multiplies the path throughput either way.
+// `emission` is the light emitted along the sampled segment, relative to the
+// throughput before it.
+#[derive(Debug, Clone, Copy)]
+pub struct MediumSample {
+    pub scatter: Option<Float0>,
+    pub weight: Float3,
+    pub emission: Float3,
+}
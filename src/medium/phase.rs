@@ -0,0 +1,79 @@
+use crate::{
+    material::generate_coordinate_system,
+    scene::{Float0, PI},
+    utils::vector::{Float2, Float3},
+};
+
+// Henyey-Greenstein phase function. `g` is the mean cosine of the scattering
+// angle: positive values scatter forward, negative backward, zero uniformly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HenyeyGreenstein {
+    pub g: Float0,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: Float0) -> Self {
+        Self {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    // Density for scattering by an angle with the given cosine between the
+    // propagation directions before and after; it is also the sampling pdf.
+    pub fn evaluate(&self, cos_theta: Float0) -> Float0 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    // Samples the new propagation direction for light travelling along
+    // `direction`, returning it with its pdf.
+    pub fn sample(&self, direction: &Float3, u: Float2) -> (Float3, Float0) {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u.x()
+        } else {
+            let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.x());
+            (1.0 + g * g - ratio * ratio) / (2.0 * g)
+        }
+        .clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y();
+
+        let forward = direction.normalize();
+        let (tangent, bitangent) = generate_coordinate_system(&forward);
+        let sampled = forward.scale(cos_theta)
+            + tangent.scale(sin_theta * phi.cos())
+            + bitangent.scale(sin_theta * phi.sin());
+        (sampled, self.evaluate(cos_theta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_matches_the_mean_cosine() {
+        let direction = Float3::new([0.0, 0.0, -1.0]);
+        for g in [-0.6, 0.0, 0.3, 0.85] {
+            let phase = HenyeyGreenstein::new(g);
+            let steps = 256;
+            let mut mean_cosine = 0.0;
+            for i in 0..steps {
+                for j in 0..4 {
+                    let u = Float2::new([
+                        (i as Float0 + 0.5) / steps as Float0,
+                        (j as Float0 + 0.5) / 4.0,
+                    ]);
+                    let (sampled, pdf) = phase.sample(&direction, u);
+                    let cos_theta = sampled.dot(&direction);
+                    assert!((sampled.length() - 1.0).abs() < 1e-9);
+                    assert!((pdf - phase.evaluate(cos_theta)).abs() < 1e-6 * pdf.max(1.0));
+                    mean_cosine += cos_theta / (steps * 4) as Float0;
+                }
+            }
+            assert!((mean_cosine - g).abs() < 0.01, "g = {g}: {mean_cosine}");
+        }
+    }
+}
@@ -45,7 +45,7 @@ impl Bvh {
         ray: &Ray,
         t_min: Float0,
         t_max: Float0,
-    ) -> Option<HitRecord<'a>> {
+    ) -> Option<(usize, HitRecord<'a>)> {
         self.root
             .as_ref()
             .and_then(|root| root.hit(objects, ray, t_min, t_max))
@@ -98,7 +98,7 @@ impl BvhNode {
         ray: &Ray,
         t_min: Float0,
         t_max: Float0,
-    ) -> Option<HitRecord<'a>> {
+    ) -> Option<(usize, HitRecord<'a>)> {
         if !self.bbox().hit(ray, t_min, t_max) {
             return None;
         }
@@ -110,14 +110,14 @@ impl BvhNode {
                 for index in indices {
                     if let Some(record) = objects[*index].hit(ray, t_min, closest) {
                         closest = record.t;
-                        hit_record = Some(record);
+                        hit_record = Some((*index, record));
                     }
                 }
                 hit_record
             }
             Self::Branch { left, right, .. } => {
                 let left_hit = left.hit(objects, ray, t_min, t_max);
-                let closest = left_hit.as_ref().map_or(t_max, |hit| hit.t);
+                let closest = left_hit.as_ref().map_or(t_max, |(_, hit)| hit.t);
                 let right_hit = right.hit(objects, ray, t_min, closest);
                 right_hit.or(left_hit)
             }
@@ -22,6 +22,7 @@ pub enum ObjectType {
     Plane,
     Cube,
     TriangleMesh,
+    Volume,
 }
 
 impl FromStr for ObjectType {
@@ -34,11 +35,35 @@ impl FromStr for ObjectType {
             "plane" => Ok(Self::Plane),
             "cube" => Ok(Self::Cube),
             "mesh" | "triangle_mesh" => Ok(Self::TriangleMesh),
+            "volume" => Ok(Self::Volume),
             _ => Err(()),
         }
     }
 }
 
+// Which kinds of rays see an object. Everything sees everything by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility {
+    pub cast_shadows: bool,
+    // Whether lights can be blocked on their way to the object.
+    pub receive_shadows: bool,
+    pub camera: bool,
+    // Seen by the camera as a hole in the alpha channel, while still
+    // casting shadows and showing up in reflections.
+    pub holdout: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            cast_shadows: true,
+            receive_shadows: true,
+            camera: true,
+            holdout: false,
+        }
+    }
+}
+
 pub trait Hittable: Sync + std::fmt::Debug {
     fn hit(&self, ray: &Ray, t_min: Float0, t_max: Float0) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> Option<aabb::Aabb>;
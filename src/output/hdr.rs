@@ -0,0 +1,242 @@
+use std::{
+    fs,
+    io::{BufRead, Read},
+    path::Path,
+};
+
+use crate::{scene::Float0, utils::vector::Float3};
+
+use super::LinearImage;
+
+// Radiance RGBE: a shared 8-bit exponent per pixel, scanlines stored with the
+// "new" run-length encoding (each component run-length coded separately).
+// Negative values can't be represented and are clamped to zero. The scene
+// TOML goes into the header as one `SCENE=` line per TOML line.
+pub fn write_hdr(path: &Path, image: &LinearImage, scene: &str) -> Result<(), String> {
+    let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nSOFTWARE=pathtracer\n".to_vec();
+    for line in scene.lines() {
+        data.extend_from_slice(format!("SCENE={line}\n").as_bytes());
+    }
+    data.extend_from_slice(format!("\n-Y {} +X {}\n", image.height, image.width).as_bytes());
+
+    for row in image.pixels.chunks_exact(image.width.max(1)) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(|pixel| to_rgbe(*pixel)).collect();
+        if !(8..0x8000).contains(&image.width) {
+            // Run-length encoding is only defined for these widths.
+            data.extend(rgbe.iter().flatten());
+            continue;
+        }
+        data.extend_from_slice(&[2, 2, (image.width >> 8) as u8, image.width as u8]);
+        for component in 0..4 {
+            let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+            encode_run_length(&values, &mut data);
+        }
+    }
+
+    fs::write(path, data).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+pub fn read_hdr(path: &Path) -> Result<LinearImage, String> {
+    let data = fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let mut reader = data.as_slice();
+
+    let mut line = String::new();
+    let mut header = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(|err| err.to_string())?;
+        if read == 0 {
+            return Err("unexpected end of Radiance header".to_string());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        header.push(line.to_string());
+    }
+    if !header.first().is_some_and(|magic| magic.starts_with("#?")) {
+        return Err("not a Radiance HDR file".to_string());
+    }
+    if header
+        .iter()
+        .any(|line| line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe")
+    {
+        return Err("only RGBE Radiance files are supported".to_string());
+    }
+
+    line.clear();
+    reader.read_line(&mut line).map_err(|err| err.to_string())?;
+    let (height, width) = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|err| err.to_string())?,
+            width.parse::<usize>().map_err(|err| err.to_string())?,
+        ),
+        _ => return Err(format!("unsupported Radiance resolution `{}`", line.trim())),
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for _ in 0..height {
+        let mut rgbe = vec![[0u8; 4]; width];
+        let mut start = [0u8; 4];
+        reader
+            .read_exact(&mut start)
+            .map_err(|_| "truncated Radiance scanline".to_string())?;
+
+        if (8..0x8000).contains(&width)
+            && start[0] == 2
+            && start[1] == 2
+            && ((start[2] as usize) << 8 | start[3] as usize) == width
+        {
+            for component in 0..4 {
+                let values = decode_run_length(&mut reader, width)?;
+                for (pixel, value) in rgbe.iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+            }
+        } else {
+            rgbe[0] = start;
+            for pixel in rgbe.iter_mut().skip(1) {
+                reader
+                    .read_exact(pixel)
+                    .map_err(|_| "truncated Radiance scanline".to_string())?;
+            }
+        }
+        pixels.extend(rgbe.into_iter().map(from_rgbe));
+    }
+
+    Ok(LinearImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn to_rgbe(color: Float3) -> [u8; 4] {
+    let color = Float3::new([color.x().max(0.0), color.y().max(0.0), color.z().max(0.0)]);
+    let max = color.x().max(color.y()).max(color.z());
+    if max < 1e-32 || !max.is_finite() {
+        return [0, 0, 0, 0];
+    }
+    // max = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / (2.0 as Float0).powi(exponent);
+    let component = |value: Float0| (value * scale).min(255.0) as u8;
+    [
+        component(color.x()),
+        component(color.y()),
+        component(color.z()),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Float3 {
+    if rgbe[3] == 0 {
+        return Float3::new([0.0, 0.0, 0.0]);
+    }
+    let scale = (2.0 as Float0).powi(rgbe[3] as i32 - 128 - 8);
+    Float3::new([
+        (rgbe[0] as Float0 + 0.5) * scale,
+        (rgbe[1] as Float0 + 0.5) * scale,
+        (rgbe[2] as Float0 + 0.5) * scale,
+    ])
+}
+
+// Runs of equal bytes are stored as (128 + length, value), everything else as
+// (length, literal bytes...), both with at most 127 bytes per packet.
+fn encode_run_length(values: &[u8], data: &mut Vec<u8>) {
+    let mut index = 0;
+    while index < values.len() {
+        let run = values[index..]
+            .iter()
+            .take(127)
+            .take_while(|value| **value == values[index])
+            .count();
+        if run > 2 {
+            data.extend_from_slice(&[128 + run as u8, values[index]]);
+            index += run;
+            continue;
+        }
+
+        let mut end = index;
+        while end < values.len()
+            && end - index < 127
+            && !(end + 2 < values.len()
+                && values[end] == values[end + 1]
+                && values[end] == values[end + 2])
+        {
+            end += 1;
+        }
+        data.push((end - index) as u8);
+        data.extend_from_slice(&values[index..end]);
+        index = end;
+    }
+}
+
+fn decode_run_length(reader: &mut &[u8], width: usize) -> Result<Vec<u8>, String> {
+    let truncated = || "truncated Radiance scanline".to_string();
+    let mut values = Vec::with_capacity(width);
+    while values.len() < width {
+        let (&count, rest) = reader.split_first().ok_or_else(truncated)?;
+        *reader = rest;
+        if count > 128 {
+            let (&value, rest) = reader.split_first().ok_or_else(truncated)?;
+            *reader = rest;
+            values.extend(std::iter::repeat_n(value, count as usize - 128));
+        } else {
+            let count = count as usize;
+            if count == 0 || reader.len() < count {
+                return Err("corrupt Radiance scanline".to_string());
+            }
+            values.extend_from_slice(&reader[..count]);
+            *reader = &reader[count..];
+        }
+    }
+    if values.len() > width {
+        return Err("corrupt Radiance scanline".to_string());
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_within_rgbe_precision() {
+        let width = 40;
+        let pixels: Vec<Float3> = (0..width * 3)
+            .map(|index| {
+                // Long flat runs mixed with varying values exercise both packet kinds.
+                let value = if index % width < 20 {
+                    0.5
+                } else {
+                    index as Float0 * 0.37
+                };
+                Float3::new([value, value * 0.25, 1000.0 + value])
+            })
+            .collect();
+        let image = LinearImage {
+            width,
+            height: 3,
+            pixels,
+        };
+
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.hdr", std::process::id()));
+        write_hdr(&file, &image, "[camera]\n\nfov = 60\n").unwrap();
+        let header = fs::read(&file).unwrap();
+        let read = read_hdr(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert!(
+            header
+                .windows(b"SCENE=fov = 60\n".len())
+                .any(|line| line == b"SCENE=fov = 60\n")
+        );
+        assert_eq!((read.width, read.height), (image.width, image.height));
+        for (expected, actual) in image.pixels.iter().zip(&read.pixels) {
+            // Every component shares the largest one's exponent.
+            let tolerance = expected.x().max(expected.y()).max(expected.z()) / 128.0;
+            assert!((*expected - *actual).length() < tolerance);
+        }
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, AttributeValue, Compression, Encoding, FlatSamples, Image, Layer,
+    LayerAttributes, SmallVec, Text, Vec2, WritableImage, attribute::Chromaticities, f16,
+};
+use png::{BitDepth, ColorType, Encoder, text_metadata::ITXtChunk};
+
+use crate::{
+    color::ColorSpace,
+    film::{Channel, Film},
+    sampler::{hash, u32_to_unit},
+    scene::Float0,
+    utils::vector::Float3,
+};
+
+pub mod hdr;
+pub mod pfm;
+pub mod tonemap;
+
+pub use hdr::{read_hdr, write_hdr};
+pub use pfm::{read_pfm, write_pfm};
+use tonemap::DisplayTransform;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Float3>,
+}
+
+impl LinearImage {
+    pub fn read(path: &Path) -> Result<LinearImage, String> {
+        match ImageFormat::from_path(path) {
+            Some(ImageFormat::Hdr) => read_hdr(path),
+            Some(ImageFormat::Pfm) => read_pfm(path),
+            Some(ImageFormat::Exr) => read_exr(path),
+            _ => Err(format!(
+                "{}: only .exr, .hdr and .pfm images can be read",
+                path.display()
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl FromStr for ImageFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ImageFormat, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "exr" | "openexr" => Ok(Self::Exr),
+            "hdr" | "rgbe" | "radiance" => Ok(Self::Hdr),
+            "pfm" => Ok(Self::Pfm),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Exr => "exr",
+            Self::Hdr => "hdr",
+            Self::Pfm => "pfm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExrPrecision {
+    #[default]
+    Half,
+    Float,
+}
+
+impl FromStr for ExrPrecision {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ExrPrecision, ()> {
+        match s {
+            "half" | "f16" => Ok(Self::Half),
+            "float" | "f32" => Ok(Self::Float),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExrCompression {
+    None,
+    #[default]
+    Zip,
+    Piz,
+}
+
+impl FromStr for ExrCompression {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ExrCompression, ()> {
+        match s {
+            "none" | "uncompressed" => Ok(Self::None),
+            "zip" => Ok(Self::Zip),
+            "piz" => Ok(Self::Piz),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExrSettings {
+    pub precision: ExrPrecision,
+    pub compression: ExrCompression,
+    pub color_space: ColorSpace,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngBitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl FromStr for PngBitDepth {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<PngBitDepth, ()> {
+        match s {
+            "8" => Ok(Self::Eight),
+            "16" => Ok(Self::Sixteen),
+            _ => Err(()),
+        }
+    }
+}
+
+// How colour relates to alpha in a PNG. The format specifies straight
+// colour, but some compositors want it premultiplied like the film.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngAlpha {
+    #[default]
+    Straight,
+    Premultiplied,
+}
+
+impl FromStr for PngAlpha {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<PngAlpha, ()> {
+        match s {
+            "straight" | "unassociated" => Ok(Self::Straight),
+            "premultiplied" | "associated" => Ok(Self::Premultiplied),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngSettings {
+    pub display: DisplayTransform,
+    pub bit_depth: PngBitDepth,
+    pub dither: bool,
+    pub alpha: PngAlpha,
+}
+
+impl Default for PngSettings {
+    fn default() -> Self {
+        Self {
+            display: DisplayTransform::default(),
+            bit_depth: PngBitDepth::default(),
+            dither: true,
+            alpha: PngAlpha::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutputSettings {
+    pub png: PngSettings,
+    pub exr: ExrSettings,
+}
+
+impl OutputSettings {
+    // The space the film's colours are in: PNG converts out of it for
+    // display, EXR tags its chromaticities.
+    pub fn with_working_space(mut self, working_space: ColorSpace) -> Self {
+        self.png.display.working_space = working_space;
+        self.exr.color_space = working_space;
+        self
+    }
+}
+
+// Writes the given film channels and returns the files that were created.
+// EXR gets a single file with every channel as a named layer, the other
+// formats one file per channel (`<name>_<channel>.<ext>` next to the beauty).
+// PNG runs colour channels through the display transform and stores the
+// display mapping of data channels, HDR and PFM keep the raw values. Alpha
+// goes into the beauty PNG, with straight colour unless asked otherwise, and
+// is an `A` layer in EXR, premultiplied like the film.
+pub fn write_film(
+    path: &Path,
+    format: ImageFormat,
+    film: &Film,
+    channels: &[Channel],
+    settings: &OutputSettings,
+    scene: &str,
+) -> Result<Vec<PathBuf>, String> {
+    match format {
+        ImageFormat::Png => {
+            let alpha: Option<Vec<Float0>> = if channels.contains(&Channel::Alpha) {
+                Some(
+                    resolve(film, Channel::Alpha)?
+                        .iter()
+                        .map(|alpha| alpha.x().clamp(0.0, 1.0))
+                        .collect(),
+                )
+            } else {
+                None
+            };
+            channels
+                .iter()
+                .filter(|channel| **channel != Channel::Alpha)
+                .map(|channel| {
+                    let buffer = resolve(film, *channel)?;
+                    let alpha = alpha.as_deref().filter(|_| *channel == Channel::Beauty);
+                    let encoded: Vec<Float3> = if is_data(*channel) {
+                        channel.display(&buffer)
+                    } else {
+                        buffer
+                            .iter()
+                            .enumerate()
+                            .map(|(index, color)| match alpha {
+                                // Tone mapping applies to the straight colour.
+                                Some(alpha) if alpha[index] > 0.0 => {
+                                    let encoded = settings
+                                        .png
+                                        .display
+                                        .apply(color.scale(alpha[index].recip()));
+                                    match settings.png.alpha {
+                                        PngAlpha::Straight => encoded,
+                                        PngAlpha::Premultiplied => encoded.scale(alpha[index]),
+                                    }
+                                }
+                                Some(_) if settings.png.alpha == PngAlpha::Premultiplied => {
+                                    Float3::new([0.0, 0.0, 0.0])
+                                }
+                                _ => settings.png.display.apply(*color),
+                            })
+                            .collect()
+                    };
+                    let output_file = channel_path(path, *channel);
+                    write_png(
+                        &output_file,
+                        &LinearImage {
+                            width: film.width,
+                            height: film.height,
+                            pixels: encoded,
+                        },
+                        alpha,
+                        settings.png.bit_depth,
+                        // IDs and other data must keep their exact values.
+                        settings.png.dither && !is_data(*channel),
+                        scene,
+                    )?;
+                    Ok(output_file)
+                })
+                .collect()
+        }
+        ImageFormat::Exr => {
+            write_exr(path, film, channels, &settings.exr, scene)?;
+            Ok(vec![path.to_path_buf()])
+        }
+        ImageFormat::Hdr | ImageFormat::Pfm => channels
+            .iter()
+            .map(|channel| {
+                let image = LinearImage {
+                    width: film.width,
+                    height: film.height,
+                    pixels: resolve(film, *channel)?,
+                };
+                let output_file = channel_path(path, *channel);
+                if format == ImageFormat::Hdr {
+                    write_hdr(&output_file, &image, scene)?;
+                } else {
+                    write_pfm(&output_file, &image)?;
+                }
+                Ok(output_file)
+            })
+            .collect(),
+    }
+}
+
+pub fn channel_path(path: &Path, channel: Channel) -> PathBuf {
+    match channel {
+        Channel::Beauty => path.to_path_buf(),
+        _ => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            path.with_file_name(format!("{stem}_{channel}.{extension}"))
+        }
+    }
+}
+
+// Quantizes an already display-encoded [0, 1] image, with `alpha` as a
+// fourth channel when given. Dithering adds triangular noise of one
+// quantization step to the colour so smooth gradients don't band.
+pub fn write_png(
+    path: &Path,
+    image: &LinearImage,
+    alpha: Option<&[Float0]>,
+    bit_depth: PngBitDepth,
+    dither: bool,
+    scene: &str,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let mut encoder = Encoder::new(file, image.width as u32, image.height as u32);
+    encoder.set_color(if alpha.is_some() {
+        ColorType::Rgba
+    } else {
+        ColorType::Rgb
+    });
+    encoder.set_depth(match bit_depth {
+        PngBitDepth::Eight => BitDepth::Eight,
+        PngBitDepth::Sixteen => BitDepth::Sixteen,
+    });
+
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+
+    let max = match bit_depth {
+        PngBitDepth::Eight => u8::MAX as Float0,
+        PngBitDepth::Sixteen => u16::MAX as Float0,
+    };
+    let mut data = Vec::with_capacity(image.pixels.len() * 8);
+    let mut push = |value: Float0, noise: Float0| {
+        let level = (value * max + 0.5 + noise).floor().clamp(0.0, max) as u16;
+        match bit_depth {
+            PngBitDepth::Eight => data.push(level as u8),
+            PngBitDepth::Sixteen => data.extend_from_slice(&level.to_be_bytes()),
+        }
+    };
+    for (index, color) in image.pixels.iter().enumerate() {
+        for (component, value) in color.0.iter().enumerate() {
+            let noise = if dither {
+                let bits = hash(&[index as u64, component as u64]);
+                u32_to_unit(bits as u32) + u32_to_unit((bits >> 32) as u32) - 1.0
+            } else {
+                0.0
+            };
+            push(*value, noise);
+        }
+        if let Some(alpha) = alpha {
+            push(alpha[index], 0.0);
+        }
+    }
+    writer
+        .write_image_data(&data)
+        .map_err(|err| err.to_string())?;
+
+    writer
+        .write_text_chunk(&ITXtChunk::new("scene", scene))
+        .map_err(|err| err.to_string())
+}
+
+// Beauty is stored as plain R, G, B and alpha as A; every other channel
+// becomes a layer prefixed with its name (`albedo.R`, `normal.X`, `depth.Z`,
+// ...). Geometric data and IDs are always written as 32-bit floats, the
+// precision setting only applies to colour channels. The scene TOML is kept
+// in a `scene` header attribute and the working space in the standard
+// chromaticities attribute.
+pub fn write_exr(
+    path: &Path,
+    film: &Film,
+    channels: &[Channel],
+    settings: &ExrSettings,
+    scene: &str,
+) -> Result<(), String> {
+    let mut exr_channels = SmallVec::new();
+    for channel in channels {
+        let buffer = resolve(film, *channel)?;
+        for (component, name) in component_names(*channel).iter().enumerate() {
+            let name = match channel {
+                Channel::Beauty | Channel::Alpha => name.to_string(),
+                _ => format!("{channel}.{name}"),
+            };
+            let values = buffer.iter().map(|value| value.0[component] as f32);
+            let samples = if settings.precision == ExrPrecision::Half && !is_data(*channel) {
+                FlatSamples::F16(values.map(f16::from_f32).collect())
+            } else {
+                FlatSamples::F32(values.collect())
+            };
+            exr_channels.push(AnyChannel::new(name.as_str(), samples));
+        }
+    }
+
+    let encoding = Encoding {
+        compression: match settings.compression {
+            ExrCompression::None => Compression::Uncompressed,
+            ExrCompression::Zip => Compression::ZIP16,
+            ExrCompression::Piz => Compression::PIZ,
+        },
+        ..Encoding::default()
+    };
+    let mut attributes = LayerAttributes::default();
+    // OpenEXR string attributes are plain bytes, so UTF-8 survives unchanged.
+    attributes.other.insert(
+        Text::from("scene"),
+        AttributeValue::Text(Text::from_slice_unchecked(scene.as_bytes())),
+    );
+    let layer = Layer::new(
+        (film.width, film.height),
+        attributes,
+        encoding,
+        AnyChannels::sort(exr_channels),
+    );
+
+    let mut image = Image::from_layer(layer);
+    let [red, green, blue, white] = settings
+        .color_space
+        .chromaticities()
+        .map(|[x, y]| Vec2(x as f32, y as f32));
+    image.attributes.chromaticities = Some(Chromaticities {
+        red,
+        green,
+        blue,
+        white,
+    });
+
+    image
+        .write()
+        .to_file(path)
+        .map_err(|err| format!("{}: {err}", path.display()))
+}
+
+// The RGB channels of the first layer with them, such as our own beauty.
+pub fn read_exr(path: &Path) -> Result<LinearImage, String> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| LinearImage {
+            width: resolution.width(),
+            height: resolution.height(),
+            pixels: vec![Float3::new([0.0, 0.0, 0.0]); resolution.area()],
+        },
+        |image: &mut LinearImage, position, (r, g, b, _): (f32, f32, f32, f32)| {
+            image.pixels[position.y() * image.width + position.x()] =
+                Float3::new([r as Float0, g as Float0, b as Float0]);
+        },
+    )
+    .map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(image.layer_data.channel_data.pixels)
+}
+
+fn resolve(film: &Film, channel: Channel) -> Result<Vec<Float3>, String> {
+    film.resolve(channel)
+        .ok_or_else(|| format!("the film has no `{channel}` channel"))
+}
+
+fn component_names(channel: Channel) -> &'static [&'static str] {
+    match channel {
+        Channel::Normal | Channel::Position => &["X", "Y", "Z"],
+        Channel::Depth => &["Z"],
+        Channel::Alpha => &["A"],
+        Channel::ObjectId | Channel::MaterialId | Channel::SampleCount => &["V"],
+        _ => &["R", "G", "B"],
+    }
+}
+
+fn is_data(channel: Channel) -> bool {
+    matches!(
+        channel,
+        Channel::Normal
+            | Channel::Position
+            | Channel::Depth
+            | Channel::ObjectId
+            | Channel::MaterialId
+            | Channel::SampleCount
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use exr::prelude::{ReadChannels, ReadLayers, read};
+
+    use super::*;
+    use crate::{film::PathSample, filter::FilterMode};
+
+    #[test]
+    fn sixteen_bit_png_keeps_fine_gradients() {
+        let buffer: Vec<Float3> = (0..4)
+            .map(|x| {
+                let value = 0.5 + x as Float0 / 65535.0;
+                Float3::new([value, value, value])
+            })
+            .collect();
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.png", std::process::id()));
+        let image = LinearImage {
+            width: 4,
+            height: 1,
+            pixels: buffer,
+        };
+        write_png(&file, &image, None, PngBitDepth::Sixteen, false, "").unwrap();
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&file).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut data).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(info.bit_depth, BitDepth::Sixteen);
+        let levels: Vec<u16> = data[..info.buffer_size()]
+            .chunks_exact(6)
+            .map(|pixel| u16::from_be_bytes([pixel[0], pixel[1]]))
+            .collect();
+        assert_eq!(levels, [32768, 32769, 32770, 32771]);
+    }
+
+    #[test]
+    fn alpha_travels_with_the_beauty() {
+        let film = Film::new(
+            2,
+            1,
+            Default::default(),
+            FilterMode::Importance,
+            &[Channel::Alpha],
+        );
+        let mut path = PathSample::new(0);
+        path.color = Float3::new([0.25, 0.25, 0.25]);
+        path.alpha = 0.5;
+        film.add_sample(
+            1,
+            0,
+            crate::utils::vector::Float2::new([1.5, 0.5]),
+            1.0,
+            0,
+            &path,
+        );
+        let channels = [Channel::Beauty, Channel::Alpha];
+        let stem = std::env::temp_dir().join(format!("pathtracer-alpha-{}", std::process::id()));
+
+        let png = stem.with_extension("png");
+        let mut settings = OutputSettings::default();
+        settings.png.display.transfer = tonemap::TransferFunction::Linear;
+        settings.png.dither = false;
+        let files = write_film(&png, ImageFormat::Png, &film, &channels, &settings, "").unwrap();
+        assert_eq!(files, std::slice::from_ref(&png));
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&png).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut data).unwrap();
+        std::fs::remove_file(&png).unwrap();
+        assert_eq!(info.color_type, ColorType::Rgba);
+        // PNG wants straight colour, so the premultiplied film is divided out.
+        assert_eq!(data[..info.buffer_size()][4..], [128, 128, 128, 128]);
+        assert_eq!(data[3], 0);
+
+        settings.png.alpha = PngAlpha::Premultiplied;
+        write_film(&png, ImageFormat::Png, &film, &channels, &settings, "").unwrap();
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&png).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let info = reader.next_frame(&mut data).unwrap();
+        std::fs::remove_file(&png).unwrap();
+        assert_eq!(data[..info.buffer_size()][4..], [64, 64, 64, 128]);
+
+        let exr = stem.with_extension("exr");
+        write_film(&exr, ImageFormat::Exr, &film, &channels, &settings, "").unwrap();
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_file(&exr)
+            .unwrap();
+        std::fs::remove_file(&exr).unwrap();
+        let names: Vec<String> = image
+            .layer_data
+            .channel_data
+            .list
+            .iter()
+            .map(|channel| channel.name.to_string())
+            .collect();
+        assert_eq!(names, ["A", "B", "G", "R"]);
+    }
+
+    #[test]
+    fn picks_format_from_extension() {
+        assert_eq!(
+            ImageFormat::from_path(Path::new("out.EXR")),
+            Some(ImageFormat::Exr)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("renders/scene.png")),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("out.hdr")),
+            Some(ImageFormat::Hdr)
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("scene")), None);
+        assert_eq!(
+            channel_path(Path::new("renders/scene.exr"), Channel::Albedo),
+            Path::new("renders/scene_albedo.exr")
+        );
+    }
+
+    #[test]
+    fn exr_round_trips_layers_and_scene() {
+        let film = Film::new(
+            2,
+            1,
+            Default::default(),
+            FilterMode::Splat,
+            &[Channel::Depth],
+        );
+        let mut path = PathSample::new(0);
+        path.color = Float3::new([4.5, 0.25, 0.0]);
+        path.depth = 1234.5678;
+        film.add_sample(
+            1,
+            0,
+            crate::utils::vector::Float2::new([1.5, 0.5]),
+            1.0,
+            0,
+            &path,
+        );
+
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.exr", std::process::id()));
+        let scene = "[camera]\nname = \"caméra\"\n";
+        write_exr(
+            &file,
+            &film,
+            &[Channel::Beauty, Channel::Depth],
+            &ExrSettings {
+                precision: ExrPrecision::Half,
+                compression: ExrCompression::Piz,
+                color_space: ColorSpace::AcesCg,
+            },
+            scene,
+        )
+        .unwrap();
+
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_file(&file)
+            .unwrap();
+        let beauty = LinearImage::read(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(beauty.pixels[1].0, [4.5, 0.25, 0.0]);
+
+        let names: Vec<String> = image
+            .layer_data
+            .channel_data
+            .list
+            .iter()
+            .map(|channel| channel.name.to_string())
+            .collect();
+        assert_eq!(names, ["B", "G", "R", "depth.Z"]);
+
+        let sample = |name: &str| {
+            let channel = image
+                .layer_data
+                .channel_data
+                .list
+                .iter()
+                .find(|channel| channel.name.to_string() == name)
+                .unwrap();
+            match &channel.sample_data {
+                FlatSamples::F16(values) => values[1].to_f32(),
+                FlatSamples::F32(values) => values[1],
+                FlatSamples::U32(values) => values[1] as f32,
+            }
+        };
+        assert_eq!(sample("R"), 4.5);
+        assert_eq!(sample("G"), 0.25);
+        // Depth keeps full precision even in a half-float file.
+        assert_eq!(sample("depth.Z"), 1234.5678);
+
+        match image.layer_data.attributes.other.get(&Text::from("scene")) {
+            Some(AttributeValue::Text(text)) => assert_eq!(text.as_slice(), scene.as_bytes()),
+            other => panic!("unexpected scene attribute {other:?}"),
+        }
+        let chromaticities = image.attributes.chromaticities.unwrap();
+        assert_eq!(chromaticities.white, Vec2(0.32168, 0.33767));
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fs, path::Path};
+
+use crate::{scene::Float0, utils::vector::Float3};
+
+use super::LinearImage;
+
+// Portable float map: a text header followed by raw 32-bit floats, rows
+// stored bottom to top. A negative scale in the header marks little-endian
+// data, which is what we write.
+pub fn write_pfm(path: &Path, image: &LinearImage) -> Result<(), String> {
+    let mut data = format!("PF\n{} {}\n-1.0\n", image.width, image.height).into_bytes();
+    for row in image.pixels.chunks_exact(image.width.max(1)).rev() {
+        for pixel in row {
+            for value in pixel.0 {
+                data.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+    }
+    fs::write(path, data).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+// Reads colour (`PF`) and greyscale (`Pf`) maps in either byte order.
+pub fn read_pfm(path: &Path) -> Result<LinearImage, String> {
+    let data = fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+
+    // The header is four whitespace separated tokens followed by a single
+    // whitespace byte.
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    while tokens.len() < 4 {
+        while data.get(offset).is_some_and(u8::is_ascii_whitespace) {
+            offset += 1;
+        }
+        let start = offset;
+        while data
+            .get(offset)
+            .is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            offset += 1;
+        }
+        if start == offset {
+            return Err("truncated PFM header".to_string());
+        }
+        tokens.push(String::from_utf8_lossy(&data[start..offset]).into_owned());
+    }
+    offset += 1;
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err("not a PFM file".to_string()),
+    };
+    let parse = |token: &str| {
+        token
+            .parse::<usize>()
+            .map_err(|_| format!("invalid PFM size `{token}`"))
+    };
+    let (width, height) = (parse(&tokens[1])?, parse(&tokens[2])?);
+    let scale: Float0 = tokens[3]
+        .parse()
+        .map_err(|_| format!("invalid PFM scale `{}`", tokens[3]))?;
+
+    let floats = data
+        .get(offset..offset + width * height * channels * 4)
+        .ok_or_else(|| "truncated PFM data".to_string())?
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if scale < 0.0 {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            }
+        })
+        .map(|value| value as Float0 * scale.abs())
+        .collect::<Vec<Float0>>();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in floats.chunks_exact((width * channels).max(1)).rev() {
+        pixels.extend(row.chunks_exact(channels).map(|pixel| match pixel {
+            [r, g, b] => Float3::new([*r, *g, *b]),
+            [value] => Float3::new([*value, *value, *value]),
+            _ => unreachable!(),
+        }));
+    }
+
+    Ok(LinearImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_flips_rows() {
+        let image = LinearImage {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Float3::new([1.0, -2.0, 3.5]),
+                Float3::new([0.0, 0.0, 0.0]),
+                Float3::new([0.25, 100.0, 1e-3]),
+                Float3::new([7.0, 8.0, 9.0]),
+            ],
+        };
+
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.pfm", std::process::id()));
+        write_pfm(&file, &image).unwrap();
+        let data = fs::read(&file).unwrap();
+        let read = read_pfm(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        // The bottom row comes first in the file.
+        let first = f32::from_le_bytes(data[12..16].try_into().unwrap());
+        assert_eq!(first, 0.25);
+        assert_eq!((read.width, read.height), (2, 2));
+        for (expected, actual) in image.pixels.iter().zip(&read.pixels) {
+            assert!((*expected - *actual).length() < 1e-6);
+        }
+    }
+}
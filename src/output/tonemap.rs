@@ -0,0 +1,283 @@
+use std::str::FromStr;
+
+use crate::{
+    color::ColorSpace,
+    scene::Float0,
+    utils::{
+        matrix::{Float3x3, Matrix},
+        vector::Float3,
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToneMapper {
+    #[default]
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    AgX,
+}
+
+impl FromStr for ToneMapper {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ToneMapper, ()> {
+        match s {
+            "clamp" | "none" => Ok(Self::Clamp),
+            "reinhard" => Ok(Self::Reinhard),
+            "extended_reinhard" | "reinhard_extended" => Ok(Self::ExtendedReinhard),
+            "aces" => Ok(Self::Aces),
+            "agx" => Ok(Self::AgX),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferFunction {
+    #[default]
+    Srgb,
+    Rec709,
+    Linear,
+}
+
+impl FromStr for TransferFunction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<TransferFunction, ()> {
+        match s {
+            "srgb" => Ok(Self::Srgb),
+            "rec709" | "bt709" => Ok(Self::Rec709),
+            "linear" => Ok(Self::Linear),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TransferFunction {
+    pub fn encode(&self, value: Float0) -> Float0 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Srgb if value <= 0.003_130_8 => 12.92 * value,
+            Self::Srgb => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+            Self::Rec709 if value < 0.018 => 4.5 * value,
+            Self::Rec709 => 1.099 * value.powf(0.45) - 0.099,
+            Self::Linear => value,
+        }
+    }
+
+    pub fn decode(&self, value: Float0) -> Float0 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Srgb if value <= 0.040_45 => value / 12.92,
+            Self::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Self::Rec709 if value < 0.081 => value / 4.5,
+            Self::Rec709 => ((value + 0.099) / 1.099).powf(1.0 / 0.45),
+            Self::Linear => value,
+        }
+    }
+}
+
+// Scene-linear to display-encoded colour: conversion from the working space
+// to the display primaries, exposure in stops, a tone mapping operator and
+// the display transfer function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    pub working_space: ColorSpace,
+    pub display_space: ColorSpace,
+    pub exposure: Float0,
+    pub tone_mapper: ToneMapper,
+    pub white_point: Float0,
+    pub transfer: TransferFunction,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            working_space: ColorSpace::default(),
+            display_space: ColorSpace::LinearSrgb,
+            exposure: 0.0,
+            tone_mapper: ToneMapper::default(),
+            white_point: 4.0,
+            transfer: TransferFunction::default(),
+        }
+    }
+}
+
+impl DisplayTransform {
+    pub fn apply(&self, color: Float3) -> Float3 {
+        let color = self.working_space.convert(color, self.display_space);
+        let color = color.scale((2.0 as Float0).powf(self.exposure));
+        let weights = || Float3::new(self.display_space.rgb_to_xyz()[1]);
+        let mapped = match self.tone_mapper {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => reinhard(color, Float0::INFINITY, weights()),
+            ToneMapper::ExtendedReinhard => reinhard(color, self.white_point, weights()),
+            ToneMapper::Aces => aces(color),
+            ToneMapper::AgX => agx(color),
+        };
+        Float3::new([
+            self.transfer.encode(mapped.x()),
+            self.transfer.encode(mapped.y()),
+            self.transfer.encode(mapped.z()),
+        ])
+    }
+}
+
+// Reinhard et al. on luminance so hues are kept; the extended form maps
+// `white` to 1 instead of only approaching it at infinity. `weights` is the
+// Y row of the display space's RGB to XYZ matrix.
+fn reinhard(color: Float3, white: Float0, weights: Float3) -> Float3 {
+    let luminance_in = weights.dot(&color);
+    if luminance_in <= 0.0 {
+        return Float3::new([0.0, 0.0, 0.0]);
+    }
+    let luminance_out =
+        luminance_in * (1.0 + luminance_in / (white * white)) / (1.0 + luminance_in);
+    color.scale(luminance_out / luminance_in)
+}
+
+// Stephen Hill's fit of the ACES reference rendering and sRGB output
+// transforms, including the conversions to and from the ACES working space.
+fn aces(color: Float3) -> Float3 {
+    const INPUT: Float3x3 = Matrix([
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ]);
+    const OUTPUT: Float3x3 = Matrix([
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ]);
+    let fit = |v: Float0| {
+        let a = v * (v + 0.024_578_6) - 0.000_090_537;
+        let b = v * (0.983_729 * v + 0.432_951) + 0.238_081;
+        a / b
+    };
+
+    let color = INPUT.multiply_by_vector(&color);
+    let color = Float3::new([fit(color.x()), fit(color.y()), fit(color.z())]);
+    OUTPUT.multiply_by_vector(&color)
+}
+
+// Troy Sobotka's AgX with the polynomial contrast curve from Benjamin
+// Wrensch's minimal implementation: inset into a log2 encoding, sigmoid,
+// then outset back to linear so the transfer function still applies.
+fn agx(color: Float3) -> Float3 {
+    const INSET: Float3x3 = Matrix([
+        [
+            0.842_479_062_253_094,
+            0.078_433_599_999_999_2,
+            0.079_223_745_147_764_3,
+        ],
+        [
+            0.042_328_242_261_012_3,
+            0.878_468_636_469_772,
+            0.079_166_127_460_543_4,
+        ],
+        [0.042_375_654_905_705_1, 0.078_433_6, 0.879_142_973_793_104],
+    ]);
+    const OUTSET: Float3x3 = Matrix([
+        [
+            1.196_879_005_120_17,
+            -0.098_020_881_140_136_8,
+            -0.099_029_744_079_720_5,
+        ],
+        [
+            -0.052_896_851_757_456_2,
+            1.151_903_129_904_17,
+            -0.098_961_176_844_843_3,
+        ],
+        [
+            -0.052_971_635_514_443_8,
+            -0.098_043_450_117_124_1,
+            1.151_073_672_641_16,
+        ],
+    ]);
+    const MIN_EV: Float0 = -12.473_93;
+    const MAX_EV: Float0 = 4.026_069;
+
+    let curve = |v: Float0| {
+        let v = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let v2 = v * v;
+        let v4 = v2 * v2;
+        15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v
+            - 0.00232
+    };
+
+    let color = INSET.multiply_by_vector(&color);
+    let color = Float3::new([curve(color.x()), curve(color.y()), curve(color.z())]);
+    let color = OUTSET.multiply_by_vector(&color);
+    Float3::new([
+        color.x().max(0.0).powf(2.2),
+        color.y().max(0.0).powf(2.2),
+        color.z().max(0.0).powf(2.2),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_functions_round_trip() {
+        for transfer in [TransferFunction::Srgb, TransferFunction::Rec709] {
+            for value in [0.0, 0.001, 0.018, 0.2, 0.5, 1.0] {
+                let encoded = transfer.encode(value);
+                assert!((transfer.decode(encoded) - value).abs() < 1e-9);
+            }
+        }
+        assert!((TransferFunction::Srgb.encode(0.5) - 0.735_356_983).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tone_mappers_stay_in_range_and_are_monotonic() {
+        for tone_mapper in [
+            ToneMapper::Reinhard,
+            ToneMapper::ExtendedReinhard,
+            ToneMapper::Aces,
+            ToneMapper::AgX,
+        ] {
+            let transform = DisplayTransform {
+                tone_mapper,
+                ..Default::default()
+            };
+            let mut previous = -1.0;
+            for step in 0..64 {
+                let value = (2.0 as Float0).powf(step as Float0 / 4.0 - 8.0);
+                let mapped = transform.apply(Float3::new([value, value, value]));
+                assert!((0.0..=1.0).contains(&mapped.x()), "{tone_mapper:?}");
+                assert!(mapped.x() >= previous, "{tone_mapper:?} is not monotonic");
+                previous = mapped.x();
+            }
+        }
+
+        let extended = DisplayTransform {
+            tone_mapper: ToneMapper::ExtendedReinhard,
+            white_point: 4.0,
+            transfer: TransferFunction::Linear,
+            ..Default::default()
+        };
+        assert!((extended.apply(Float3::new([4.0, 4.0, 4.0])).x() - 1.0).abs() < 1e-9);
+
+        // White is measured by the display primaries' luminance.
+        let weights = Float3::new(ColorSpace::Rec2020.rgb_to_xyz()[1]);
+        let green = Float3::new([0.0, 4.0 / weights.y(), 0.0]);
+        assert!((weights.dot(&reinhard(green, 4.0, weights)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn converts_working_space_to_display_primaries() {
+        let transform = DisplayTransform {
+            working_space: ColorSpace::AcesCg,
+            transfer: TransferFunction::Linear,
+            ..Default::default()
+        };
+        let red = Float3::new([1.0, 0.0, 0.0]);
+        let acescg_red = ColorSpace::LinearSrgb.convert(red, ColorSpace::AcesCg);
+        let displayed = transform.apply(acescg_red);
+        assert!((displayed - red).length() < 1e-9);
+    }
+}
@@ -1,14 +1,29 @@
-use crate::material::SamplingFunctions;
+use crate::denoise::{self, Guides};
+use crate::film::{Channel, Film};
+use crate::filter::{Filter, FilterMode};
+use crate::integrator::{IntegratorType, RenderSettings, mlt::MetropolisSettings};
+use crate::sampler::SamplerType;
 use crate::scene::{Float0, RNGType, Scene};
 use crate::utils::vector::Float3;
 use rand::{RngExt, SeedableRng};
-use rayon::prelude::*;
 
 pub struct PathTracer {
     pub width: usize,
     pub height: usize,
     samples: usize,
     seed: Option<u64>,
+    sampler: SamplerType,
+    filter: Filter,
+    filter_mode: FilterMode,
+    aovs: Vec<Channel>,
+    light_aovs: bool,
+    denoise: bool,
+    prefilter_aux: bool,
+    spectral: bool,
+    integrator: IntegratorType,
+    photons: Option<usize>,
+    photon_radius: Option<Float0>,
+    metropolis: MetropolisSettings,
 }
 
 impl PathTracer {
@@ -26,85 +41,146 @@ impl PathTracer {
             height,
             samples,
             seed,
+            sampler: SamplerType::default(),
+            filter: Filter::default(),
+            filter_mode: FilterMode::default(),
+            aovs: Vec::new(),
+            light_aovs: false,
+            denoise: true,
+            prefilter_aux: false,
+            spectral: false,
+            integrator: IntegratorType::default(),
+            photons: None,
+            photon_radius: None,
+            metropolis: MetropolisSettings::default(),
         }
     }
 
+    pub fn with_sampler(mut self, sampler: SamplerType) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter, filter_mode: FilterMode) -> Self {
+        self.filter = filter;
+        self.filter_mode = filter_mode;
+        self
+    }
+
+    pub fn with_aovs(mut self, aovs: Vec<Channel>, light_aovs: bool) -> Self {
+        self.aovs = aovs;
+        self.light_aovs = light_aovs;
+        self
+    }
+
+    pub fn with_denoiser(mut self, denoise: bool, prefilter_aux: bool) -> Self {
+        self.denoise = denoise;
+        self.prefilter_aux = prefilter_aux;
+        self
+    }
+
+    // Traces hero wavelengths instead of RGB, for dispersion and blackbody
+    // emitters; the film converts the estimates back to the working space.
+    pub fn with_spectral(mut self, spectral: bool) -> Self {
+        self.spectral = spectral;
+        self
+    }
+
+    pub fn with_integrator(mut self, integrator: IntegratorType) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    // Photons per iteration and initial gather radius for photon mapping.
+    pub fn with_photons(mut self, photons: Option<usize>, radius: Option<Float0>) -> Self {
+        self.photons = photons;
+        self.photon_radius = radius;
+        self
+    }
+
+    pub fn with_metropolis(mut self, metropolis: MetropolisSettings) -> Self {
+        self.metropolis = metropolis;
+        self
+    }
+
+    // Alpha is on the film only when asked for or needed by the scene.
+    pub fn is_output(&self, channel: Channel) -> bool {
+        matches!(channel, Channel::Beauty | Channel::Alpha)
+            || self.aovs.contains(&channel)
+            || (self.light_aovs && matches!(channel, Channel::Light(_)))
+    }
+
+    // Whether the chosen integrator renders `scene` as the path tracer would.
+    pub fn supports(&self, scene: &Scene) -> Result<(), String> {
+        self.integrator.build().supports(scene)
+    }
+
     pub fn trace(&self, scene: &Scene, debug: bool) -> Vec<Float3> {
-        let mut buffer = vec![Float3::new([0.0, 0.0, 0.0]); self.width * self.height];
-
-        buffer
-            .par_iter_mut()
-            .enumerate()
-            .for_each(|(index, pixel)| {
-                let mut rand_state = self.rng_for_pixel(index);
-
-                let x = index % self.width;
-                let y = index / self.width;
-
-                let mut color = Float3::new([0.0, 0.0, 0.0]);
-
-                for _sample in 0..self.samples {
-                    let ray = scene.camera.get_ray(
-                        x as Float0,
-                        y as Float0,
-                        self.width as Float0,
-                        self.height as Float0,
-                        &mut rand_state,
-                    );
-                    let is_left = x < self.width / 2;
-
-                    let sample_type = if debug {
-                        if is_left {
-                            SamplingFunctions::RandomUnitVector
-                        } else {
-                            SamplingFunctions::CosineWeightedSample2
-                        }
-                    } else {
-                        SamplingFunctions::CosineWeightedSample1
-                    };
-                    color += scene.trace_ray(&ray, 10, &mut rand_state, &sample_type);
-                }
-
-                *pixel = color.scale(1.0 / self.samples as Float0);
-            });
-
-        #[cfg(feature = "oidn")]
-        self::denoise_image(self.width, self.height, &mut buffer);
-
-        buffer
-    }
-
-    fn rng_for_pixel(&self, index: usize) -> RNGType {
-        match self.seed {
-            Some(seed) => seeded_rng(seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)),
-            None => get_rng(),
-        }
+        self.render(scene, debug).beauty()
     }
-}
 
-#[cfg(feature = "oidn")]
-fn denoise_image(width: usize, height: usize, buffer: &mut [Float3]) {
-    let mut binding: Vec<f32> = buffer
-        .iter()
-        .flat_map(|v| v.as_array().to_vec())
-        .map(|x| x as f32)
-        .collect();
-
-    let input: &mut [f32] = binding.as_mut_slice();
-
-    let device = oidn::Device::cpu();
-    oidn::RayTracing::new(&device)
-        .hdr(true)
-        .srgb(false)
-        .image_dimensions(width, height)
-        .filter_in_place(input)
-        .expect("Filter config error!");
-
-    for (i, pixel) in buffer.iter_mut().enumerate() {
-        let start = i * 3;
-        let end = start + 3;
-        let slice = &input[start..end];
-        *pixel = Float3::new([slice[0] as Float0, slice[1] as Float0, slice[2] as Float0]);
+    pub fn render(&self, scene: &Scene, debug: bool) -> Film {
+        let mut channels = self.aovs.clone();
+        if self.light_aovs {
+            channels.extend((0..scene.lights.len()).map(Channel::Light));
+        }
+        if scene.needs_alpha() {
+            channels.push(Channel::Alpha);
+        }
+        if self.denoise {
+            channels.extend([
+                Channel::Raw,
+                Channel::Albedo,
+                Channel::Normal,
+                Channel::Depth,
+            ]);
+        }
+        let film = Film::new(
+            self.width,
+            self.height,
+            self.filter.clone(),
+            self.filter_mode,
+            &channels,
+        )
+        .with_splat_scale(1.0 / self.samples as Float0);
+        let settings = RenderSettings {
+            width: self.width,
+            height: self.height,
+            samples: self.samples,
+            seed: self.seed.unwrap_or_else(|| get_rng().random()),
+            sampler: self.sampler,
+            spectral: self.spectral,
+            debug,
+            max_depth: 10,
+            photons: self.photons,
+            photon_radius: self.photon_radius,
+            metropolis: self.metropolis,
+        };
+        self.integrator.build().render(scene, &film, &settings);
+
+        if self.denoise {
+            let mut buffer = film.beauty();
+            let resolve = |channel| film.resolve(channel).expect("denoising records guides");
+            let (albedo, normal, depth) = (
+                resolve(Channel::Albedo),
+                resolve(Channel::Normal),
+                resolve(Channel::Depth),
+            );
+            denoise::denoise(
+                self.width,
+                self.height,
+                &mut buffer,
+                &Guides {
+                    albedo: &albedo,
+                    normal: &normal,
+                    depth: &depth,
+                },
+                self.prefilter_aux,
+            );
+            film.set(Channel::Beauty, &buffer);
+        }
+
+        film
     }
 }
 
@@ -0,0 +1,97 @@
+use crate::{scene::Float0, utils::vector::Float2};
+
+use super::{Sampler, hash, u64_to_unit};
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+#[derive(Debug)]
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u64,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Every pixel walks the same sequence; a per-pixel Cranley-Patterson
+    // rotation decorrelates neighbours without breaking stratification.
+    fn sample_dimension(&self, dimension: usize) -> Float0 {
+        let rotation = u64_to_unit(hash(&[self.pixel, dimension as u64, self.seed]));
+        let value = match PRIMES.get(dimension) {
+            Some(base) => radical_inverse(*base, self.sample_index),
+            None => u64_to_unit(hash(&[
+                self.pixel,
+                self.sample_index,
+                dimension as u64,
+                self.seed,
+            ])),
+        };
+        let rotated = value + rotation;
+        if rotated >= 1.0 {
+            rotated - 1.0
+        } else {
+            rotated
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample_index: usize) {
+        self.pixel = hash(&[x as u64, y as u64]);
+        self.sample_index = sample_index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float0 {
+        let sample = self.sample_dimension(self.dimension);
+        self.dimension += 1;
+        sample
+    }
+
+    fn get_2d(&mut self) -> Float2 {
+        let sample = Float2::new([
+            self.sample_dimension(self.dimension),
+            self.sample_dimension(self.dimension + 1),
+        ]);
+        self.dimension += 2;
+        sample
+    }
+}
+
+fn radical_inverse(base: u64, mut index: u64) -> Float0 {
+    let inverse_base = 1.0 / base as Float0;
+    let mut reversed = 0;
+    let mut inverse_base_power = 1.0;
+    while index > 0 {
+        let next = index / base;
+        let digit = index - next * base;
+        reversed = reversed * base + digit;
+        inverse_base_power *= inverse_base;
+        index = next;
+    }
+    (reversed as Float0 * inverse_base_power).min(1.0 - Float0::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radical_inverse_mirrors_digits() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-12);
+    }
+}
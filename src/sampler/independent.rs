@@ -0,0 +1,63 @@
+use rand::RngExt;
+
+use crate::{
+    pathtracer::seeded_rng,
+    scene::{Float0, RNGType},
+    utils::vector::Float2,
+};
+
+use super::{Sampler, hash};
+
+#[derive(Debug)]
+pub struct IndependentSampler {
+    width: usize,
+    seed: u64,
+    rng: RNGType,
+    // The sample that follows on from the stream as it stands.
+    next_sample: usize,
+}
+
+impl IndependentSampler {
+    pub fn new(width: usize, seed: u64) -> Self {
+        Self {
+            width,
+            seed,
+            rng: seeded_rng(seed),
+            next_sample: 0,
+        }
+    }
+
+    pub fn from_rng(rng: RNGType) -> Self {
+        Self {
+            width: 0,
+            seed: 0,
+            rng,
+            next_sample: 0,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    // A pixel's samples continue one stream. A sample taken out of turn,
+    // as progressive integrators do, starts a stream of its own.
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample_index: usize) {
+        let index = (y * self.width + x) as u64;
+        if sample_index == 0 {
+            self.rng = seeded_rng(self.seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        } else if sample_index != self.next_sample {
+            self.rng = seeded_rng(hash(&[self.seed, index, sample_index as u64]));
+        }
+        self.next_sample = sample_index + 1;
+    }
+
+    fn get_1d(&mut self) -> Float0 {
+        self.rng.random_range(0.0..1.0)
+    }
+
+    fn get_2d(&mut self) -> Float2 {
+        Float2::new([
+            self.rng.random_range(0.0..1.0),
+            self.rng.random_range(0.0..1.0),
+        ])
+    }
+}
@@ -0,0 +1,173 @@
+use rand::RngExt;
+
+use crate::{
+    pathtracer::seeded_rng,
+    scene::{Float0, PI, RNGType},
+    utils::vector::Float2,
+};
+
+use super::Sampler;
+
+// Primary sample space Metropolis after Kelemen et al., as in pbrt-v3: the
+// samples handed out are the coordinates of a point in the unit hypercube,
+// and every iteration mutates the point, either drawing it anew (a large
+// step) or nudging each coordinate it uses. Coordinates are mutated lazily
+// when first asked for, and a rejected mutation restores them, so the
+// previous path can be traced again.
+#[derive(Debug)]
+pub struct MetropolisSampler {
+    rng: RNGType,
+    // Standard deviation of a small step, per coordinate.
+    sigma: Float0,
+    large_step_probability: Float0,
+    samples: Vec<PrimarySample>,
+    dimension: usize,
+    iteration: u64,
+    last_large_step: u64,
+    large_step: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PrimarySample {
+    value: Float0,
+    // The iteration that last changed the value, so that small steps skipped
+    // while it wasn't used can be caught up on at once.
+    last_modified: u64,
+    backup: Float0,
+    backup_modified: u64,
+}
+
+impl MetropolisSampler {
+    // The first iteration is a large step, so samplers with the same seed
+    // start from the same point.
+    pub fn new(seed: u64, sigma: Float0, large_step_probability: Float0) -> Self {
+        Self {
+            rng: seeded_rng(seed),
+            sigma,
+            large_step_probability,
+            samples: Vec::new(),
+            dimension: 0,
+            iteration: 0,
+            last_large_step: 0,
+            large_step: true,
+        }
+    }
+
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.random_range(0.0..1.0) < self.large_step_probability;
+        self.dimension = 0;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    // Puts back every coordinate this iteration mutated.
+    pub fn reject(&mut self) {
+        for sample in self
+            .samples
+            .iter_mut()
+            .filter(|sample| sample.last_modified == self.iteration)
+        {
+            sample.value = sample.backup;
+            sample.last_modified = sample.backup_modified;
+        }
+        self.iteration -= 1;
+    }
+
+    fn next_sample(&mut self) -> Float0 {
+        let index = self.dimension;
+        self.dimension += 1;
+        while self.samples.len() <= index {
+            // A coordinate no path has used yet is as good drawn now as by
+            // any earlier step.
+            let value = self.rng.random_range(0.0..1.0);
+            self.samples.push(PrimarySample {
+                value,
+                last_modified: self.iteration,
+                backup: value,
+                backup_modified: self.iteration,
+            });
+        }
+        let mut sample = self.samples[index];
+
+        // Drawn anew by a large step accepted since it was last used.
+        if sample.last_modified < self.last_large_step {
+            sample.value = self.rng.random_range(0.0..1.0);
+            sample.last_modified = self.last_large_step;
+        }
+        sample.backup = sample.value;
+        sample.backup_modified = sample.last_modified;
+        if self.large_step {
+            sample.value = self.rng.random_range(0.0..1.0);
+        } else {
+            let steps = (self.iteration - sample.last_modified) as Float0;
+            let offset = self.normal() * self.sigma * steps.sqrt();
+            sample.value += offset;
+            sample.value -= sample.value.floor();
+        }
+        sample.last_modified = self.iteration;
+
+        self.samples[index] = sample;
+        sample.value
+    }
+
+    // A standard normal variate, by the Box-Muller transform.
+    fn normal(&mut self) -> Float0 {
+        let u1: Float0 = 1.0 - self.rng.random_range(0.0..1.0);
+        let u2: Float0 = self.rng.random_range(0.0..1.0);
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+impl Sampler for MetropolisSampler {
+    // The pixel comes out of the samples instead, so this only goes back to
+    // the first coordinate.
+    fn start_pixel_sample(&mut self, _x: usize, _y: usize, _sample_index: usize) {
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float0 {
+        self.next_sample()
+    }
+
+    fn get_2d(&mut self) -> Float2 {
+        Float2::new([self.next_sample(), self.next_sample()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(sampler: &mut MetropolisSampler, count: usize) -> Vec<Float0> {
+        (0..count).map(|_| sampler.get_1d()).collect()
+    }
+
+    fn point(sampler: &MetropolisSampler) -> Vec<Float0> {
+        sampler.samples.iter().map(|sample| sample.value).collect()
+    }
+
+    #[test]
+    fn rejected_mutations_restore_the_previous_point() {
+        let mut sampler = MetropolisSampler::new(3, 0.01, 0.3);
+        let start = draw(&mut sampler, 6);
+        assert_eq!(start, draw(&mut MetropolisSampler::new(3, 0.01, 0.3), 6));
+
+        for _ in 0..20 {
+            sampler.start_iteration();
+            let mutated = draw(&mut sampler, 6);
+            assert!(mutated.iter().all(|value| (0.0..1.0).contains(value)));
+            assert_ne!(mutated, start);
+            sampler.reject();
+            assert_eq!(point(&sampler), start);
+        }
+        sampler.start_iteration();
+        let accepted = draw(&mut sampler, 6);
+        sampler.accept();
+        assert_eq!(point(&sampler), accepted);
+    }
+}
@@ -0,0 +1,148 @@
+use std::str::FromStr;
+
+use crate::{scene::Float0, utils::vector::Float2};
+
+pub mod halton;
+pub mod independent;
+pub mod metropolis;
+pub mod sobol;
+pub mod stratified;
+
+use halton::HaltonSampler;
+use independent::IndependentSampler;
+use sobol::{SobolSampler, ZSobolSampler};
+use stratified::StratifiedSampler;
+
+pub trait Sampler: std::fmt::Debug {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample_index: usize);
+    fn get_1d(&mut self) -> Float0;
+    fn get_2d(&mut self) -> Float2;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplerType {
+    Independent,
+    Stratified,
+    Halton,
+    #[default]
+    Sobol,
+    ZSobol,
+}
+
+impl FromStr for SamplerType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<SamplerType, ()> {
+        match s {
+            "independent" | "random" => Ok(Self::Independent),
+            "stratified" | "jittered" => Ok(Self::Stratified),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            "zsobol" | "blue_noise" => Ok(Self::ZSobol),
+            _ => Err(()),
+        }
+    }
+}
+
+impl SamplerType {
+    pub fn build(
+        &self,
+        samples_per_pixel: usize,
+        width: usize,
+        height: usize,
+        seed: u64,
+    ) -> Box<dyn Sampler> {
+        match self {
+            Self::Independent => Box::new(IndependentSampler::new(width, seed)),
+            Self::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            Self::Halton => Box::new(HaltonSampler::new(seed)),
+            Self::Sobol => Box::new(SobolSampler::new(seed)),
+            Self::ZSobol => Box::new(ZSobolSampler::new(samples_per_pixel, width, height, seed)),
+        }
+    }
+}
+
+pub(crate) fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |acc, value| {
+        mix_bits(acc ^ value.wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+pub(crate) fn u32_to_unit(value: u32) -> Float0 {
+    value as Float0 * (1.0 / 4_294_967_296.0)
+}
+
+pub(crate) fn u64_to_unit(value: u64) -> Float0 {
+    (value >> 11) as Float0 * (1.0 / 9_007_199_254_740_992.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn occupied_strata(sampler_type: SamplerType, samples: usize, grid: usize) -> usize {
+        let mut sampler = sampler_type.build(samples, 8, 8, 7);
+        let mut strata = vec![false; grid * grid];
+        for sample_index in 0..samples {
+            sampler.start_pixel_sample(3, 5, sample_index);
+            let u = sampler.get_2d();
+            assert!((0.0..1.0).contains(&u.x()) && (0.0..1.0).contains(&u.y()));
+            let cell_x = (u.x() * grid as Float0) as usize;
+            let cell_y = (u.y() * grid as Float0) as usize;
+            strata[cell_y * grid + cell_x] = true;
+        }
+        strata.into_iter().filter(|occupied| *occupied).count()
+    }
+
+    #[test]
+    fn parses_sampler_names() {
+        assert_eq!("sobol".parse(), Ok(SamplerType::Sobol));
+        assert_eq!("blue_noise".parse(), Ok(SamplerType::ZSobol));
+        assert_eq!("jittered".parse(), Ok(SamplerType::Stratified));
+        assert!("nope".parse::<SamplerType>().is_err());
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_fill_every_stratum() {
+        for sampler_type in [
+            SamplerType::Stratified,
+            SamplerType::Sobol,
+            SamplerType::ZSobol,
+        ] {
+            assert_eq!(
+                occupied_strata(sampler_type, 16, 4),
+                16,
+                "{sampler_type:?} left a 4x4 stratum empty"
+            );
+        }
+    }
+
+    #[test]
+    fn samplers_repeat_for_the_same_seed() {
+        for sampler_type in [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+            SamplerType::ZSobol,
+        ] {
+            let mut a = sampler_type.build(4, 8, 8, 42);
+            let mut b = sampler_type.build(4, 8, 8, 42);
+            a.start_pixel_sample(1, 2, 3);
+            b.start_pixel_sample(1, 2, 3);
+            for _ in 0..8 {
+                assert_eq!(a.get_1d(), b.get_1d());
+                assert_eq!(a.get_2d(), b.get_2d());
+            }
+        }
+    }
+}
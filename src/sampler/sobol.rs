@@ -0,0 +1,248 @@
+use crate::{scene::Float0, utils::vector::Float2};
+
+use super::{Sampler, hash, mix_bits, u32_to_unit};
+
+// Burley, "Practical Hash-based Owen Scrambling": the first two Sobol
+// dimensions, Owen-scrambled and index-shuffled with a different seed for every
+// dimension pair, padded out to as many dimensions as the path needs.
+#[derive(Debug)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn dimension_seed(&self) -> u64 {
+        hash(&[self.pixel, self.dimension, self.seed])
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample_index: usize) {
+        self.pixel = hash(&[x as u64, y as u64]);
+        self.sample_index = sample_index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float0 {
+        let seed = self.dimension_seed();
+        let index = nested_uniform_scramble(self.sample_index, seed as u32);
+        self.dimension += 1;
+        u32_to_unit(nested_uniform_scramble(
+            sobol_dimension_0(u64::from(index)),
+            (seed >> 32) as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> Float2 {
+        let seed = self.dimension_seed();
+        let index = nested_uniform_scramble(self.sample_index, seed as u32);
+        let scramble = mix_bits(seed);
+        self.dimension += 2;
+        Float2::new([
+            u32_to_unit(nested_uniform_scramble(
+                sobol_dimension_0(u64::from(index)),
+                scramble as u32,
+            )),
+            u32_to_unit(nested_uniform_scramble(
+                sobol_dimension_1(u64::from(index)),
+                (scramble >> 32) as u32,
+            )),
+        ])
+    }
+}
+
+// Ahmed and Wonka, "Screen-Space Blue-Noise Diffusion of Monte Carlo Sampling
+// Error via Hierarchical Ordering of Pixels": pixels are walked in Morton order
+// and the base-4 digits of the sample index are shuffled, so the error of
+// neighbouring pixels is decorrelated like blue noise.
+#[derive(Debug)]
+pub struct ZSobolSampler {
+    log2_samples_per_pixel: u32,
+    base4_digits: u32,
+    seed: u64,
+    morton_index: u64,
+    dimension: u64,
+}
+
+impl ZSobolSampler {
+    pub fn new(samples_per_pixel: usize, width: usize, height: usize, seed: u64) -> Self {
+        let log2_samples_per_pixel = samples_per_pixel.max(1).next_power_of_two().ilog2();
+        let log2_resolution = width.max(height).max(1).next_power_of_two().ilog2();
+        Self {
+            log2_samples_per_pixel,
+            base4_digits: log2_resolution + log2_samples_per_pixel.div_ceil(2),
+            seed,
+            morton_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Like the Morton index it shuffles, this takes more than 32 bits at high
+    // resolutions and sample counts.
+    fn sample_index(&self) -> u64 {
+        const PERMUTATIONS: [[u64; 4]; 24] = [
+            [0, 1, 2, 3],
+            [0, 1, 3, 2],
+            [0, 2, 1, 3],
+            [0, 2, 3, 1],
+            [0, 3, 2, 1],
+            [0, 3, 1, 2],
+            [1, 0, 2, 3],
+            [1, 0, 3, 2],
+            [1, 2, 0, 3],
+            [1, 2, 3, 0],
+            [1, 3, 2, 0],
+            [1, 3, 0, 2],
+            [2, 1, 0, 3],
+            [2, 1, 3, 0],
+            [2, 0, 1, 3],
+            [2, 0, 3, 1],
+            [2, 3, 0, 1],
+            [2, 3, 1, 0],
+            [3, 1, 2, 0],
+            [3, 1, 0, 2],
+            [3, 2, 1, 0],
+            [3, 2, 0, 1],
+            [3, 0, 2, 1],
+            [3, 0, 1, 2],
+        ];
+
+        let odd_power = self.log2_samples_per_pixel & 1 == 1;
+        let last_digit = u32::from(odd_power);
+        let dimension_mask = 0x5555_5555_u64.wrapping_mul(self.dimension);
+        let mut sample_index = 0;
+
+        for i in (last_digit..self.base4_digits).rev() {
+            let digit_shift = 2 * i - last_digit;
+            let digit = (self.morton_index >> digit_shift) & 3;
+            let higher_digits = self.morton_index >> (digit_shift + 2);
+            let permutation = (mix_bits(higher_digits ^ dimension_mask) >> 24) % 24;
+            sample_index |= PERMUTATIONS[permutation as usize][digit as usize] << digit_shift;
+        }
+
+        if odd_power {
+            let digit = self.morton_index & 1;
+            sample_index |= digit ^ (mix_bits((self.morton_index >> 1) ^ dimension_mask) & 1);
+        }
+
+        sample_index
+    }
+}
+
+impl Sampler for ZSobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample_index: usize) {
+        self.morton_index = (encode_morton_2(x as u32, y as u32) << self.log2_samples_per_pixel)
+            | sample_index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float0 {
+        let index = self.sample_index();
+        let seed = hash(&[self.dimension, self.seed]);
+        self.dimension += 1;
+        u32_to_unit(nested_uniform_scramble(
+            sobol_dimension_0(index),
+            seed as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> Float2 {
+        let index = self.sample_index();
+        let seed = hash(&[self.dimension, self.seed]);
+        self.dimension += 2;
+        Float2::new([
+            u32_to_unit(nested_uniform_scramble(
+                sobol_dimension_0(index),
+                seed as u32,
+            )),
+            u32_to_unit(nested_uniform_scramble(
+                sobol_dimension_1(index),
+                (seed >> 32) as u32,
+            )),
+        ])
+    }
+}
+
+// The first two Sobol dimensions to 32 bits of precision. Index bits past
+// the 32nd still move the points of the second dimension.
+fn sobol_dimension_0(index: u64) -> u32 {
+    (index.reverse_bits() >> 32) as u32
+}
+
+fn sobol_dimension_1(mut index: u64) -> u32 {
+    let mut direction: u64 = 1 << 63;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    (result >> 32) as u32
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn encode_morton_2(x: u32, y: u32) -> u64 {
+    fn spread(v: u32) -> u64 {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        v = (v | (v << 1)) & 0x5555_5555_5555_5555;
+        v
+    }
+    (spread(y) << 1) | spread(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unscrambled_sobol_matches_reference_points() {
+        let points: Vec<(u32, u32)> = (0..4)
+            .map(|i| (sobol_dimension_0(i) >> 30, sobol_dimension_1(i) >> 30))
+            .collect();
+        assert_eq!(points, vec![(0, 0), (2, 2), (1, 3), (3, 1)]);
+        assert_eq!(sobol_dimension_1(1 << 32), 1 << 31);
+    }
+
+    #[test]
+    fn zsobol_keeps_every_bit_of_large_indices() {
+        let mut sampler = ZSobolSampler::new(512, 4096, 4096, 0);
+        sampler.start_pixel_sample(0, 2048, 0);
+        assert!(sampler.sample_index() > u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn morton_interleaves_coordinates() {
+        assert_eq!(encode_morton_2(0b11, 0), 0b0101);
+        assert_eq!(encode_morton_2(0, 0b11), 0b1010);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{scene::Float0, utils::vector::Float2};
+
+use super::{Sampler, hash, u64_to_unit};
+
+#[derive(Debug)]
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    x_strata: usize,
+    y_strata: usize,
+    seed: u64,
+    pixel: u64,
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (samples_per_pixel as Float0).sqrt().ceil() as usize;
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        Self {
+            samples_per_pixel,
+            x_strata,
+            y_strata,
+            seed,
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn stratum(&self, count: usize) -> usize {
+        let permutation_seed = hash(&[self.pixel, self.dimension, self.seed]);
+        permutation_element(
+            (self.sample_index % count) as u32,
+            count as u32,
+            permutation_seed as u32,
+        ) as usize
+    }
+
+    fn jitter(&self, axis: u64) -> Float0 {
+        u64_to_unit(hash(&[
+            self.pixel,
+            self.sample_index as u64,
+            self.dimension,
+            axis,
+            self.seed,
+        ]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, sample_index: usize) {
+        self.pixel = hash(&[x as u64, y as u64]);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float0 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        let sample = (stratum as Float0 + self.jitter(0)) / self.samples_per_pixel as Float0;
+        self.dimension += 1;
+        sample
+    }
+
+    fn get_2d(&mut self) -> Float2 {
+        let stratum = self.stratum(self.x_strata * self.y_strata);
+        let (stratum_x, stratum_y) = (stratum % self.x_strata, stratum / self.x_strata);
+        let sample = Float2::new([
+            (stratum_x as Float0 + self.jitter(0)) / self.x_strata as Float0,
+            (stratum_y as Float0 + self.jitter(1)) / self.y_strata as Float0,
+        ]);
+        self.dimension += 2;
+        sample
+    }
+}
+
+// Kensler, "Correlated Multi-Jittered Sampling": a hashed permutation of `0..length`.
+fn permutation_element(mut i: u32, length: u32, p: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | (p >> 27));
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permutation_covers_every_index_once() {
+        let mut seen = [false; 13];
+        for i in 0..13 {
+            seen[permutation_element(i, 13, 0xdead_beef) as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
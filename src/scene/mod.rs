@@ -1,18 +1,52 @@
-use std::str::FromStr;
+use std::{path::Path, str::FromStr, sync::Arc};
 
 use toml::Value;
 
 use crate::{
     camera::Camera,
-    light::{Light, LightType, arealight::Arealight, pointlight::PointLight},
-    material::{Material, SamplingFunctions},
+    color::ColorSpace,
+    film::PathSample,
+    light::{
+        Light, LightSample, LightType,
+        arealight::Arealight,
+        directionallight::DirectionalLight,
+        disklight::DiskLight,
+        environment::EnvironmentLight,
+        ies::{self, IesProfile, OrientedProfile},
+        linking::LightLinks,
+        pointlight::PointLight,
+        sampling::{LightSampler, LightSampling},
+        spherelight::SphereLight,
+        spotlight::SpotLight,
+        sun::SunLight,
+        units::{self, LightUnit},
+    },
+    material::{
+        Material, SamplingFunctions,
+        dielectric::{self, Ior},
+        generate_coordinate_system,
+    },
+    medium::{
+        Medium, MediumType, grid::DensityGrid, heterogeneous::HeterogeneousMedium,
+        homogeneous::HomogeneousMedium,
+    },
     object::{
-        HitRecord, Hittable, ObjectType, bvh::Bvh, cube::Cube, plane::Plane, quad::Quad,
-        sphere::Sphere, triangle_mesh::TriangleMesh,
+        HitRecord, Hittable, ObjectType, Visibility, aabb::Aabb, bvh::Bvh, cube::Cube,
+        plane::Plane, quad::Quad, sphere::Sphere, triangle_mesh::TriangleMesh,
     },
+    output::LinearImage,
     ray::Ray,
-    skybox::Skybox,
-    utils::vector::{Float2, Float3},
+    sampler::Sampler,
+    skybox::{
+        Skybox,
+        environment::{EnvironmentMap, Projection},
+        sunsky::{self, SunSky},
+    },
+    spectrum::{self, SampledWavelengths, Spectrum},
+    utils::{
+        matrix::Float3x3,
+        vector::{Float2, Float3},
+    },
 };
 
 pub type Float0 = f64;
@@ -21,14 +55,51 @@ pub const PI: Float0 = std::f64::consts::PI as Float0;
 
 pub type RNGType = rand::rngs::StdRng;
 
+// Where next-event estimation happens: a point on `object` with `normal`,
+// or in a medium with neither.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ShadingPoint {
+    pub(crate) point: Float3,
+    pub(crate) normal: Option<Float3>,
+    pub(crate) object: Option<usize>,
+}
+
+// Next-event estimate, and what it would be if nothing cast shadows.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DirectLighting {
+    pub(crate) radiance: Float3,
+    pub(crate) unoccluded: Float3,
+}
+
 #[derive(Debug)]
 pub struct Scene {
     pub objects: Vec<Box<dyn Hittable>>,
     pub lights: Vec<Box<dyn Light>>,
     pub skybox: Skybox,
     pub camera: Camera,
+    pub color_space: ColorSpace,
     bvh: Bvh,
     unbounded_objects: Vec<usize>,
+    material_ids: Vec<usize>,
+    media: Vec<Box<dyn Medium>>,
+    object_media: Vec<Option<usize>>,
+    atmosphere: Option<usize>,
+    object_visibility: Vec<Visibility>,
+    // Whether any object is a holdout or shadow catcher.
+    has_mattes: bool,
+    has_shadow_catchers: bool,
+    has_subsurface: bool,
+    // Whether camera rays that leave the scene see the skybox, which lights
+    // the scene either way.
+    background_visible: bool,
+    // One per light.
+    light_links: Vec<LightLinks>,
+    light_sampler: LightSampler,
+    // The light standing for the environment map or the sun, which escaping
+    // rays find.
+    sky_light: Option<usize>,
+    // Centre and radius of a sphere around the bounded objects.
+    bounding_sphere: (Float3, Float0),
 }
 
 impl Scene {
@@ -40,106 +111,611 @@ impl Scene {
         illumination
     }
 
+    // Chooses how next-event estimation picks among the lights.
+    pub fn with_light_sampling(mut self, strategy: LightSampling) -> Self {
+        self.light_sampler = LightSampler::new(strategy, &self.lights);
+        self
+    }
+
     pub fn hit(&self, ray: &Ray, arg: Float0) -> Option<HitRecord<'_>> {
+        self.hit_object(ray, arg).map(|(_, record)| record)
+    }
+
+    pub fn hit_object(&self, ray: &Ray, arg: Float0) -> Option<(usize, HitRecord<'_>)> {
         let mut hit_record = self.bvh.hit(&self.objects, ray, arg, Float0::INFINITY);
         let mut closest_so_far = hit_record
             .as_ref()
-            .map_or(Float0::INFINITY, |record| record.t);
+            .map_or(Float0::INFINITY, |(_, record)| record.t);
         for object_index in &self.unbounded_objects {
             if let Some(record) = self.objects[*object_index].hit(ray, arg, closest_so_far) {
                 closest_so_far = record.t;
-                hit_record = Some(record);
+                hit_record = Some((*object_index, record));
             }
         }
         hit_record
     }
 
+    // The first object a camera ray sees, passing through those hidden from
+    // the camera.
+    pub(crate) fn hit_from_camera(&self, ray: &Ray) -> Option<(usize, HitRecord<'_>)> {
+        let mut t_min = 0.001;
+        loop {
+            let (object_index, record) = self.hit_object(ray, t_min)?;
+            if self.object_visibility[object_index].camera {
+                return Some((object_index, record));
+            }
+            t_min = record.t + 0.001;
+        }
+    }
+
+    pub fn material_id(&self, object_index: usize) -> usize {
+        self.material_ids[object_index]
+    }
+
+    pub(crate) fn visibility(&self, object_index: usize) -> Visibility {
+        self.object_visibility[object_index]
+    }
+
+    pub(crate) fn light_links(&self, light_index: usize) -> &LightLinks {
+        &self.light_links[light_index]
+    }
+
+    pub(crate) fn sky_light(&self) -> Option<usize> {
+        self.sky_light
+    }
+
+    pub(crate) fn background_visible(&self) -> bool {
+        self.background_visible
+    }
+
+    pub(crate) fn bounding_sphere(&self) -> (Float3, Float0) {
+        self.bounding_sphere
+    }
+
+    // What the scene uses that only `trace_ray` renders, for integrators
+    // that leave these out to refuse the scene by.
+    pub(crate) fn path_traced_features(&self) -> Vec<&'static str> {
+        let shadow_flags = self
+            .object_visibility
+            .iter()
+            .any(|visibility| !visibility.cast_shadows || !visibility.receive_shadows)
+            || self
+                .light_links
+                .iter()
+                .any(|links| !links.shadow_exclude.is_empty());
+        [
+            (!self.media.is_empty(), "participating media"),
+            (self.has_subsurface, "subsurface materials"),
+            (self.has_shadow_catchers, "shadow catchers"),
+            (shadow_flags, "shadow flags"),
+        ]
+        .into_iter()
+        .filter_map(|(used, feature)| used.then_some(feature))
+        .collect()
+    }
+
+    // Holdouts, shadow catchers and a hidden background only make sense
+    // with an alpha channel.
+    pub fn needs_alpha(&self) -> bool {
+        self.has_mattes || !self.background_visible
+    }
+
     pub fn trace_ray(
         &self,
         ray: &Ray,
         depth: u32,
-        rand_state: &mut RNGType,
+        sampler: &mut dyn Sampler,
         sample_type: &SamplingFunctions,
-    ) -> Float3 {
+        wavelengths: Option<SampledWavelengths>,
+        path: &mut PathSample,
+    ) {
+        path.reset();
+        path.wavelengths = wavelengths;
         let mut throughput = Float3::new([1.0, 1.0, 1.0]);
+        // The RGB tint of specular transmission, for the albedo guide.
+        let mut guide_throughput = Float3::new([1.0, 1.0, 1.0]);
         let mut ray: Ray = *ray;
-        let mut emitted = Float3::new([0.0, 0.0, 0.0]);
-        for _bounce in 0..depth {
-            if let Some(hit_record) = self.hit(&ray, 0.001) {
-                if hit_record.material.reflectivity == 1.0 {
+        let mut medium = self.atmosphere;
+        let mut diffuse_vertices = 0;
+        // Pdf of the direction sampled at the last non-specular vertex, for
+        // weighting an environment map hit against light sampling.
+        let mut scatter_pdf: Option<Float0> = None;
+        for bounce in 0..depth {
+            let hit = if bounce == 0 {
+                self.hit_from_camera(&ray)
+            } else {
+                self.hit_object(&ray, 0.001)
+            };
+
+            if let Some(medium_index) = medium {
+                let t_max = hit
+                    .as_ref()
+                    .map_or(Float0::INFINITY, |(_, record)| record.t);
+                let medium = &self.media[medium_index];
+                let sample = medium.sample(&ray, t_max, sampler, path.wavelengths.as_ref());
+                let emitted = throughput * sample.emission;
+                path.color += emitted;
+                if diffuse_vertices <= 1 {
+                    path.direct += emitted;
+                }
+                throughput *= sample.weight;
+                if let Some(t) = sample.scatter {
+                    if diffuse_vertices == 0 {
+                        path.albedo = guide_throughput * medium.albedo();
+                    }
+                    diffuse_vertices += 1;
+
+                    let point = ray.at(t);
+                    let direction = ray.direction.normalize();
+                    let phase = medium.phase();
+                    let direct = self
+                        .direct_lighting(
+                            ShadingPoint {
+                                point,
+                                normal: None,
+                                object: None,
+                            },
+                            Some(medium_index),
+                            throughput,
+                            sampler,
+                            path,
+                            |to_light| {
+                                let density = phase.evaluate(direction.dot(&to_light));
+                                (Float3::new([density, density, density]), density)
+                            },
+                        )
+                        .radiance;
+                    path.color += direct;
+                    if diffuse_vertices == 1 {
+                        path.direct += direct;
+                    }
+
+                    // The phase function is sampled exactly, so the throughput
+                    // is unchanged.
+                    let (next, pdf) = phase.sample(&direction, sampler.get_2d());
+                    scatter_pdf = Some(pdf);
+                    ray = Ray::new(point, next);
+                    continue;
+                }
+            }
+
+            if let Some((object_index, hit_record)) = hit {
+                if bounce == 0 {
+                    path.position = hit_record.point;
+                    path.depth = hit_record.t * ray.direction.length();
+                    path.object_id = Some(object_index);
+                    path.material_id = Some(self.material_id(object_index));
+                    if self.object_visibility[object_index].holdout {
+                        path.alpha = 0.0;
+                        path.discard_radiance();
+                        return;
+                    }
+                }
+
+                if bounce == 0 && hit_record.material.shadow_catcher {
+                    // The catcher itself stays transparent: its alpha is how
+                    // much of the light reaching it other objects block, its
+                    // colour the light they reflect onto it.
+                    let color = hit_record.material.color(&hit_record.uv);
+                    path.albedo = color;
+                    path.normal = hit_record.normal;
+                    diffuse_vertices += 1;
+
+                    let brdf = spectrum::uplift(color, path.wavelengths.as_ref()).scale(1.0 / PI);
+                    let normal = hit_record.normal;
+                    let direct = self.direct_lighting(
+                        ShadingPoint {
+                            point: hit_record.point,
+                            normal: Some(normal),
+                            object: Some(object_index),
+                        },
+                        medium,
+                        throughput,
+                        sampler,
+                        path,
+                        |to_light| {
+                            let cos_theta = normal.dot(&to_light).max(0.0);
+                            (
+                                brdf.scale(cos_theta),
+                                Material::sample_pdf(&normal, &to_light, sample_type),
+                            )
+                        },
+                    );
+                    // Nothing was lit before the first surface.
+                    path.lights.fill(Float3::new([0.0, 0.0, 0.0]));
+
+                    let pdf;
+                    (ray, pdf) = hit_record
+                        .material
+                        .scatter(&hit_record, sampler, sample_type);
+                    let cos_theta = ray.direction.dot(&normal).max(0.0);
+                    let mut lit = direct.radiance;
+                    let mut unoccluded = direct.unoccluded;
+                    let mut blocked = false;
+                    if pdf > 0.0 && cos_theta > 0.0 {
+                        throughput *= brdf.scale(cos_theta / pdf);
+                        scatter_pdf = Some(pdf);
+                        let sky = throughput
+                            * spectrum::uplift(
+                                self.escaped_radiance(&ray, scatter_pdf),
+                                path.wavelengths.as_ref(),
+                            );
+                        blocked = self.hit_object(&ray, 0.001).is_some_and(|(index, record)| {
+                            !record.material.is_interface()
+                                && self.object_visibility[index].cast_shadows
+                        });
+                        if !blocked {
+                            lit += sky;
+                        }
+                        unoccluded += sky;
+                    }
+                    let (lit, unoccluded) = (path.to_rgb(lit), path.to_rgb(unoccluded));
+                    let unoccluded = unoccluded.0.iter().sum::<Float0>();
+                    path.alpha = if unoccluded > 0.0 {
+                        (1.0 - lit.0.iter().sum::<Float0>() / unoccluded).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+                    // Follow the path only into whatever blocks the sky.
+                    if !blocked {
+                        return;
+                    }
+                    continue;
+                }
+
+                if let Some(ior) = &hit_record.material.ior {
+                    let lambda = match path.wavelengths.as_mut() {
+                        Some(wavelengths) => {
+                            if ior.is_dispersive() {
+                                wavelengths.terminate_secondary();
+                            }
+                            wavelengths.hero()
+                        }
+                        None => Ior::REFERENCE_WAVELENGTH,
+                    };
+                    let eta = if hit_record.front_face {
+                        ior.at(lambda).recip()
+                    } else {
+                        ior.at(lambda)
+                    };
+                    let direction = ray.direction.normalize();
+                    let reflectance = dielectric::fresnel(-direction.dot(&hit_record.normal), eta);
+                    let refracted = dielectric::refract(&direction, &hit_record.normal, eta)
+                        .filter(|_| sampler.get_1d() >= reflectance);
+                    let next = match refracted {
+                        Some(refracted) => {
+                            let tint = hit_record.material.color(&hit_record.uv);
+                            throughput *= spectrum::uplift(tint, path.wavelengths.as_ref());
+                            guide_throughput *= tint;
+                            medium = self.medium_behind(object_index, hit_record.front_face);
+                            refracted
+                        }
+                        None => Material::reflect(&direction, &hit_record.normal),
+                    };
+                    scatter_pdf = None;
+                    ray = Ray {
+                        origin: hit_record.point + next.scale(0.001),
+                        direction: next,
+                    };
+                } else if let Some(subsurface) = hit_record
+                    .material
+                    .subsurface
+                    .filter(|_| hit_record.front_face)
+                {
+                    let color = hit_record.material.color(&hit_record.uv);
+                    if diffuse_vertices == 0 {
+                        path.albedo = guide_throughput * color;
+                        path.normal = hit_record.normal;
+                    }
+                    diffuse_vertices += 1;
+
+                    let Some(exit) = subsurface.random_walk(
+                        self.objects[object_index].as_ref(),
+                        &hit_record,
+                        color,
+                        sampler,
+                        path.wavelengths.as_ref(),
+                    ) else {
+                        return;
+                    };
+                    throughput *= exit.weight;
+
+                    // Light leaves through a white Lambertian lobe, the walk
+                    // has already applied the colour.
+                    let normal = exit.normal;
+                    let direct = self
+                        .direct_lighting(
+                            ShadingPoint {
+                                point: exit.point,
+                                normal: Some(normal),
+                                object: Some(object_index),
+                            },
+                            medium,
+                            throughput,
+                            sampler,
+                            path,
+                            |to_light| {
+                                let cos_theta = normal.dot(&to_light).max(0.0) / PI;
+                                (
+                                    Float3::new([cos_theta, cos_theta, cos_theta]),
+                                    Material::sample_pdf(&normal, &to_light, sample_type),
+                                )
+                            },
+                        )
+                        .radiance;
+                    path.color += direct;
+                    if diffuse_vertices == 1 {
+                        path.direct += direct;
+                    }
+
+                    let exit_record = HitRecord {
+                        point: exit.point,
+                        normal,
+                        front_face: false,
+                        ..hit_record
+                    };
+                    let pdf;
+                    (ray, pdf) = hit_record
+                        .material
+                        .scatter(&exit_record, sampler, sample_type);
+                    let cos_theta = ray.direction.dot(&normal).max(0.0);
+                    if pdf <= 0.0 || cos_theta <= 0.0 {
+                        return;
+                    }
+                    throughput = throughput.scale(cos_theta / (PI * pdf));
+                    scatter_pdf = Some(pdf);
+                } else if hit_record.material.reflectivity == 1.0 {
                     let reflected =
                         Material::reflect(&ray.direction.normalize(), &hit_record.normal);
+                    scatter_pdf = None;
                     ray = Ray {
                         origin: hit_record.point + reflected.scale(0.001),
                         direction: reflected,
                     };
                 } else {
-                    emitted +=
-                        throughput * self.direct_lighting(&hit_record, rand_state, sample_type);
+                    let color = hit_record.material.color(&hit_record.uv);
+                    // Albedo and normal guides follow perfect mirrors to the
+                    // first diffuse surface, as the denoiser expects.
+                    if diffuse_vertices == 0 {
+                        path.albedo = guide_throughput * color;
+                        path.normal = hit_record.normal;
+                    }
+                    diffuse_vertices += 1;
+
+                    let brdf = spectrum::uplift(color, path.wavelengths.as_ref()).scale(1.0 / PI);
+                    let normal = hit_record.normal;
+                    let direct = self
+                        .direct_lighting(
+                            ShadingPoint {
+                                point: hit_record.point,
+                                normal: Some(normal),
+                                object: Some(object_index),
+                            },
+                            medium,
+                            throughput,
+                            sampler,
+                            path,
+                            |to_light| {
+                                let cos_theta = normal.dot(&to_light).max(0.0);
+                                (
+                                    brdf.scale(cos_theta),
+                                    Material::sample_pdf(&normal, &to_light, sample_type),
+                                )
+                            },
+                        )
+                        .radiance;
+                    path.color += direct;
+                    if diffuse_vertices == 1 {
+                        path.direct += direct;
+                    }
 
                     let pdf;
                     (ray, pdf) = hit_record
                         .material
-                        .scatter(&hit_record, rand_state, sample_type);
-
-                    let brdf = hit_record.material.color(&hit_record.uv).scale(1.0 / PI);
+                        .scatter(&hit_record, sampler, sample_type);
 
                     let cos_theta = ray.direction.dot(&hit_record.normal).max(0.0);
                     if pdf <= 0.0 || cos_theta <= 0.0 {
-                        return emitted;
+                        return;
                     }
 
                     throughput *= brdf.scale(cos_theta).scale(pdf.recip());
+                    scatter_pdf = Some(pdf);
                 }
             } else {
-                return emitted + (throughput * self.skybox.color);
+                // Camera rays that leave the scene are what alpha doesn't
+                // cover.
+                if bounce == 0 {
+                    path.alpha = 0.0;
+                    if !self.background_visible {
+                        return;
+                    }
+                }
+                let sky = self.skybox.radiance(ray.direction);
+                let seen = self.escaped_radiance(&ray, scatter_pdf);
+                let background = throughput * spectrum::uplift(seen, path.wavelengths.as_ref());
+                if diffuse_vertices == 0 {
+                    path.albedo = guide_throughput * sky;
+                }
+                path.color += background;
+                if diffuse_vertices <= 1 {
+                    path.direct += background;
+                }
+                return;
+            }
+        }
+        // Paths that run out of bounces contribute nothing, except inside an
+        // unbounded atmosphere, which no path ever escapes, so keep the light
+        // gathered so far.
+        if self.atmosphere.is_none() {
+            path.discard_radiance();
+        }
+    }
+
+    // What a ray leaving the scene sees. Environment maps and the sun are
+    // also sampled as lights, so after a non-specular vertex, which sampled
+    // the ray with `scatter_pdf`, the two ways of finding them are weighed
+    // against each other.
+    pub(crate) fn escaped_radiance(&self, ray: &Ray, scatter_pdf: Option<Float0>) -> Float3 {
+        match scatter_pdf {
+            Some(pdf) => {
+                let selection_pdf = self
+                    .sky_light
+                    .map_or(1.0, |light| self.light_sampler.infinite_pmf(light));
+                self.skybox.weighted_radiance(ray.direction, |light_pdf| {
+                    Self::power_heuristic(pdf, light_pdf * selection_pdf)
+                })
             }
+            None => self.skybox.radiance(ray.direction),
+        }
+    }
+
+    // The medium a ray is in after passing through the surface of
+    // `object_index`: the object's own when entering it, the atmosphere when
+    // leaving. Media don't nest.
+    fn medium_behind(&self, object_index: usize, entering: bool) -> Option<usize> {
+        if entering {
+            self.object_media[object_index]
+        } else {
+            self.atmosphere
         }
-        Float3::new([0.0, 0.0, 0.0])
     }
 
-    fn direct_lighting(
+    // Returns the next-event estimate from `at` weighted by `throughput`, with
+    // and without shadows, and adds each light's share to the path's per-light
+    // AOVs. `scattering` gives the BSDF times cosine, or the phase function,
+    // towards a light together with the pdf of sampling that direction.
+    // Depending on the light sampling strategy every light is sampled or just
+    // one picked at random.
+    pub(crate) fn direct_lighting(
         &self,
-        hit_record: &HitRecord,
-        rand_state: &mut RNGType,
-        sample_type: &SamplingFunctions,
-    ) -> Float3 {
-        let mut contribution = Float3::new([0.0, 0.0, 0.0]);
-        let brdf = hit_record.material.color(&hit_record.uv).scale(1.0 / PI);
+        at: ShadingPoint,
+        medium: Option<usize>,
+        throughput: Float3,
+        sampler: &mut dyn Sampler,
+        path: &mut PathSample,
+        scattering: impl Fn(Float3) -> (Float3, Float0),
+    ) -> DirectLighting {
+        let mut contribution = DirectLighting {
+            radiance: Float3::new([0.0, 0.0, 0.0]),
+            unoccluded: Float3::new([0.0, 0.0, 0.0]),
+        };
+        let wavelengths = path.wavelengths;
 
-        for light in &self.lights {
-            let sample = light.sample(hit_record.point, rand_state);
-            if sample.pdf <= 0.0 || sample.radiance.length_squared() == 0.0 {
-                continue;
+        let chosen = match self.light_sampler.strategy() {
+            LightSampling::All => None,
+            LightSampling::Power | LightSampling::Bvh => {
+                match self
+                    .light_sampler
+                    .sample(at.point, at.normal, sampler.get_1d())
+                {
+                    Some(choice) => Some(choice),
+                    None => return contribution,
+                }
             }
+        };
+        let (candidates, selection_pdf) = match chosen {
+            Some((light_index, probability)) => (light_index..light_index + 1, probability),
+            None => (0..self.lights.len(), 1.0),
+        };
 
-            let cos_theta = hit_record.normal.dot(&sample.direction).max(0.0);
-            if cos_theta <= 0.0
-                || !self.visible_to_light(hit_record.point, sample.direction, sample.distance)
+        for light_index in candidates {
+            if at
+                .object
+                .is_some_and(|object| !self.light_links[light_index].illuminates(object))
             {
                 continue;
             }
+            let light = &self.lights[light_index];
+            let sample = light.sample(at.point, sampler, wavelengths.as_ref());
+            if sample.pdf <= 0.0 || sample.radiance.length_squared() == 0.0 {
+                continue;
+            }
 
-            let weight = if sample.delta {
+            let (value, scattering_pdf) = scattering(sample.direction);
+            if value.length_squared() == 0.0 {
+                continue;
+            }
+            let light_pdf = sample.pdf * selection_pdf;
+            let weight = if sample.delta || !light.found_by_scattering() {
                 1.0
             } else {
-                let bsdf_pdf =
-                    Material::sample_pdf(&hit_record.normal, &sample.direction, sample_type);
-                Self::power_heuristic(sample.pdf, bsdf_pdf)
+                Self::power_heuristic(light_pdf, scattering_pdf)
             };
-            contribution += brdf
-                * sample
-                    .radiance
-                    .scale(cos_theta * weight * sample.pdf.recip());
+            let unoccluded = throughput * value * sample.radiance.scale(weight * light_pdf.recip());
+            contribution.unoccluded += unoccluded;
+
+            let transmittance = self.transmittance_to_light(
+                at,
+                light_index,
+                &sample,
+                medium,
+                sampler,
+                wavelengths.as_ref(),
+            );
+            if transmittance.length_squared() == 0.0 {
+                continue;
+            }
+            let light_contribution = unoccluded * transmittance;
+            if let Some(total) = path.lights.get_mut(light_index) {
+                *total += light_contribution;
+            }
+            contribution.radiance += light_contribution;
         }
 
         contribution
     }
 
-    fn visible_to_light(&self, point: Float3, direction: Float3, distance: Float0) -> bool {
-        let shadow_ray = Ray::new(point + direction.scale(0.001), direction);
-        self.hit(&shadow_ray, 0.001)
-            .is_none_or(|record| record.t >= distance - 0.001)
+    // Transmittance of the shadow ray from `at` to the light sampled by
+    // `sample`. Opaque and refracting surfaces block it unless they don't
+    // cast shadows, on the point or for this light; index-matched medium
+    // boundaries only switch the medium it attenuates through.
+    pub(crate) fn transmittance_to_light(
+        &self,
+        at: ShadingPoint,
+        light_index: usize,
+        sample: &LightSample,
+        mut medium: Option<usize>,
+        sampler: &mut dyn Sampler,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Float3 {
+        let direction = sample.direction;
+        let receives_shadows = at
+            .object
+            .is_none_or(|object| self.object_visibility[object].receive_shadows);
+        let mut transmittance = Float3::new([1.0, 1.0, 1.0]);
+        let mut origin = at.point;
+        let mut remaining = sample.distance;
+        loop {
+            let shadow_ray = Ray::new(origin + direction.scale(0.001), direction);
+            let hit = self
+                .hit_object(&shadow_ray, 0.001)
+                .filter(|(_, record)| record.t < remaining - 0.001);
+            if let Some(medium_index) = medium {
+                let t_max = hit.as_ref().map_or(remaining, |(_, record)| record.t);
+                transmittance *= self.media[medium_index].transmittance(
+                    &shadow_ray,
+                    t_max,
+                    sampler,
+                    wavelengths,
+                );
+            }
+            match hit {
+                None => return transmittance,
+                Some((object_index, record)) => {
+                    if record.material.is_interface() {
+                        medium = self.medium_behind(object_index, record.front_face);
+                    } else if receives_shadows
+                        && self.object_visibility[object_index].cast_shadows
+                        && self.light_links[light_index].is_shadowed_by(object_index)
+                    {
+                        return Float3::new([0.0, 0.0, 0.0]);
+                    }
+                    origin = record.point;
+                    remaining -= record.t + 0.001;
+                }
+            }
+        }
     }
 
     fn power_heuristic(a_pdf: Float0, b_pdf: Float0) -> Float0 {
@@ -151,6 +727,18 @@ impl Scene {
     pub fn try_from_toml(toml: &Value) -> Result<Self, String> {
         let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
         let mut lights: Vec<Box<dyn Light>> = Vec::new();
+        let mut material_keys: Vec<String> = Vec::new();
+        let mut material_ids: Vec<usize> = Vec::new();
+        let mut media: Vec<Box<dyn Medium>> = Vec::new();
+        let mut object_media: Vec<Option<usize>> = Vec::new();
+        let mut object_visibility: Vec<Visibility> = Vec::new();
+        let mut object_names: Vec<Option<&str>> = Vec::new();
+        let mut light_links: Vec<LightLinks> = Vec::new();
+        let mut has_mattes = false;
+        let mut has_shadow_catchers = false;
+        let mut has_subsurface = false;
+
+        let color_space = color_space_field(toml, "scene")?.unwrap_or_default();
 
         let camera_value = required(toml, "camera", "scene")?;
         let camera = Camera {
@@ -158,16 +746,41 @@ impl Scene {
             rotation: float3_field(camera_value, "rotation", "camera")?,
         };
 
-        let skybox_value = required(toml, "skybox", "scene")?;
-        let skybox = Skybox {
-            color: float3_field(skybox_value, "color", "skybox")?,
+        let atmosphere = match toml.get("atmosphere") {
+            Some(atmosphere) => {
+                media.push(medium_from_toml(
+                    atmosphere,
+                    "atmosphere",
+                    None,
+                    color_space,
+                )?);
+                Some(media.len() - 1)
+            }
+            None => None,
         };
 
+        let skybox_value = required(toml, "skybox", "scene")?;
+        let skybox = skybox_from_toml(skybox_value, color_space)?;
+        let background_visible =
+            bool_field(skybox_value, "visible_to_camera", "skybox")?.unwrap_or(true);
+
         for (index, object) in array_field(toml, "objects", "scene")?.iter().enumerate() {
             let path = format!("objects[{index}]");
             let object_type = str_field(object, "type", &path)?;
-            let material = material_from_object(object, &path)?;
+            let material = material_from_object(object, &path, color_space)?;
+            has_mattes |= material.shadow_catcher;
+            has_shadow_catchers |= material.shadow_catcher;
+            has_subsurface |= material.subsurface.is_some();
+            let material_key = material_key(object);
+            let material_id = material_keys
+                .iter()
+                .position(|key| *key == material_key)
+                .unwrap_or_else(|| {
+                    material_keys.push(material_key);
+                    material_keys.len() - 1
+                });
 
+            let mut volume_medium: Option<Box<dyn Medium>> = None;
             match ObjectType::from_str(object_type) {
                 Ok(object_type) => match object_type {
                     ObjectType::Sphere => {
@@ -215,6 +828,20 @@ impl Scene {
                             material,
                         )));
                     }
+                    ObjectType::Volume => {
+                        let bounds = match (
+                            optional_float3_field(object, "min", &path)?,
+                            optional_float3_field(object, "max", &path)?,
+                        ) {
+                            (Some(min), Some(max)) => Some((min, max)),
+                            (None, None) => None,
+                            _ => return Err(format!("{path} needs both `min` and `max`")),
+                        };
+                        let (medium, (min, max)) =
+                            grid_medium_from_toml(object, &path, bounds, color_space)?;
+                        objects.push(Box::new(Cube::new(min, max, Material::interface())));
+                        volume_medium = Some(Box::new(medium));
+                    }
                 },
                 Err(_) => {
                     return Err(format!(
@@ -222,28 +849,177 @@ impl Scene {
                     ));
                 }
             }
+            material_ids.push(material_id);
+            let visibility = visibility_from_object(object, &path)?;
+            has_mattes |= visibility.holdout;
+            object_visibility.push(visibility);
+            object_names.push(
+                object
+                    .get("name")
+                    .map(|name| {
+                        name.as_str()
+                            .ok_or_else(|| format!("{path}.name must be a string"))
+                    })
+                    .transpose()?,
+            );
+            if let Some(medium) = volume_medium {
+                media.push(medium);
+                object_media.push(Some(media.len() - 1));
+                continue;
+            }
+            object_media.push(match object.get("medium") {
+                Some(medium) => {
+                    let bounds = objects
+                        .last()
+                        .and_then(|object| object.bounding_box())
+                        .map(|bounds| (bounds.min, bounds.max));
+                    media.push(medium_from_toml(
+                        medium,
+                        &format!("{path}.medium"),
+                        bounds,
+                        color_space,
+                    )?);
+                    Some(media.len() - 1)
+                }
+                None => None,
+            });
         }
 
         if let Some(lights_array) = toml.get("lights").and_then(|lights| lights.as_array()) {
             for (index, light) in lights_array.iter().enumerate() {
                 let path = format!("lights[{index}]");
                 let light_type = str_field(light, "type", &path)?;
+                if light.get("ies").is_some() && !matches!(light_type, "point" | "spot") {
+                    return Err(format!("{path}.ies only applies to point and spot lights"));
+                }
+                if light.get("two_sided").is_some() && !matches!(light_type, "area" | "disk") {
+                    return Err(format!(
+                        "{path}.two_sided only applies to area and disk lights"
+                    ));
+                }
+                let two_sided = bool_field(light, "two_sided", &path)?.unwrap_or(false);
+                light_links.push(light_links_from_toml(light, &path, &object_names)?);
                 match LightType::from_str(light_type) {
                     Ok(light_type_enum) => match light_type_enum {
                         LightType::PointLight => {
-                            lights.push(Box::new(PointLight::new(
-                                float3_field(light, "position", &path)?,
-                                float3_field(light, "color", &path)?,
-                            )));
+                            let position = float3_field(light, "position", &path)?;
+                            let profile = ies_field(light, &path, identity())?;
+                            lights.push(physical_light(
+                                light,
+                                &path,
+                                color_space,
+                                "candela",
+                                true,
+                                |emission| {
+                                    let point_light = PointLight::new(position, emission);
+                                    match &profile {
+                                        Some(profile) => point_light.with_profile(profile.clone()),
+                                        None => point_light,
+                                    }
+                                },
+                            )?);
+                        }
+                        LightType::SpotLight => {
+                            let outer_angle =
+                                optional_float_field(light, "outer_angle", &path)?.unwrap_or(30.0);
+                            let inner_angle = optional_float_field(light, "inner_angle", &path)?
+                                .unwrap_or(outer_angle);
+                            if !(0.0..=180.0).contains(&outer_angle) || inner_angle < 0.0 {
+                                return Err(format!(
+                                    "{path}.outer_angle must be between 0 and 180 degrees and inner_angle not negative"
+                                ));
+                            }
+                            let position = float3_field(light, "position", &path)?;
+                            // The profile's downward axis follows the spot.
+                            let down = light_direction(light, &path)?.normalize();
+                            let (tangent, _) = generate_coordinate_system(&down);
+                            let frame = Float3x3::new_from_columns([
+                                tangent.0,
+                                (-down).0,
+                                tangent.cross(&-down).0,
+                            ]);
+                            let profile = ies_field(light, &path, frame)?;
+                            lights.push(physical_light(
+                                light,
+                                &path,
+                                color_space,
+                                "candela",
+                                true,
+                                |emission| {
+                                    let spot_light = SpotLight::new(
+                                        position,
+                                        down,
+                                        inner_angle,
+                                        outer_angle,
+                                        emission,
+                                    );
+                                    match &profile {
+                                        Some(profile) => spot_light.with_profile(profile.clone()),
+                                        None => spot_light,
+                                    }
+                                },
+                            )?);
+                        }
+                        LightType::DirectionalLight => {
+                            let angle = optional_float_field(light, "angle", &path)?.unwrap_or(0.0);
+                            if !(0.0..180.0).contains(&angle) {
+                                return Err(format!(
+                                    "{path}.angle must be between 0 and 180 degrees"
+                                ));
+                            }
+                            let direction = light_direction(light, &path)?;
+                            lights.push(physical_light(
+                                light,
+                                &path,
+                                color_space,
+                                "lux",
+                                false,
+                                |emission| DirectionalLight::new(direction, angle, emission),
+                            )?);
+                        }
+                        LightType::DiskLight => {
+                            let position = float3_field(light, "position", &path)?;
+                            let normal = float3_field(light, "normal", &path)?;
+                            let radius = light_radius(light, &path)?;
+                            lights.push(physical_light(
+                                light,
+                                &path,
+                                color_space,
+                                "nits",
+                                true,
+                                |emission| {
+                                    DiskLight::new(position, normal, radius, emission)
+                                        .with_two_sided(two_sided)
+                                },
+                            )?);
+                        }
+                        LightType::SphereLight => {
+                            let position = float3_field(light, "position", &path)?;
+                            let radius = light_radius(light, &path)?;
+                            lights.push(physical_light(
+                                light,
+                                &path,
+                                color_space,
+                                "nits",
+                                true,
+                                |emission| SphereLight::new(position, radius, emission),
+                            )?);
                         }
                         LightType::AreaLight => {
                             let _c = float3_field(light, "point3", &path)?;
-                            lights.push(Box::new(Arealight::new(
-                                float3_field(light, "point1", &path)?,
-                                float3_field(light, "point2", &path)?,
-                                float3_field(light, "point4", &path)?,
-                                float3_field(light, "color", &path)?,
-                            )));
+                            let a = float3_field(light, "point1", &path)?;
+                            let b = float3_field(light, "point2", &path)?;
+                            let d = float3_field(light, "point4", &path)?;
+                            lights.push(physical_light(
+                                light,
+                                &path,
+                                color_space,
+                                "nits",
+                                true,
+                                |emission| {
+                                    Arealight::new(a, b, d, emission).with_two_sided(two_sided)
+                                },
+                            )?);
                         }
                         LightType::ObjectLight => {
                             return Err(format!("{path}.type object lights are not implemented"));
@@ -256,6 +1032,34 @@ impl Scene {
             }
         }
 
+        // After the scene's own lights, so their per-light AOVs keep their
+        // indices.
+        let bounding_sphere = objects
+            .iter()
+            .filter_map(|object| object.bounding_box())
+            .reduce(Aabb::surrounding)
+            .map_or((Float3::new([0.0, 0.0, 0.0]), 1.0), |bounds| {
+                (
+                    (bounds.min + bounds.max).scale(0.5),
+                    (bounds.max - bounds.min).length() * 0.5,
+                )
+            });
+        let scene_radius = bounding_sphere.1;
+        let mut sky_light = None;
+        if let Some(environment) = &skybox.environment {
+            sky_light = Some(lights.len());
+            lights.push(Box::new(EnvironmentLight::new(environment.clone())));
+        }
+        if let Some(sun_sky) = &skybox.sun_sky {
+            sky_light = sky_light.or(Some(lights.len()));
+            lights.push(Box::new(SunLight::new(sun_sky.clone())));
+        }
+        for light in lights.iter_mut() {
+            light.set_scene_radius(scene_radius);
+        }
+        light_links.resize(lights.len(), LightLinks::default());
+        let light_sampler = LightSampler::new(LightSampling::default(), &lights);
+
         let (bvh, unbounded_objects) = Bvh::build(&objects);
 
         Ok(Scene {
@@ -263,8 +1067,22 @@ impl Scene {
             lights,
             camera,
             skybox,
+            color_space,
             bvh,
             unbounded_objects,
+            material_ids,
+            media,
+            object_media,
+            atmosphere,
+            object_visibility,
+            has_mattes,
+            has_shadow_catchers,
+            has_subsurface,
+            background_visible,
+            light_links,
+            light_sampler,
+            sky_light,
+            bounding_sphere,
         })
     }
 
@@ -273,16 +1091,558 @@ impl Scene {
     }
 }
 
-fn material_from_object(object: &Value, path: &str) -> Result<Material, String> {
-    if let Some(material) = object.get("material") {
-        Material::try_from_toml(material).map_err(|err| format!("{path}.material: {err}"))
+fn material_from_object(
+    object: &Value,
+    path: &str,
+    working_space: ColorSpace,
+) -> Result<Material, String> {
+    let mut material = if let Some(material) = object.get("material") {
+        Material::try_from_toml(material).map_err(|err| format!("{path}.material: {err}"))?
     } else if let Some(color) = object.get("color") {
-        Ok(Material::from_color(float3(
-            color,
-            &format!("{path}.color"),
-        )?))
+        Material::from_color(float3(color, &format!("{path}.color"))?)
+    } else if object.get("medium").is_some() || is_volume(object) {
+        Material::interface()
+    } else {
+        Material::default()
+    };
+    if let Some(input_space) = color_space_field(object, path)? {
+        material.albedo = to_working_space(material.albedo, input_space, working_space);
+    }
+    Ok(material)
+}
+
+// `{ type = "homogeneous", sigma_a = [..], sigma_s = [..], g = 0.0 }` with
+// absorption and scattering coefficients per unit of distance and the
+// Henyey-Greenstein asymmetry `g`. Every field is optional. Heterogeneous
+// media stretch their grid over `bounds`, the object's bounding box.
+fn medium_from_toml(
+    value: &Value,
+    path: &str,
+    bounds: Option<(Float3, Float3)>,
+    working_space: ColorSpace,
+) -> Result<Box<dyn Medium>, String> {
+    let medium_type = match value.get("type") {
+        Some(_) => str_field(value, "type", path)?,
+        None => "homogeneous",
+    };
+    match MediumType::from_str(medium_type) {
+        Ok(MediumType::Homogeneous) => Ok(Box::new(HomogeneousMedium::new(
+            coefficient_field(value, "sigma_a", path)?,
+            coefficient_field(value, "sigma_s", path)?,
+            asymmetry_field(value, path)?,
+        ))),
+        Ok(MediumType::Heterogeneous) => {
+            let (medium, _) = grid_medium_from_toml(value, path, bounds, working_space)?;
+            Ok(Box::new(medium))
+        }
+        Err(_) => Err(format!(
+            "{path}.type has unknown medium type `{medium_type}`"
+        )),
+    }
+}
+
+// A density `grid` file (`.vol`, or raw floats with a `resolution`) scaled by
+// `density_scale`, with coefficients per unit of density, and optional
+// `emission` or blackbody `temperature` for fire, modulated by an
+// `emission_grid`. Returns the medium with the box it fills: `bounds` if
+// given, else the bounds stored in the grid file.
+fn grid_medium_from_toml(
+    value: &Value,
+    path: &str,
+    bounds: Option<(Float3, Float3)>,
+    working_space: ColorSpace,
+) -> Result<(HeterogeneousMedium, (Float3, Float3)), String> {
+    let resolution = value
+        .get("resolution")
+        .map(|resolution| {
+            let resolution = number_array::<3>(resolution, &format!("{path}.resolution"))?;
+            if resolution
+                .iter()
+                .any(|size| *size < 1.0 || size.fract() != 0.0)
+            {
+                return Err(format!("{path}.resolution must be positive integers"));
+            }
+            Ok(resolution.map(|size| size as usize))
+        })
+        .transpose()?;
+    let grid = |key: &str| {
+        DensityGrid::read(Path::new(str_field(value, key, path)?), resolution)
+            .map_err(|err| format!("{path}.{key}: {err}"))
+    };
+
+    let density = grid("grid")?;
+    let (min, max) = bounds.or(density.bounds).ok_or_else(|| {
+        format!("{path} needs `min` and `max` bounds, the grid file doesn't store them")
+    })?;
+    if (0..3).any(|axis| max.0[axis] <= min.0[axis]) {
+        return Err(format!("{path} bounds must have max above min"));
+    }
+    let scale = match value.get("density_scale") {
+        Some(scale) => number(scale, &format!("{path}.density_scale"))?,
+        None => 1.0,
+    };
+    if scale < 0.0 {
+        return Err(format!("{path}.density_scale must not be negative"));
+    }
+
+    let mut medium = HeterogeneousMedium::new(
+        density,
+        (min, max),
+        coefficient_field(value, "sigma_a", path)?.scale(scale),
+        coefficient_field(value, "sigma_s", path)?.scale(scale),
+        asymmetry_field(value, path)?,
+    );
+    if value.get("emission").is_some() || value.get("temperature").is_some() {
+        let emission = emission_field(value, "emission", path, working_space)?;
+        let emission_grid = match value.get("emission_grid") {
+            Some(_) => Some(grid("emission_grid")?),
+            None => None,
+        };
+        medium = medium.with_emission(emission, emission_grid);
+    }
+    Ok((medium, (min, max)))
+}
+
+fn coefficient_field(value: &Value, key: &str, path: &str) -> Result<Float3, String> {
+    let sigma =
+        optional_float3_field(value, key, path)?.unwrap_or_else(|| Float3::new([0.0, 0.0, 0.0]));
+    if sigma.0.iter().any(|sigma| *sigma < 0.0) {
+        return Err(format!("{path}.{key} must not be negative"));
+    }
+    Ok(sigma)
+}
+
+fn asymmetry_field(value: &Value, path: &str) -> Result<Float0, String> {
+    let g = match value.get("g") {
+        Some(g) => number(g, &format!("{path}.g"))?,
+        None => 0.0,
+    };
+    if !(-1.0..=1.0).contains(&g) {
+        return Err(format!("{path}.g must be between -1 and 1"));
+    }
+    Ok(g)
+}
+
+fn is_volume(object: &Value) -> bool {
+    object.get("type").and_then(Value::as_str) == Some("volume")
+}
+
+// A constant `color`, or an equirectangular `environment` image or six `cube`
+// faces scaled by `intensity`, tinted by `color` and turned by `rotation`
+// degrees about the vertical axis. Images are in the working space unless
+// tagged with a `color_space`. `type = "sun_sky"` makes a physical sky.
+fn skybox_from_toml(value: &Value, working_space: ColorSpace) -> Result<Skybox, String> {
+    if let Some(skybox_type) = value.get("type") {
+        let name = skybox_type
+            .as_str()
+            .ok_or_else(|| "skybox.type must be a string".to_string())?;
+        return match name {
+            "sun_sky" | "sky" | "preetham" => Ok(Skybox {
+                color: Float3::new([0.0, 0.0, 0.0]),
+                environment: None,
+                sun_sky: Some(Arc::new(sun_sky_from_toml(value, working_space)?)),
+            }),
+            _ => Err(format!(
+                "skybox.type has unknown skybox type `{name}` (sun_sky)"
+            )),
+        };
+    }
+
+    let image = |file: &Value, path: &str| -> Result<LinearImage, String> {
+        let file = file
+            .as_str()
+            .ok_or_else(|| format!("{path} must be a file path"))?;
+        let mut image =
+            LinearImage::read(Path::new(file)).map_err(|err| format!("{path}: {err}"))?;
+        if image.pixels.is_empty() {
+            return Err(format!("{path}: the image is empty"));
+        }
+        let input_space = color_space_field(value, "skybox")?.unwrap_or(working_space);
+        for pixel in image.pixels.iter_mut() {
+            *pixel = to_working_space(*pixel, input_space, working_space);
+        }
+        Ok(image)
+    };
+
+    let projection = match (value.get("environment"), value.get("cube")) {
+        (Some(_), Some(_)) => {
+            return Err("skybox can't have both an `environment` and a `cube`".to_string());
+        }
+        (Some(file), None) => Some(Projection::Equirectangular(image(
+            file,
+            "skybox.environment",
+        )?)),
+        (None, Some(cube)) => {
+            let files = cube
+                .as_array()
+                .filter(|files| files.len() == 6)
+                .ok_or_else(|| "skybox.cube must list six face images".to_string())?;
+            let mut faces = Vec::with_capacity(6);
+            for (index, file) in files.iter().enumerate() {
+                faces.push(image(file, &format!("skybox.cube[{index}]"))?);
+            }
+            if faces
+                .iter()
+                .any(|face| face.width != face.height || face.width != faces[0].width)
+            {
+                return Err("skybox.cube faces must be square and the same size".to_string());
+            }
+            let faces: [LinearImage; 6] = faces.try_into().expect("six faces");
+            Some(Projection::CubeMap(Box::new(faces)))
+        }
+        (None, None) => None,
+    };
+
+    let Some(projection) = projection else {
+        return Ok(Skybox {
+            color: color_field(value, "color", "skybox", working_space)?,
+            environment: None,
+            sun_sky: None,
+        });
+    };
+    let tint = match value.get("color") {
+        Some(_) => color_field(value, "color", "skybox", working_space)?,
+        None => Float3::new([1.0, 1.0, 1.0]),
+    };
+    let intensity = match value.get("intensity") {
+        Some(intensity) => number(intensity, "skybox.intensity")?,
+        None => 1.0,
+    };
+    if intensity < 0.0 {
+        return Err("skybox.intensity must not be negative".to_string());
+    }
+    let rotation = match value.get("rotation") {
+        Some(rotation) => number(rotation, "skybox.rotation")?,
+        None => 0.0,
+    };
+    Ok(Skybox {
+        color: tint,
+        environment: Some(Arc::new(EnvironmentMap::new(
+            projection,
+            rotation,
+            tint.scale(intensity),
+        ))),
+        sun_sky: None,
+    })
+}
+
+// A sun placed by `sun_elevation` and `sun_azimuth` in degrees (clockwise
+// from north, which is -z), or by `latitude` and `longitude` (east positive)
+// with a local `date` and `time` and the `timezone` offset in hours from UTC.
+// `turbidity` runs from a clear 2 to a hazy 10; `ground_albedo` is a number
+// or a colour.
+fn sun_sky_from_toml(value: &Value, working_space: ColorSpace) -> Result<SunSky, String> {
+    let optional_number = |key: &str, default: Float0| match value.get(key) {
+        Some(field) => number(field, &format!("skybox.{key}")),
+        None => Ok(default),
+    };
+    let turbidity = optional_number("turbidity", 3.0)?;
+    if !(1.7..=10.0).contains(&turbidity) {
+        return Err("skybox.turbidity must be between 1.7 and 10".to_string());
+    }
+    let intensity = optional_number("intensity", 1.0)?;
+    if intensity < 0.0 {
+        return Err("skybox.intensity must not be negative".to_string());
+    }
+    let ground_albedo = match value.get("ground_albedo") {
+        Some(albedo) if albedo.is_array() => {
+            color_field(value, "ground_albedo", "skybox", working_space)?
+        }
+        Some(albedo) => Float3::new([number(albedo, "skybox.ground_albedo")?; 3]),
+        None => Float3::new([0.3, 0.3, 0.3]),
+    };
+
+    let (elevation, azimuth) = if value.get("sun_elevation").is_some() {
+        (
+            optional_number("sun_elevation", 0.0)?,
+            optional_number("sun_azimuth", 180.0)?,
+        )
+    } else if value.get("latitude").is_some() {
+        let latitude = number(required(value, "latitude", "skybox")?, "skybox.latitude")?;
+        let longitude = number(required(value, "longitude", "skybox")?, "skybox.longitude")?;
+        let date = required(value, "date", "skybox")?
+            .as_datetime()
+            .and_then(|datetime| datetime.date)
+            .ok_or_else(|| "skybox.date must be a date like 2024-06-21".to_string())?;
+        let time = required(value, "time", "skybox")?
+            .as_datetime()
+            .and_then(|datetime| datetime.time)
+            .ok_or_else(|| "skybox.time must be a time like 14:30:00".to_string())?;
+        let hours = time.hour as Float0
+            + time.minute as Float0 / 60.0
+            + time.second.unwrap_or(0) as Float0 / 3600.0
+            - optional_number("timezone", 0.0)?;
+        let day = sunsky::day_of_year(date.year as i32, date.month as u32, date.day as u32);
+        sunsky::solar_position(latitude, longitude, day, hours)
+    } else {
+        return Err(
+            "skybox needs a `sun_elevation` or a `latitude`, `longitude`, `date` and `time`"
+                .to_string(),
+        );
+    };
+
+    Ok(SunSky::new(
+        sunsky::sun_direction(elevation, azimuth),
+        turbidity,
+        ground_albedo,
+        intensity,
+        working_space,
+    ))
+}
+
+// An IES profile from the `ies` file, turned by `ies_rotation` degrees about
+// x, y and z within `frame`, the light's own orientation.
+fn ies_field(
+    light: &Value,
+    path: &str,
+    frame: Float3x3,
+) -> Result<Option<OrientedProfile>, String> {
+    let Some(file) = light.get("ies") else {
+        return Ok(None);
+    };
+    let file = file
+        .as_str()
+        .ok_or_else(|| format!("{path}.ies must be a file path"))?;
+    let profile = IesProfile::read(Path::new(file)).map_err(|err| format!("{path}.ies: {err}"))?;
+    let rotation =
+        optional_float3_field(light, "ies_rotation", path)?.unwrap_or(Float3::new([0.0, 0.0, 0.0]));
+    Ok(Some(OrientedProfile::new(
+        Arc::new(profile),
+        frame * ies::rotation(rotation),
+    )))
+}
+
+fn identity() -> Float3x3 {
+    Float3x3::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+}
+
+fn light_direction(light: &Value, path: &str) -> Result<Float3, String> {
+    let direction = float3_field(light, "direction", path)?;
+    if direction.length_squared() == 0.0 {
+        return Err(format!("{path}.direction must not be zero"));
+    }
+    Ok(direction)
+}
+
+fn light_radius(light: &Value, path: &str) -> Result<Float0, String> {
+    let radius = float_field(light, "radius", path)?;
+    if radius <= 0.0 {
+        return Err(format!("{path}.radius must be positive"));
+    }
+    Ok(radius)
+}
+
+fn visibility_from_object(object: &Value, path: &str) -> Result<Visibility, String> {
+    let visible = Visibility::default();
+    Ok(Visibility {
+        cast_shadows: bool_field(object, "cast_shadows", path)?.unwrap_or(visible.cast_shadows),
+        receive_shadows: bool_field(object, "receive_shadows", path)?
+            .unwrap_or(visible.receive_shadows),
+        camera: bool_field(object, "visible_to_camera", path)?.unwrap_or(visible.camera),
+        holdout: bool_field(object, "holdout", path)?.unwrap_or(visible.holdout),
+    })
+}
+
+// A light's `include`, `exclude` and `shadow_exclude` lists of object names.
+// Several objects may share a name and are then linked together.
+fn light_links_from_toml(
+    light: &Value,
+    path: &str,
+    object_names: &[Option<&str>],
+) -> Result<LightLinks, String> {
+    let objects = |key: &str| -> Result<Option<Vec<usize>>, String> {
+        let Some(names) = light.get(key) else {
+            return Ok(None);
+        };
+        let names = names
+            .as_array()
+            .ok_or_else(|| format!("{path}.{key} must be an array of object names"))?;
+        let mut indices = Vec::new();
+        for (index, name) in names.iter().enumerate() {
+            let name = name
+                .as_str()
+                .ok_or_else(|| format!("{path}.{key}[{index}] must be an object name"))?;
+            let before = indices.len();
+            indices.extend(
+                object_names
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, object_name)| **object_name == Some(name))
+                    .map(|(object, _)| object),
+            );
+            if indices.len() == before {
+                return Err(format!("{path}.{key}[{index}] names no object `{name}`"));
+            }
+        }
+        Ok(Some(indices))
+    };
+    let include = objects("include")?;
+    let exclude = objects("exclude")?;
+    if include.is_some() && exclude.is_some() {
+        return Err(format!("{path} takes either include or exclude, not both"));
+    }
+    Ok(LightLinks {
+        include,
+        exclude: exclude.unwrap_or_default(),
+        shadow_exclude: objects("shadow_exclude")?.unwrap_or_default(),
+    })
+}
+
+// Builds a light with `build` from its emission. A plain `color` or
+// `temperature` is in the renderer's units; next to one of the physical
+// units they only give the hue. `direct` is the unit that sets this kind of
+// light's emission, candela, nits or lux, and `by_power` whether watts and
+// lumens may set its total power instead.
+fn physical_light<L: Light + 'static>(
+    light: &Value,
+    path: &str,
+    color_space: ColorSpace,
+    direct: &str,
+    by_power: bool,
+    build: impl Fn(Spectrum) -> L,
+) -> Result<Box<dyn Light>, String> {
+    let mut given = Vec::new();
+    for key in LightUnit::KEYS {
+        if let Some(value) = optional_float_field(light, key, path)? {
+            given.push((key, value));
+        }
+    }
+    let (key, value) = match given.as_slice() {
+        [] => {
+            return Ok(Box::new(build(emission_field(
+                light,
+                "color",
+                path,
+                color_space,
+            )?)));
+        }
+        [unit] => *unit,
+        _ => {
+            return Err(format!(
+                "{path} takes only one of {}",
+                LightUnit::KEYS.join(", ")
+            ));
+        }
+    };
+    if key != direct && !(by_power && matches!(key, "watts" | "lumens")) {
+        let allowed = if by_power {
+            format!("{direct}, lumens or watts")
+        } else {
+            direct.to_string()
+        };
+        return Err(format!(
+            "{path}.{key} doesn't apply to this light, use {allowed}"
+        ));
+    }
+    if value < 0.0 {
+        return Err(format!("{path}.{key} must not be negative"));
+    }
+
+    let hue = if light.get("color").is_some() || light.get("temperature").is_some() {
+        emission_field(light, "color", path, color_space)?
     } else {
-        Ok(Material::default())
+        Spectrum::Rgb(Float3::new([1.0, 1.0, 1.0]))
+    };
+    let luminance = units::luminance(hue.rgb(), color_space);
+    if luminance <= 0.0 {
+        return Err(format!("{path}.color must not be black"));
+    }
+    // One candela, nit or lux.
+    let unit_emission = hue.scaled((luminance * units::NITS_PER_UNIT).recip());
+    let unit = LightUnit::from_key(key, value).expect("one of LightUnit::KEYS");
+    match unit.lumens() {
+        None => Ok(Box::new(build(unit_emission.scaled(value)))),
+        Some(lumens) => {
+            let unit_lumens = units::luminance(build(unit_emission).illuminate(), color_space)
+                * units::NITS_PER_UNIT;
+            if unit_lumens <= 0.0 {
+                return Err(format!("{path} has no area or beam to emit {key} from"));
+            }
+            Ok(Box::new(build(unit_emission.scaled(lumens / unit_lumens))))
+        }
+    }
+}
+
+// Either an RGB colour under `key` or a blackbody `temperature` in kelvin
+// with an optional `intensity`, its luminance.
+fn emission_field(
+    light: &Value,
+    key: &str,
+    path: &str,
+    working_space: ColorSpace,
+) -> Result<Spectrum, String> {
+    match light.get("temperature") {
+        Some(temperature) => {
+            let temperature = number(temperature, &format!("{path}.temperature"))?;
+            if temperature <= 0.0 {
+                return Err(format!("{path}.temperature must be positive"));
+            }
+            let intensity = light
+                .get("intensity")
+                .map(|intensity| number(intensity, &format!("{path}.intensity")))
+                .transpose()?
+                .unwrap_or(1.0);
+            Ok(Spectrum::blackbody(temperature, intensity, working_space))
+        }
+        None => Ok(Spectrum::Rgb(color_field(light, key, path, working_space)?)),
+    }
+}
+
+// Colour values are in the working space unless tagged with a `color_space`
+// next to them.
+fn color_field(
+    value: &Value,
+    key: &str,
+    path: &str,
+    working_space: ColorSpace,
+) -> Result<Float3, String> {
+    let color = float3_field(value, key, path)?;
+    Ok(match color_space_field(value, path)? {
+        Some(input_space) => to_working_space(color, input_space, working_space),
+        None => color,
+    })
+}
+
+fn color_space_field(value: &Value, path: &str) -> Result<Option<ColorSpace>, String> {
+    value
+        .get("color_space")
+        .map(|space| {
+            let name = space
+                .as_str()
+                .ok_or_else(|| format!("{path}.color_space must be a string"))?;
+            ColorSpace::from_str(name).map_err(|_| {
+                format!(
+                    "{path}.color_space has unknown color space `{name}` (linear_srgb, acescg, rec2020)"
+                )
+            })
+        })
+        .transpose()
+}
+
+// Colours outside the working gamut come out with negative components, which
+// don't make sense as reflectance or emission, so they are clipped.
+fn to_working_space(color: Float3, input_space: ColorSpace, working_space: ColorSpace) -> Float3 {
+    let color = input_space.convert(color, working_space);
+    Float3::new(color.0.map(|value| value.max(0.0)))
+}
+
+fn material_key(object: &Value) -> String {
+    let key = object
+        .get("material")
+        .or_else(|| object.get("color"))
+        .map_or_else(
+            || {
+                if object.get("medium").is_some() || is_volume(object) {
+                    "\"interface\"".to_string()
+                } else {
+                    "\"white\"".to_string()
+                }
+            },
+            Value::to_string,
+        );
+    match object.get("color_space") {
+        Some(space) => format!("{key} in {space}"),
+        None => key,
     }
 }
 
@@ -320,6 +1680,13 @@ fn bool_field(value: &Value, key: &str, path: &str) -> Result<Option<bool>, Stri
         .transpose()
 }
 
+fn optional_float_field(value: &Value, key: &str, path: &str) -> Result<Option<Float0>, String> {
+    value
+        .get(key)
+        .map(|value| number(value, &format!("{path}.{key}")))
+        .transpose()
+}
+
 fn optional_float2_field(value: &Value, key: &str, path: &str) -> Result<Option<Float2>, String> {
     value
         .get(key)
@@ -435,7 +1802,34 @@ fn usize_value(value: &Value, path: &str) -> Result<usize, String> {
 
 #[cfg(test)]
 mod tests {
-    use super::Scene;
+    use super::{ColorSpace, Float0, Float3, PI, Ray, Scene, ShadingPoint, units};
+    use crate::{
+        film::PathSample, material::SamplingFunctions, pathtracer::get_rng,
+        sampler::independent::IndependentSampler,
+    };
+
+    // Builds a scene from a TOML fragment, filling in an empty object list,
+    // a camera looking down the z axis and a black sky where it has none.
+    fn scene_with(fragment: &str) -> Result<Scene, String> {
+        let mut table: toml::Table = toml::from_str(fragment).unwrap();
+        let defaults: toml::Table = toml::from_str(
+            r#"
+            objects = []
+
+            [camera]
+            position = [0.0, 0.0, 5.0]
+            rotation = [0.0, 0.0, 0.0]
+
+            [skybox]
+            color = [0.0, 0.0, 0.0]
+            "#,
+        )
+        .unwrap();
+        for (key, value) in defaults {
+            table.entry(key).or_insert(value);
+        }
+        Scene::try_from_toml(&toml::Value::Table(table))
+    }
 
     #[test]
     fn reports_missing_object_field_path() {
@@ -495,4 +1889,611 @@ mod tests {
         assert_eq!(scene.objects.len(), 1);
         assert_eq!(scene.lights.len(), 1);
     }
+
+    #[test]
+    fn converts_tagged_colors_into_the_working_space() {
+        let scene = toml::from_str(
+            r#"
+            color_space = "acescg"
+
+            [[objects]]
+            type = "sphere"
+            position = [0.0, 0.0, -1.0]
+            radius = 0.5
+            color = [1.0, 0.0, 0.0]
+            color_space = "linear_srgb"
+
+            [[objects]]
+            type = "sphere"
+            position = [1.0, 0.0, -1.0]
+            radius = 0.5
+            color = [1.0, 0.0, 0.0]
+
+            [camera]
+            position = [0.0, 0.0, 1.0]
+            rotation = [0.0, 0.0, 0.0]
+
+            [skybox]
+            color = [1.0, 1.0, 1.0]
+            color_space = "rec2020"
+            "#,
+        )
+        .unwrap();
+
+        let scene = Scene::try_from_toml(&scene).unwrap();
+        assert_eq!(scene.color_space, ColorSpace::AcesCg);
+        let albedo = |x: Float0| {
+            let ray = Ray {
+                origin: Float3::new([x, 0.0, 1.0]),
+                direction: Float3::new([0.0, 0.0, -1.0]),
+            };
+            scene.hit(&ray, 0.001).unwrap().material.albedo
+        };
+        let tagged = albedo(0.0);
+        assert!((tagged.x() - 0.613).abs() < 1e-3 && tagged.y() > 0.05);
+        // Untagged values are already in the working space.
+        assert_eq!(albedo(1.0).0, [1.0, 0.0, 0.0]);
+        assert_ne!(scene.material_ids[0], scene.material_ids[1]);
+        assert!((scene.skybox.color - Float3::new([1.0, 1.0, 1.0])).length() < 1e-9);
+    }
+
+    #[test]
+    fn parses_atmosphere_and_object_media() {
+        let scene = toml::from_str(
+            r#"
+            [atmosphere]
+            sigma_s = [0.05, 0.05, 0.05]
+            g = 0.2
+
+            [[objects]]
+            type = "sphere"
+            position = [0.0, 0.0, -1.0]
+            radius = 0.5
+            medium = { sigma_a = [0.1, 0.2, 0.3] }
+
+            [camera]
+            position = [0.0, 0.0, 1.0]
+            rotation = [0.0, 0.0, 0.0]
+
+            [skybox]
+            color = [1.0, 1.0, 1.0]
+            "#,
+        )
+        .unwrap();
+
+        let scene = Scene::try_from_toml(&scene).unwrap();
+        assert_eq!(scene.media.len(), 2);
+        assert_eq!(scene.atmosphere, Some(0));
+        assert_eq!(scene.object_media, vec![Some(1)]);
+        let ray = Ray {
+            origin: Float3::new([0.0, 0.0, 1.0]),
+            direction: Float3::new([0.0, 0.0, -1.0]),
+        };
+        assert!(scene.hit(&ray, 0.001).unwrap().material.is_interface());
+        assert_eq!(scene.medium_behind(0, true), Some(1));
+        assert_eq!(scene.medium_behind(0, false), Some(0));
+
+        let invalid = toml::from_str(
+            r#"
+            [atmosphere]
+            g = 2.0
+            [camera]
+            position = [0.0, 0.0, 1.0]
+            rotation = [0.0, 0.0, 0.0]
+            [skybox]
+            color = [1.0, 1.0, 1.0]
+            "#,
+        )
+        .unwrap();
+        assert!(Scene::try_from_toml(&invalid).is_err());
+    }
+
+    #[test]
+    fn parses_grid_volumes() {
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.raw", std::process::id()));
+        let values: Vec<u8> = [0.0f32, 1.0, 0.5, 0.25]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        std::fs::write(&file, values).unwrap();
+        let scene = |resolution: &str| {
+            scene_with(&format!(
+                r#"
+                [[objects]]
+                type = "volume"
+                grid = "{}"
+                {resolution}
+                min = [-1.0, -1.0, -2.0]
+                max = [1.0, 1.0, 0.0]
+                sigma_s = [1.0, 1.0, 1.0]
+                density_scale = 4.0
+                temperature = 1500.0
+                "#,
+                file.display()
+            ))
+        };
+
+        let volume = scene("resolution = [2, 2, 1]").unwrap();
+        let missing_resolution = scene("");
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(volume.object_media, vec![Some(0)]);
+        let ray = Ray {
+            origin: Float3::new([0.0, 0.0, 1.0]),
+            direction: Float3::new([0.0, 0.0, -1.0]),
+        };
+        let (index, record) = volume.hit_object(&ray, 0.001).unwrap();
+        assert!(record.material.is_interface());
+        assert!((record.t - 1.0).abs() < 1e-9);
+        assert_eq!(volume.medium_behind(index, record.front_face), Some(0));
+        assert!(
+            missing_resolution
+                .unwrap_err()
+                .contains("raw grids need a `resolution")
+        );
+    }
+
+    #[test]
+    fn parses_sun_and_sky() {
+        let scene = |skybox: &str| {
+            scene_with(&format!(
+                r#"
+                [skybox]
+                type = "sun_sky"
+                {skybox}
+                "#
+            ))
+        };
+
+        // The sun peaks over London around 1 pm summer time, 12:00 UTC.
+        let located = scene(
+            "latitude = 51.5\nlongitude = -0.1\ndate = 2024-06-21\ntime = 13:00:00\ntimezone = 1",
+        )
+        .unwrap();
+        assert_eq!(located.lights.len(), 1);
+        let sun = located.skybox.sun_sky.as_ref().unwrap().sun_direction();
+        let elevation = sun.y().asin().to_degrees();
+        assert!((elevation - 61.9).abs() < 1.0, "{elevation}");
+        assert!(sun.z() > 0.9 * sun.length() * elevation.to_radians().cos());
+
+        let placed =
+            scene("sun_elevation = 20.0\nsun_azimuth = 90.0\nground_albedo = [0.1, 0.2, 0.3]")
+                .unwrap();
+        let sun = placed.skybox.sun_sky.as_ref().unwrap().sun_direction();
+        assert!(sun.x() > 0.9 && sun.y() > 0.3);
+        assert!(
+            placed
+                .skybox
+                .radiance(Float3::new([0.0, 1.0, 0.0]))
+                .length()
+                > 0.0
+        );
+
+        assert!(
+            scene("sun_elevation = 20.0\nturbidity = 20.0")
+                .unwrap_err()
+                .contains("turbidity")
+        );
+        assert!(scene("latitude = 10.0").unwrap_err().contains("longitude"));
+    }
+
+    #[test]
+    fn parses_light_types_and_ies_profiles() {
+        let file = std::env::temp_dir().join(format!("pathtracer-{}.ies", std::process::id()));
+        std::fs::write(
+            &file,
+            "IESNA:LM-63-2002\nTILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 40\n0 90\n0\n500 100\n",
+        )
+        .unwrap();
+        let scene = |lights: &str| {
+            scene_with(&format!(
+                r#"
+                {lights}
+                "#
+            ))
+        };
+        let lights = format!(
+            r#"
+            [[lights]]
+            type = "point"
+            position = [0.0, 2.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+            ies = "{}"
+
+            [[lights]]
+            type = "spot"
+            position = [0.0, 2.0, 0.0]
+            direction = [0.0, -1.0, 0.0]
+            outer_angle = 40.0
+            color = [1.0, 1.0, 1.0]
+            ies = "{}"
+            ies_rotation = [0.0, 0.0, 90.0]
+
+            [[lights]]
+            type = "directional"
+            direction = [0.0, -1.0, 0.0]
+            angle = 0.5
+            color = [1.0, 1.0, 1.0]
+
+            [[lights]]
+            type = "disk"
+            position = [0.0, 2.0, 0.0]
+            normal = [0.0, -1.0, 0.0]
+            radius = 0.5
+            color = [1.0, 1.0, 1.0]
+
+            [[lights]]
+            type = "sphere"
+            position = [0.0, 2.0, 0.0]
+            radius = 0.5
+            color = [1.0, 1.0, 1.0]
+            "#,
+            file.display(),
+            file.display()
+        );
+        let parsed = scene(&lights);
+        let misplaced = scene(&format!(
+            "[[lights]]\ntype = \"sphere\"\nposition = [0.0, 0.0, 0.0]\nradius = 1.0\ncolor = [1.0, 1.0, 1.0]\nies = \"{}\"",
+            file.display()
+        ));
+        std::fs::remove_file(&file).unwrap();
+
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.lights.len(), 5);
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let below = Float3::new([0.0, 0.0, 0.0]);
+        // Straight below the fixture is its 500 cd peak; the point light's
+        // profile points down, the spot's has been turned to shine sideways.
+        let point = parsed.lights[0].sample(below, &mut sampler, None);
+        assert!((point.radiance.x() - 0.25).abs() < 1e-9);
+        let spot = parsed.lights[1].sample(below, &mut sampler, None);
+        assert!((spot.radiance.x() - 0.25 * 0.2).abs() < 1e-9);
+        assert!(misplaced.unwrap_err().contains("point and spot"));
+        assert!(
+            scene("[[lights]]\ntype = \"sphere\"\nposition = [0.0, 0.0, 0.0]\nradius = 0.0\ncolor = [1.0, 1.0, 1.0]")
+                .unwrap_err()
+                .contains("radius")
+        );
+    }
+
+    #[test]
+    fn parses_physical_light_units() {
+        let scene = |lights: &str| {
+            scene_with(&format!(
+                r#"
+                {lights}
+
+                [[objects]]
+                type = "sphere"
+                position = [0.0, 0.0, 0.0]
+                radius = 2.0
+                color = [0.5, 0.5, 0.5]
+                "#
+            ))
+        };
+        let parsed = scene(
+            r#"
+            [[lights]]
+            type = "point"
+            position = [0.0, 3.0, 0.0]
+            watts = 1.0
+
+            [[lights]]
+            type = "spot"
+            position = [0.0, 3.0, 0.0]
+            direction = [0.0, -1.0, 0.0]
+            outer_angle = 40.0
+            inner_angle = 20.0
+            lumens = 800.0
+            color = [1.0, 0.5, 0.2]
+
+            [[lights]]
+            type = "area"
+            point1 = [0.0, 3.0, 0.0]
+            point2 = [1.0, 3.0, 0.0]
+            point3 = [1.0, 3.0, 1.0]
+            point4 = [0.0, 3.0, 1.0]
+            nits = 2000.0
+            two_sided = true
+
+            [[lights]]
+            type = "sphere"
+            position = [0.0, 3.0, 0.0]
+            radius = 0.1
+            temperature = 2700.0
+            lumens = 1000.0
+
+            [[lights]]
+            type = "directional"
+            direction = [0.0, -1.0, 0.0]
+            lux = 100000.0
+            "#,
+        )
+        .unwrap();
+        let lumens = |light: usize| {
+            units::luminance(parsed.lights[light].illuminate(), ColorSpace::LinearSrgb)
+                * units::NITS_PER_UNIT
+        };
+        assert!((lumens(0) - 683.0).abs() < 1e-6);
+        assert!((lumens(1) - 800.0).abs() < 1e-6);
+        // 2 kcd/m² on both sides of a square metre.
+        assert!((lumens(2) - 2.0 * PI * 2000.0).abs() < 1e-6);
+        assert!((lumens(3) - 1000.0).abs() < 1e-6);
+        // 100 klx over a disk the size of the scene.
+        assert!((lumens(4) - 1e5 * PI * 12.0).abs() < 1e-3);
+
+        // One candela is a thousandth of the renderer's unit, whatever the
+        // hue.
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let candela = scene("[[lights]]\ntype = \"point\"\nposition = [0.0, 3.0, 0.0]\ncandela = 90.0\ncolor = [0.2, 0.9, 0.1]")
+            .unwrap();
+        let sample = candela.lights[0].sample(Float3::new([0.0, 0.0, 0.0]), &mut sampler, None);
+        assert!((units::luminance(sample.radiance, ColorSpace::LinearSrgb) - 0.01).abs() < 1e-9);
+
+        for (light, error) in [
+            (
+                "type = \"area\"\npoint1 = [0.0, 0.0, 0.0]\npoint2 = [1.0, 0.0, 0.0]\npoint3 = [1.0, 1.0, 0.0]\npoint4 = [0.0, 1.0, 0.0]\ncandela = 1.0",
+                "use nits, lumens or watts",
+            ),
+            (
+                "type = \"directional\"\ndirection = [0.0, -1.0, 0.0]\nwatts = 1.0",
+                "use lux",
+            ),
+            (
+                "type = \"point\"\nposition = [0.0, 0.0, 0.0]\nwatts = 1.0\nlumens = 1.0",
+                "only one of",
+            ),
+            (
+                "type = \"point\"\nposition = [0.0, 0.0, 0.0]\ncolor = [1.0, 1.0, 1.0]\ntwo_sided = true",
+                "area and disk",
+            ),
+            (
+                "type = \"point\"\nposition = [0.0, 0.0, 0.0]\ncolor = [0.0, 0.0, 0.0]\nlumens = 1.0",
+                "black",
+            ),
+        ] {
+            let err = scene(&format!("[[lights]]\n{light}")).unwrap_err();
+            assert!(err.contains(error), "{err}");
+        }
+    }
+
+    #[test]
+    fn links_lights_and_shadows_to_objects() {
+        let scene = |lights: &str| {
+            scene_with(&format!(
+                r#"
+                {lights}
+
+                [[objects]]
+                type = "plane"
+                name = "floor"
+                point = [0.0, 0.0, 0.0]
+                normal = [0.0, 1.0, 0.0]
+                color = [0.5, 0.5, 0.5]
+
+                [[objects]]
+                type = "sphere"
+                name = "bottle"
+                position = [0.0, 1.0, 0.0]
+                radius = 0.5
+                color = [0.5, 0.5, 0.5]
+
+                [[objects]]
+                type = "sphere"
+                name = "occluder"
+                position = [0.0, 3.0, 0.0]
+                radius = 0.5
+                color = [0.5, 0.5, 0.5]
+
+                [[objects]]
+                type = "sphere"
+                position = [0.0, 1.0, 5.0]
+                radius = 1.0
+                color = [0.5, 0.5, 0.5]
+                visible_to_camera = false
+
+                [[objects]]
+                type = "sphere"
+                position = [6.0, 3.0, 0.0]
+                radius = 0.5
+                color = [0.5, 0.5, 0.5]
+                cast_shadows = false
+
+                [[objects]]
+                type = "sphere"
+                position = [-6.0, 3.0, 0.0]
+                radius = 0.5
+                color = [0.5, 0.5, 0.5]
+
+                [[objects]]
+                type = "sphere"
+                position = [-6.0, 0.5, 0.0]
+                radius = 0.5
+                color = [0.5, 0.5, 0.5]
+                receive_shadows = false
+                "#
+            ))
+        };
+        let parsed = scene(
+            r#"
+            [[lights]]
+            type = "point"
+            position = [0.0, 5.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+            include = ["bottle"]
+
+            [[lights]]
+            type = "point"
+            position = [0.0, 5.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+            shadow_exclude = ["occluder"]
+
+            [[lights]]
+            type = "point"
+            position = [0.0, 5.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+
+            [[lights]]
+            type = "point"
+            position = [6.0, 5.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+
+            [[lights]]
+            type = "point"
+            position = [-6.0, 5.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+            "#,
+        )
+        .unwrap();
+        assert!(parsed.light_links[0].illuminates(1));
+        assert!(!parsed.light_links[0].illuminates(0));
+        assert!(parsed.light_links[2].illuminates(0));
+
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let mut lit = |point: [Float0; 3], object: usize, light: usize| {
+            let at = ShadingPoint {
+                point: Float3::new(point),
+                normal: Some(Float3::new([0.0, 1.0, 0.0])),
+                object: Some(object),
+            };
+            let sample = parsed.lights[light].sample(at.point, &mut sampler, None);
+            parsed
+                .transmittance_to_light(at, light, &sample, None, &mut sampler, None)
+                .x()
+        };
+        // The occluder shades the top of the bottle, except from the light
+        // that ignores it.
+        assert_eq!(lit([0.0, 1.5, 0.0], 1, 2), 0.0);
+        assert_eq!(lit([0.0, 1.5, 0.0], 1, 1), 1.0);
+        // Objects that don't cast shadows and ones that don't receive them.
+        assert_eq!(lit([6.0, 0.0, 0.0], 0, 3), 1.0);
+        assert_eq!(lit([-6.0, 1.0, 0.0], 6, 4), 1.0);
+        assert_eq!(lit([-6.0, 1.0, 0.0], 0, 4), 0.0);
+
+        // Camera rays pass through the sphere hidden from them.
+        let ray = Ray::new(Float3::new([0.0, 1.0, 10.0]), Float3::new([0.0, 0.0, -1.0]));
+        assert_eq!(parsed.hit_object(&ray, 0.001).unwrap().0, 3);
+        assert_eq!(parsed.hit_from_camera(&ray).unwrap().0, 1);
+
+        let light =
+            "[[lights]]\ntype = \"point\"\nposition = [0.0, 5.0, 0.0]\ncolor = [1.0, 1.0, 1.0]\n";
+        for (links, error) in [
+            ("include = [\"vase\"]", "names no object `vase`"),
+            ("include = [\"bottle\"]\nexclude = [\"floor\"]", "not both"),
+            ("shadow_exclude = \"floor\"", "must be an array"),
+        ] {
+            let err = scene(&format!("{light}{links}")).unwrap_err();
+            assert!(err.contains(error), "{err}");
+        }
+    }
+
+    #[test]
+    fn composites_shadow_catchers_and_holdouts() {
+        let scene = scene_with(
+            r#"
+            [[objects]]
+            type = "plane"
+            point = [0.0, 0.0, 0.0]
+            normal = [0.0, 1.0, 0.0]
+            material = "shadow_catcher"
+
+            [[objects]]
+            type = "sphere"
+            position = [0.0, 1.0, 0.0]
+            radius = 0.5
+            color = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "sphere"
+            position = [3.0, 1.0, 0.0]
+            radius = 0.5
+            color = [0.5, 0.5, 0.5]
+            holdout = true
+
+            [[lights]]
+            type = "point"
+            position = [0.0, 5.0, 0.0]
+            color = [1.0, 1.0, 1.0]
+            "#,
+        )
+        .unwrap();
+        assert!(scene.needs_alpha());
+
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let mut path = PathSample::new(0);
+        let mut trace_down = |x: Float0, y: Float0| {
+            let ray = Ray::new(Float3::new([x, y, 0.0]), Float3::new([0.0, -1.0, 0.0]));
+            scene.trace_ray(
+                &ray,
+                10,
+                &mut sampler,
+                &SamplingFunctions::CosineWeightedSample1,
+                None,
+                &mut path,
+            );
+            (path.alpha, path.color, path.object_id)
+        };
+
+        // Under the sphere the catcher holds its full shadow, in the open it
+        // is clear.
+        let (alpha, _, object) = trace_down(0.0, 0.2);
+        assert_eq!((alpha, object), (1.0, Some(0)));
+        let (alpha, color, _) = trace_down(6.0, 1.0);
+        assert_eq!((alpha, color.0), (0.0, [0.0; 3]));
+
+        // The holdout punches a hole even where it is lit.
+        let (alpha, color, object) = trace_down(3.0, 3.0);
+        assert_eq!((alpha, color.0, object), (0.0, [0.0; 3], Some(2)));
+        // Other objects stay opaque.
+        let (alpha, color, _) = trace_down(0.0, 3.0);
+        assert_eq!(alpha, 1.0);
+        assert!(color.x() > 0.0);
+    }
+
+    #[test]
+    fn hides_the_background_from_the_camera() {
+        let scene = |skybox: &str| {
+            scene_with(&format!(
+                r#"
+                [[objects]]
+                type = "sphere"
+                position = [0.0, 0.0, 0.0]
+                radius = 1.0
+                color = [0.5, 0.5, 0.5]
+
+                [skybox]
+                color = [1.0, 1.0, 1.0]
+                {skybox}
+                "#
+            ))
+            .unwrap()
+        };
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+        let mut path = PathSample::new(0);
+        let mut trace = |scene: &Scene, x: Float0| {
+            let ray = Ray::new(Float3::new([x, 0.0, 5.0]), Float3::new([0.0, 0.0, -1.0]));
+            scene.trace_ray(
+                &ray,
+                10,
+                &mut sampler,
+                &SamplingFunctions::CosineWeightedSample1,
+                None,
+                &mut path,
+            );
+            (path.alpha, path.color)
+        };
+
+        // Misses never cover the pixel, but the skybox shows unless hidden.
+        let visible = scene("");
+        assert!(!visible.needs_alpha());
+        assert_eq!(trace(&visible, 3.0), (0.0, Float3::new([1.0, 1.0, 1.0])));
+        let hidden = scene("visible_to_camera = false");
+        assert!(hidden.needs_alpha());
+        assert_eq!(trace(&hidden, 3.0), (0.0, Float3::new([0.0, 0.0, 0.0])));
+
+        // The hidden sky still lights the sphere.
+        let (alpha, color) = trace(&hidden, 0.0);
+        assert_eq!(alpha, 1.0);
+        assert!(color.x() > 0.0);
+    }
 }
@@ -0,0 +1,292 @@
+use crate::{
+    output::LinearImage,
+    scene::{Float0, PI},
+    utils::{
+        distribution::Distribution2D,
+        vector::{Float2, Float3},
+    },
+};
+
+pub enum Projection {
+    Equirectangular(LinearImage),
+    // Faces in the OpenGL order +x, -x, +y, -y, +z, -z, each seen from
+    // inside the cube with the top row first.
+    CubeMap(Box<[LinearImage; 6]>),
+}
+
+// HDR environment lighting around the scene. Directions map to the image
+// with +y up and the centre of an equirectangular image straight ahead
+// along -z; `rotation` turns the map about the vertical axis.
+pub struct EnvironmentMap {
+    projection: Projection,
+    rotation: Float0,
+    scale: Float3,
+    // Luminance over latitude-longitude coordinates, for importance sampling
+    // whichever projection the map uses.
+    distribution: Distribution2D,
+}
+
+impl std::fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let projection = match &self.projection {
+            Projection::Equirectangular(image) => {
+                format!("equirectangular {}x{}", image.width, image.height)
+            }
+            Projection::CubeMap(faces) => format!("cube map {}", faces[0].width),
+        };
+        f.debug_struct("EnvironmentMap")
+            .field("projection", &projection)
+            .field("rotation", &self.rotation)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
+
+impl EnvironmentMap {
+    // `rotation` in degrees, `scale` the intensity times any tint.
+    pub fn new(projection: Projection, rotation: Float0, scale: Float3) -> Self {
+        let (width, height) = match &projection {
+            Projection::Equirectangular(image) => (image.width, image.height),
+            Projection::CubeMap(faces) => (4 * faces[0].width, 2 * faces[0].width),
+        };
+        let mut map = Self {
+            projection,
+            rotation: rotation.to_radians(),
+            scale,
+            distribution: Distribution2D::new(&[1.0], 1, 1),
+        };
+
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let v = (y as Float0 + 0.5) / height as Float0;
+            let sin_theta = (v * PI).sin();
+            for x in 0..width {
+                let u = (x as Float0 + 0.5) / width as Float0;
+                let color = map.lookup(direction_from_uv(u, v));
+                func.push(luminance(color).max(0.0) * sin_theta);
+            }
+        }
+        // A small floor keeps every direction samplable, which cube maps
+        // need where a texel between the bin centres is brighter.
+        let floor = 1e-3 * func.iter().sum::<Float0>() / func.len().max(1) as Float0;
+        for value in func.iter_mut() {
+            *value += floor;
+        }
+        map.distribution = Distribution2D::new(&func, width, height);
+        map
+    }
+
+    pub fn radiance(&self, direction: Float3) -> Float3 {
+        self.lookup(self.to_local(direction.normalize())) * self.scale
+    }
+
+    // Samples a direction towards the environment, returning it with its
+    // radiance and solid-angle pdf.
+    pub fn sample(&self, u: Float2) -> (Float3, Float3, Float0) {
+        let (point, map_pdf) = self.distribution.sample_continuous(u);
+        let local = direction_from_uv(point.x(), point.y());
+        let sin_theta = (point.y() * PI).sin();
+        if sin_theta <= 0.0 {
+            return (local, Float3::new([0.0, 0.0, 0.0]), 0.0);
+        }
+        let direction = self.to_world(local);
+        (
+            direction,
+            self.lookup(local) * self.scale,
+            map_pdf / (2.0 * PI * PI * sin_theta),
+        )
+    }
+
+    pub fn pdf(&self, direction: Float3) -> Float0 {
+        let local = self.to_local(direction.normalize());
+        let (u, v) = uv_from_direction(local);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(Float2::new([u, v])) / (2.0 * PI * PI * sin_theta)
+    }
+
+    // Mean radiance over all directions.
+    pub fn average(&self) -> Float3 {
+        let count = 64;
+        let mut sum = Float3::new([0.0, 0.0, 0.0]);
+        let mut weight = 0.0;
+        for y in 0..count {
+            let v = (y as Float0 + 0.5) / count as Float0;
+            let sin_theta = (v * PI).sin();
+            for x in 0..2 * count {
+                let u = (x as Float0 + 0.5) / (2 * count) as Float0;
+                sum += self.lookup(direction_from_uv(u, v)).scale(sin_theta);
+                weight += sin_theta;
+            }
+        }
+        (sum * self.scale).scale(weight.recip())
+    }
+
+    fn lookup(&self, direction: Float3) -> Float3 {
+        match &self.projection {
+            Projection::Equirectangular(image) => {
+                let (u, v) = uv_from_direction(direction);
+                texel(image, u, v)
+            }
+            Projection::CubeMap(faces) => {
+                let [x, y, z] = direction.0;
+                let (face, s, t, major) = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+                    if x > 0.0 {
+                        (0, -z, -y, x)
+                    } else {
+                        (1, z, -y, -x)
+                    }
+                } else if y.abs() >= z.abs() {
+                    if y > 0.0 {
+                        (2, x, z, y)
+                    } else {
+                        (3, x, -z, -y)
+                    }
+                } else if z > 0.0 {
+                    (4, x, -y, z)
+                } else {
+                    (5, -x, -y, -z)
+                };
+                texel(
+                    &faces[face],
+                    0.5 * (s / major + 1.0),
+                    0.5 * (t / major + 1.0),
+                )
+            }
+        }
+    }
+
+    fn to_local(&self, direction: Float3) -> Float3 {
+        rotate_y(direction, -self.rotation)
+    }
+
+    fn to_world(&self, direction: Float3) -> Float3 {
+        rotate_y(direction, self.rotation)
+    }
+}
+
+fn rotate_y(direction: Float3, angle: Float0) -> Float3 {
+    let (sin, cos) = angle.sin_cos();
+    let [x, y, z] = direction.0;
+    Float3::new([cos * x + sin * z, y, -sin * x + cos * z])
+}
+
+fn luminance(color: Float3) -> Float0 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+// Latitude-longitude coordinates: u runs once around the horizon starting
+// behind the viewer, v from straight up to straight down.
+fn uv_from_direction(direction: Float3) -> (Float0, Float0) {
+    let phi = direction.x().atan2(-direction.z());
+    let theta = direction.y().clamp(-1.0, 1.0).acos();
+    (phi / (2.0 * PI) + 0.5, theta / PI)
+}
+
+fn direction_from_uv(u: Float0, v: Float0) -> Float3 {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = v * PI;
+    Float3::new([
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    ])
+}
+
+// Nearest texel, so lookups are constant over the bins the sampling
+// distribution is built from.
+fn texel(image: &LinearImage, u: Float0, v: Float0) -> Float3 {
+    let x = ((u * image.width as Float0).max(0.0) as usize).min(image.width - 1);
+    let y = ((v * image.height as Float0).max(0.0) as usize).min(image.height - 1);
+    image.pixels[y * image.width + x]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pathtracer::get_rng, sampler::Sampler, sampler::independent::IndependentSampler};
+
+    fn image(width: usize, height: usize, pixel: impl Fn(usize, usize) -> Float3) -> LinearImage {
+        LinearImage {
+            width,
+            height,
+            pixels: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| pixel(x, y))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn importance_sampling_matches_the_map() {
+        // A dim sky with one bright texel above the horizon.
+        let sky = image(16, 8, |x, y| {
+            if (x, y) == (10, 2) {
+                Float3::new([500.0, 400.0, 300.0])
+            } else {
+                Float3::new([0.2, 0.3, 0.5])
+            }
+        });
+        let map = EnvironmentMap::new(
+            Projection::Equirectangular(sky),
+            30.0,
+            Float3::new([2.0, 2.0, 2.0]),
+        );
+        let mut sampler = IndependentSampler::from_rng(get_rng());
+
+        // Straight ahead is the middle of the image, turned by the rotation.
+        let ahead = rotate_y(Float3::new([0.0, 0.0, -1.0]), 30f64.to_radians());
+        assert_eq!(map.radiance(ahead).0, [0.4, 0.6, 1.0]);
+
+        // Importance sampling integrates the map over the sphere.
+        let count = 100_000;
+        let mut sampled = Float3::new([0.0, 0.0, 0.0]);
+        for _ in 0..count {
+            let (direction, radiance, pdf) = map.sample(sampler.get_2d());
+            assert!((pdf - map.pdf(direction)).abs() < 1e-6 * pdf.max(1.0));
+            assert!((radiance - map.radiance(direction)).length() < 1e-9);
+            sampled += radiance.scale(1.0 / (pdf * count as Float0));
+        }
+        let expected = map.average().scale(4.0 * PI);
+        for channel in 0..3 {
+            let error = (sampled.0[channel] - expected.0[channel]).abs();
+            assert!(
+                error < 0.02 * expected.0[channel],
+                "{sampled:?} {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cube_maps_pick_faces_by_major_axis() {
+        let faces = [
+            [1.0, 0.0, 0.0],
+            [0.5, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.5, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.5],
+        ]
+        .map(|color| image(2, 2, |_, _| Float3::new(color)));
+        let map = EnvironmentMap::new(
+            Projection::CubeMap(Box::new(faces)),
+            0.0,
+            Float3::new([1.0, 1.0, 1.0]),
+        );
+        assert_eq!(
+            map.radiance(Float3::new([0.2, 1.0, -0.3])).0,
+            [0.0, 1.0, 0.0]
+        );
+        assert_eq!(
+            map.radiance(Float3::new([-1.0, 0.5, 0.3])).0,
+            [0.5, 0.0, 0.0]
+        );
+        assert_eq!(
+            map.radiance(Float3::new([0.1, 0.1, -2.0])).0,
+            [0.0, 0.0, 0.5]
+        );
+        assert!(map.pdf(Float3::new([0.0, 0.0, 1.0])) > 0.0);
+    }
+}
@@ -1,6 +1,50 @@
-use crate::utils::vector::Float3;
+use std::sync::Arc;
 
+use crate::{scene::Float0, utils::vector::Float3};
+
+pub mod environment;
+pub mod sunsky;
+
+use environment::EnvironmentMap;
+use sunsky::SunSky;
+
+// What rays that leave the scene see: a constant `color`, an environment map
+// or a physical sun and sky. The latter two are also lights of the scene.
 #[derive(Debug)]
 pub struct Skybox {
     pub color: Float3,
+    pub environment: Option<Arc<EnvironmentMap>>,
+    pub sun_sky: Option<Arc<SunSky>>,
+}
+
+impl Skybox {
+    pub fn radiance(&self, direction: Float3) -> Float3 {
+        match (&self.environment, &self.sun_sky) {
+            (Some(environment), _) => environment.radiance(direction),
+            (None, Some(sun_sky)) => sun_sky.radiance(direction),
+            (None, None) => self.color,
+        }
+    }
+
+    // Radiance along a direction that was sampled from a surface or medium,
+    // with whatever part is also sampled as a light weighed by `weight` of
+    // that light's pdf.
+    pub fn weighted_radiance(
+        &self,
+        direction: Float3,
+        weight: impl Fn(Float0) -> Float0,
+    ) -> Float3 {
+        match (&self.environment, &self.sun_sky) {
+            (Some(environment), _) => environment
+                .radiance(direction)
+                .scale(weight(environment.pdf(direction))),
+            (None, Some(sun_sky)) => {
+                sun_sky.sky_radiance(direction)
+                    + sun_sky
+                        .sun_radiance(direction)
+                        .scale(weight(sun_sky.sun_pdf(direction)))
+            }
+            (None, None) => self.color,
+        }
+    }
 }
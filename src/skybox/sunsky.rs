@@ -0,0 +1,400 @@
+use crate::{
+    color::ColorSpace,
+    material::generate_coordinate_system,
+    scene::{Float0, PI},
+    spectrum::{cie_xyz, planck},
+    utils::{
+        matrix::Float3x3,
+        vector::{Float2, Float3},
+    },
+};
+
+// Angular radius of the sun's disk, in degrees.
+const SUN_RADIUS: Float0 = 0.2665;
+// Illuminance from the sun above the atmosphere, in kilolux.
+const SUN_ILLUMINANCE: Float0 = 128.0;
+const SUN_TEMPERATURE: Float0 = 5778.0;
+
+// Preetham et al.'s analytic daylight, "A Practical Analytic Model for
+// Daylight", with the sun as a small disk of atmosphere-attenuated sunlight.
+// Radiance is in kcd/m^2, so a clear sky is around 5 to 10 and sunlit white
+// surfaces reach 30; scenes lit by it want an exposure around -3. World directions
+// have +y up, -z north and +x east. Below the horizon is a diffuse ground
+// lit by the sky and sun.
+#[derive(Debug, Clone)]
+pub struct SunSky {
+    sun_direction: Float3,
+    // Perez coefficients A to E for luminance Y and chromaticities x and y.
+    perez: [[Float0; 5]; 3],
+    // Zenith Y, x and y divided by the Perez function at the zenith, so the
+    // sky is zenith * F(theta, gamma).
+    zenith: [Float0; 3],
+    sun_radiance: Float3,
+    ground: Float3,
+    xyz_to_rgb: Float3x3,
+    scale: Float0,
+}
+
+impl SunSky {
+    pub fn new(
+        sun_direction: Float3,
+        turbidity: Float0,
+        ground_albedo: Float3,
+        intensity: Float0,
+        color_space: ColorSpace,
+    ) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let theta_sun = sun_direction.y().clamp(-1.0, 1.0).acos();
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta = theta_sun.min(PI / 2.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let chromaticity = |m: [[Float0; 4]; 3]| {
+            let angles = [theta.powi(3), theta * theta, theta, 1.0];
+            let row = |r: [Float0; 4]| (0..4).map(|i| r[i] * angles[i]).sum::<Float0>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let mut zenith = [luminance, x, y];
+        for (value, coefficients) in zenith.iter_mut().zip(perez.iter()) {
+            *value /= perez_function(coefficients, 1.0, theta, theta.cos());
+        }
+
+        let mut sky = Self {
+            sun_direction,
+            perez,
+            zenith,
+            sun_radiance: Float3::new([0.0, 0.0, 0.0]),
+            ground: Float3::new([0.0, 0.0, 0.0]),
+            xyz_to_rgb: color_space.xyz_to_rgb(),
+            scale: intensity,
+        };
+        if sun_direction.y() <= 0.0 {
+            // Preetham's model only covers daylight; after sunset the sky
+            // is left black.
+            sky.zenith = [0.0; 3];
+            return sky;
+        }
+        sky.sun_radiance = sky
+            .xyz_to_rgb
+            .multiply_by_vector(&sun_xyz(theta_sun, turbidity))
+            .scale(intensity);
+
+        // Irradiance on the ground from the sky hemisphere and the sun.
+        let steps = 64;
+        let mut irradiance = Float3::new([0.0, 0.0, 0.0]);
+        for i in 0..steps {
+            let theta = (i as Float0 + 0.5) / steps as Float0 * PI / 2.0;
+            for j in 0..2 * steps {
+                let phi = (j as Float0 + 0.5) / (2 * steps) as Float0 * 2.0 * PI;
+                let direction = Float3::new([
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ]);
+                irradiance += sky.sky_radiance(direction).scale(theta.cos() * theta.sin());
+            }
+        }
+        let cell = (PI / 2.0 / steps as Float0) * (PI / steps as Float0);
+        irradiance = irradiance.scale(cell) + sky.sun_irradiance().scale(sun_direction.y());
+        sky.ground = ground_albedo * irradiance.scale(PI.recip());
+        sky
+    }
+
+    pub fn sun_direction(&self) -> Float3 {
+        self.sun_direction
+    }
+
+    // Sky and sun together.
+    pub fn radiance(&self, direction: Float3) -> Float3 {
+        let direction = direction.normalize();
+        self.sky_radiance(direction) + self.sun_radiance(direction)
+    }
+
+    // The sky without the sun's disk, or the ground below the horizon.
+    pub fn sky_radiance(&self, direction: Float3) -> Float3 {
+        if direction.y() <= 0.0 {
+            return self.ground;
+        }
+        let cos_theta = direction.y().max(1e-4);
+        let cos_gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let [luminance, x, y] = [0, 1, 2]
+            .map(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma, cos_gamma));
+        if y <= 0.0 {
+            return Float3::new([0.0, 0.0, 0.0]);
+        }
+        let xyz = Float3::new([x / y * luminance, luminance, (1.0 - x - y) / y * luminance]);
+        let rgb = self.xyz_to_rgb.multiply_by_vector(&xyz).scale(self.scale);
+        Float3::new(rgb.0.map(|channel| channel.max(0.0)))
+    }
+
+    pub fn sun_radiance(&self, direction: Float3) -> Float3 {
+        if direction.normalize().dot(&self.sun_direction) >= cos_sun_radius() {
+            self.sun_radiance
+        } else {
+            Float3::new([0.0, 0.0, 0.0])
+        }
+    }
+
+    // Samples a direction within the sun's disk, returning it with the sun's
+    // radiance and the solid-angle pdf. The pdf is zero at night.
+    pub fn sample_sun(&self, u: Float2) -> (Float3, Float3, Float0) {
+        let cos_max = cos_sun_radius();
+        let cos_theta = 1.0 - u.x() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y();
+        let (tangent, bitangent) = generate_coordinate_system(&self.sun_direction);
+        let direction = self.sun_direction.scale(cos_theta)
+            + tangent.scale(sin_theta * phi.cos())
+            + bitangent.scale(sin_theta * phi.sin());
+        if self.sun_direction.y() <= 0.0 {
+            return (direction, self.sun_radiance, 0.0);
+        }
+        (direction, self.sun_radiance, uniform_cone_pdf())
+    }
+
+    pub fn sun_pdf(&self, direction: Float3) -> Float0 {
+        if self.sun_direction.y() <= 0.0
+            || direction.normalize().dot(&self.sun_direction) < cos_sun_radius()
+        {
+            return 0.0;
+        }
+        uniform_cone_pdf()
+    }
+
+    // Total power the sun delivers per unit area facing it.
+    pub fn sun_irradiance(&self) -> Float3 {
+        self.sun_radiance.scale(uniform_cone_pdf().recip())
+    }
+}
+
+fn cos_sun_radius() -> Float0 {
+    SUN_RADIUS.to_radians().cos()
+}
+
+fn uniform_cone_pdf() -> Float0 {
+    1.0 / (2.0 * PI * (1.0 - cos_sun_radius()))
+}
+
+fn perez_function(
+    [a, b, c, d, e]: &[Float0; 5],
+    cos_theta: Float0,
+    gamma: Float0,
+    cos_gamma: Float0,
+) -> Float0 {
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+// Colour of the sun's disk seen from the ground: a blackbody scaled to the
+// extraterrestrial illuminance, attenuated by Rayleigh scattering and by
+// aerosols according to the turbidity, over Kasten and Young's air mass.
+fn sun_xyz(theta_sun: Float0, turbidity: Float0) -> Float3 {
+    let elevation = 90.0 - theta_sun.to_degrees();
+    let air_mass = 1.0 / (theta_sun.cos() + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let mut attenuated = Float3::new([0.0, 0.0, 0.0]);
+    let mut luminance = 0.0;
+    let mut lambda = 360.0;
+    while lambda <= 830.0 {
+        let micrometres: Float0 = lambda / 1000.0;
+        let rayleigh = 0.008735 * micrometres.powf(-4.08);
+        let aerosol = beta * micrometres.powf(-1.3);
+        let transmittance = (-(rayleigh + aerosol) * air_mass).exp();
+        let emitted = planck(lambda, SUN_TEMPERATURE);
+        let cmf = cie_xyz(lambda);
+        attenuated += cmf.scale(emitted * transmittance);
+        luminance += cmf.y() * emitted;
+        lambda += 5.0;
+    }
+    attenuated.scale(SUN_ILLUMINANCE * uniform_cone_pdf() / luminance)
+}
+
+// Direction towards a sun at `elevation` degrees above the horizon and
+// `azimuth` degrees clockwise from north.
+pub fn sun_direction(elevation: Float0, azimuth: Float0) -> Float3 {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+    Float3::new([
+        azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+        -azimuth.cos() * elevation.cos(),
+    ])
+}
+
+// 1 for the first of January.
+pub fn day_of_year(year: i32, month: u32, day: u32) -> u32 {
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days = [
+        31,
+        if leap { 29 } else { 28 },
+        31,
+        30,
+        31,
+        30,
+        31,
+        31,
+        30,
+        31,
+        30,
+        31,
+    ];
+    days.iter()
+        .take(month.saturating_sub(1) as usize)
+        .sum::<u32>()
+        + day
+}
+
+// The sun's elevation and azimuth in degrees (clockwise from north) at a
+// latitude and longitude in degrees, east positive, on `day` of the year at
+// `hours` UTC, using NOAA's low-accuracy solar position equations.
+pub fn solar_position(
+    latitude: Float0,
+    longitude: Float0,
+    day: u32,
+    hours: Float0,
+) -> (Float0, Float0) {
+    let gamma = 2.0 * PI / 365.0 * (day as Float0 - 1.0 + (hours - 12.0) / 24.0);
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination: Float0 = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let solar_minutes = hours * 60.0 + equation_of_time + 4.0 * longitude;
+    let hour_angle = (solar_minutes / 4.0 - 180.0).to_radians();
+    let latitude = latitude.to_radians();
+    let sin_elevation =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let azimuth = hour_angle
+        .sin()
+        .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos())
+        + PI;
+    (
+        sin_elevation.clamp(-1.0, 1.0).asin().to_degrees(),
+        azimuth.to_degrees().rem_euclid(360.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_the_sun_by_date_and_location() {
+        assert_eq!(day_of_year(2024, 3, 1), 61);
+        assert_eq!(day_of_year(2023, 12, 31), 365);
+
+        // Noon at the summer solstice at 52 degrees north: 90 - 52 + 23.44
+        // degrees up, due south.
+        let (elevation, azimuth) = solar_position(52.0, 0.0, day_of_year(2024, 6, 21), 12.0);
+        assert!((elevation - 61.44).abs() < 0.5, "{elevation}");
+        assert!((azimuth - 180.0).abs() < 2.0, "{azimuth}");
+
+        // Mid-afternoon the sun is in the south-west; in Tokyo at 15:00 local
+        // time that's 06:00 UTC.
+        let (elevation, azimuth) = solar_position(35.7, 139.7, day_of_year(2024, 6, 21), 6.0);
+        assert!(elevation > 30.0 && elevation < 55.0, "{elevation}");
+        assert!(azimuth > 240.0 && azimuth < 290.0, "{azimuth}");
+
+        let up = sun_direction(90.0, 0.0);
+        assert!((up - Float3::new([0.0, 1.0, 0.0])).length() < 1e-9);
+        let east = sun_direction(0.0, 90.0);
+        assert!((east - Float3::new([1.0, 0.0, 0.0])).length() < 1e-9);
+    }
+
+    #[test]
+    fn sky_and_sun_are_plausible_daylight() {
+        let sun = sun_direction(30.0, 180.0);
+        let sky = SunSky::new(
+            sun,
+            3.0,
+            Float3::new([0.2, 0.2, 0.2]),
+            1.0,
+            ColorSpace::LinearSrgb,
+        );
+
+        // The sky is brightest around the sun and blue overhead.
+        let luminance = |color: Float3| {
+            ColorSpace::LinearSrgb
+                .rgb_to_xyz()
+                .multiply_by_vector(&color)
+                .y()
+        };
+        let zenith = sky.radiance(Float3::new([0.0, 1.0, 0.0]));
+        let near_sun = sky.sky_radiance(sun_direction(35.0, 180.0));
+        let away = sky.sky_radiance(sun_direction(30.0, 0.0));
+        assert!(
+            luminance(zenith) > 2.0 && luminance(zenith) < 20.0,
+            "{zenith:?}"
+        );
+        assert!(luminance(near_sun) > luminance(away));
+        assert!(zenith.z() > zenith.x());
+
+        // Direct sunlight is around 100 klx and a little warm.
+        let sunlight = sky.sun_irradiance();
+        assert!(
+            luminance(sunlight) > 50.0 && luminance(sunlight) < 130.0,
+            "{sunlight:?}"
+        );
+        assert!(sunlight.x() > sunlight.z());
+        let ground = sky.radiance(Float3::new([0.3, -1.0, 0.0]));
+        assert!(ground.length() > 0.0);
+
+        // Samples stay inside the disk.
+        let (direction, radiance, pdf) = sky.sample_sun(Float2::new([0.9, 0.3]));
+        assert!(direction.dot(&sun) >= cos_sun_radius() - 1e-12);
+        assert_eq!(radiance.0, sky.sun_radiance(direction).0);
+        assert!(pdf > 0.0 && (pdf - sky.sun_pdf(sun)).abs() < 1e-9);
+        assert_eq!(sky.sun_pdf(Float3::new([0.0, 1.0, 0.0])), 0.0);
+
+        // After sunset there is no daylight at all.
+        let night = SunSky::new(
+            sun_direction(-5.0, 270.0),
+            3.0,
+            Float3::new([0.2, 0.2, 0.2]),
+            1.0,
+            ColorSpace::LinearSrgb,
+        );
+        assert_eq!(night.radiance(Float3::new([0.0, 1.0, 0.0])).0, [0.0; 3]);
+        assert_eq!(night.sample_sun(Float2::new([0.5, 0.5])).2, 0.0);
+    }
+}
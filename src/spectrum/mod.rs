@@ -0,0 +1,353 @@
+use std::sync::LazyLock;
+
+use crate::{
+    color::{ColorSpace, chromatic_adaptation},
+    scene::{Float0, PI},
+    utils::{matrix::Float3x3, vector::Float3},
+};
+
+pub mod smits;
+
+pub const LAMBDA_MIN: Float0 = 360.0;
+pub const LAMBDA_MAX: Float0 = 830.0;
+// One hero wavelength and two companions, so spectral samples fit the same
+// `Float3` the RGB integrator carries.
+pub const WAVELENGTH_SAMPLES: usize = 3;
+
+// Wyman, Sloan and Shirley's multi-lobe Gaussian fit of the CIE 1931 2°
+// colour matching functions: (amplitude, mean, sigma below, sigma above).
+type Lobe = (Float0, Float0, Float0, Float0);
+const X_LOBES: [Lobe; 3] = [
+    (1.056, 599.8, 37.9, 31.0),
+    (0.362, 442.0, 16.0, 26.7),
+    (-0.065, 501.1, 20.4, 26.2),
+];
+const Y_LOBES: [Lobe; 2] = [(0.821, 568.8, 46.9, 40.5), (0.286, 530.9, 16.3, 31.1)];
+const Z_LOBES: [Lobe; 2] = [(1.217, 437.0, 11.8, 36.0), (0.681, 459.0, 26.0, 13.8)];
+
+fn evaluate_lobes(lobes: &[Lobe], lambda: Float0) -> Float0 {
+    lobes
+        .iter()
+        .map(|(amplitude, mean, sigma_below, sigma_above)| {
+            let sigma = if lambda < *mean {
+                sigma_below
+            } else {
+                sigma_above
+            };
+            amplitude * (-0.5 * ((lambda - mean) / sigma).powi(2)).exp()
+        })
+        .sum()
+}
+
+fn integrate_lobes(lobes: &[Lobe]) -> Float0 {
+    lobes
+        .iter()
+        .map(|(amplitude, _, sigma_below, sigma_above)| {
+            amplitude * (PI / 2.0).sqrt() * (sigma_below + sigma_above)
+        })
+        .sum()
+}
+
+pub fn cie_xyz(lambda: Float0) -> Float3 {
+    Float3::new([
+        evaluate_lobes(&X_LOBES, lambda),
+        evaluate_lobes(&Y_LOBES, lambda),
+        evaluate_lobes(&Z_LOBES, lambda),
+    ])
+}
+
+// XYZ of the equal-energy spectrum, normalized to Y = 1.
+fn equal_energy_white() -> Float3 {
+    Float3::new([
+        integrate_lobes(&X_LOBES),
+        integrate_lobes(&Y_LOBES),
+        integrate_lobes(&Z_LOBES),
+    ])
+    .scale(1.0 / integrate_lobes(&Y_LOBES))
+}
+
+struct Conversions {
+    to_srgb: Float3x3,
+    from_xyz: Float3x3,
+}
+
+// Spectra are white when flat, so XYZ is adapted from the equal-energy white
+// point to the working space's white before going to RGB.
+static CONVERSIONS: LazyLock<[Conversions; 3]> = LazyLock::new(|| {
+    [
+        ColorSpace::LinearSrgb,
+        ColorSpace::AcesCg,
+        ColorSpace::Rec2020,
+    ]
+    .map(|space| Conversions {
+        to_srgb: space.conversion_matrix(ColorSpace::LinearSrgb),
+        from_xyz: space.xyz_to_rgb() * chromatic_adaptation(equal_energy_white(), space.white()),
+    })
+});
+
+fn conversions(space: ColorSpace) -> &'static Conversions {
+    &CONVERSIONS[space as usize]
+}
+
+// Importance sampling of the visible range from pbrt-v4, proportional to
+// 1 / cosh²(0.0072 (λ - 538)) on [360, 830] nm.
+fn sample_visible_wavelength(u: Float0) -> Float0 {
+    538.0 - 138.888_889 * (0.856_910_62 - 1.827_501_97 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: Float0) -> Float0 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.003_939_804_2 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+// The wavelengths one camera path carries, in nanometres, and the working
+// space their estimates are converted to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [Float0; WAVELENGTH_SAMPLES],
+    pub pdf: [Float0; WAVELENGTH_SAMPLES],
+    color_space: ColorSpace,
+}
+
+impl SampledWavelengths {
+    // Hero wavelength sampling (Wilkie et al.): the companions are offset
+    // evenly in sample space, which stratifies the set over the spectrum.
+    pub fn sample_visible(u: Float0, color_space: ColorSpace) -> Self {
+        let mut lambda = [0.0; WAVELENGTH_SAMPLES];
+        let mut pdf = [0.0; WAVELENGTH_SAMPLES];
+        for (index, (lambda, pdf)) in lambda.iter_mut().zip(pdf.iter_mut()).enumerate() {
+            let u = (u + index as Float0 / WAVELENGTH_SAMPLES as Float0).fract();
+            *lambda = sample_visible_wavelength(u);
+            *pdf = visible_wavelength_pdf(*lambda);
+        }
+        Self {
+            lambda,
+            pdf,
+            color_space,
+        }
+    }
+
+    pub fn hero(&self) -> Float0 {
+        self.lambda[0]
+    }
+
+    // Wavelength-dependent scattering such as dispersion only follows the
+    // hero wavelength; the others drop out of the estimate.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        self.pdf[1..].fill(0.0);
+        self.pdf[0] /= WAVELENGTH_SAMPLES as Float0;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+
+    pub fn evaluate(&self, spectrum: impl Fn(Float0) -> Float0) -> Float3 {
+        Float3::new(self.lambda.map(spectrum))
+    }
+
+    // Reflectance or emission spectrum for a working-space RGB colour.
+    pub fn uplift(&self, rgb: Float3) -> Float3 {
+        let srgb = conversions(self.color_space)
+            .to_srgb
+            .multiply_by_vector(&rgb);
+        // Colours outside the sRGB gamut can't be built from Smits' spectra.
+        let srgb = Float3::new(srgb.0.map(|value| value.max(0.0)));
+        self.evaluate(|lambda| smits::uplift(srgb, lambda))
+    }
+
+    pub fn to_xyz(&self, values: Float3) -> Float3 {
+        let mut xyz = Float3::new([0.0, 0.0, 0.0]);
+        for ((lambda, pdf), value) in self.lambda.iter().zip(self.pdf).zip(values.0) {
+            if pdf > 0.0 {
+                xyz += cie_xyz(*lambda).scale(value / pdf);
+            }
+        }
+        xyz.scale(1.0 / (WAVELENGTH_SAMPLES as Float0 * integrate_lobes(&Y_LOBES)))
+    }
+
+    pub fn to_rgb(&self, values: Float3) -> Float3 {
+        conversions(self.color_space)
+            .from_xyz
+            .multiply_by_vector(&self.to_xyz(values))
+    }
+}
+
+// Colour values along a path are working-space RGB, or spectral samples when
+// the path carries wavelengths.
+pub fn uplift(rgb: Float3, wavelengths: Option<&SampledWavelengths>) -> Float3 {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.uplift(rgb),
+        None => rgb,
+    }
+}
+
+// An emission spectrum, with the working-space RGB used when rendering in RGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spectrum {
+    Rgb(Float3),
+    Blackbody {
+        temperature: Float0,
+        scale: Float0,
+        rgb: Float3,
+    },
+}
+
+impl Spectrum {
+    // A blackbody emitter scaled to the given luminance, so the temperature
+    // only changes the colour.
+    pub fn blackbody(temperature: Float0, luminance: Float0, color_space: ColorSpace) -> Self {
+        let mut xyz = Float3::new([0.0, 0.0, 0.0]);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            xyz += cie_xyz(lambda).scale(planck(lambda, temperature));
+            lambda += 1.0;
+        }
+        let xyz = xyz.scale(1.0 / integrate_lobes(&Y_LOBES));
+        let scale = if xyz.y() > 0.0 {
+            luminance / xyz.y()
+        } else {
+            0.0
+        };
+        Self::Blackbody {
+            temperature,
+            scale,
+            rgb: conversions(color_space)
+                .from_xyz
+                .multiply_by_vector(&xyz.scale(scale)),
+        }
+    }
+
+    // The same spectrum `factor` times as bright.
+    pub fn scaled(self, factor: Float0) -> Self {
+        match self {
+            Self::Rgb(rgb) => Self::Rgb(rgb.scale(factor)),
+            Self::Blackbody {
+                temperature,
+                scale,
+                rgb,
+            } => Self::Blackbody {
+                temperature,
+                scale: scale * factor,
+                rgb: rgb.scale(factor),
+            },
+        }
+    }
+
+    pub fn rgb(&self) -> Float3 {
+        match self {
+            Self::Rgb(rgb) | Self::Blackbody { rgb, .. } => *rgb,
+        }
+    }
+
+    pub fn evaluate(&self, wavelengths: Option<&SampledWavelengths>) -> Float3 {
+        match (self, wavelengths) {
+            (_, None) => self.rgb(),
+            (Self::Rgb(rgb), Some(wavelengths)) => wavelengths.uplift(*rgb),
+            (
+                Self::Blackbody {
+                    temperature, scale, ..
+                },
+                Some(wavelengths),
+            ) => wavelengths.evaluate(|lambda| planck(lambda, *temperature) * scale),
+        }
+    }
+}
+
+// Planck's law: spectral radiance of a blackbody at `temperature` kelvin, per
+// nanometre of wavelength.
+pub fn planck(lambda: Float0, temperature: Float0) -> Float0 {
+    const C: Float0 = 299_792_458.0;
+    const H: Float0 = 6.626_070_15e-34;
+    const K_B: Float0 = 1.380_649e-23;
+    if temperature <= 0.0 {
+        return 0.0;
+    }
+    let lambda = lambda * 1e-9;
+    2.0 * H * C * C / (lambda.powi(5) * ((H * C / (lambda * K_B * temperature)).exp() - 1.0)) * 1e-9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Averages the RGB estimate of `spectrum` over stratified wavelength sets.
+    fn estimate(space: ColorSpace, spectrum: impl Fn(&SampledWavelengths) -> Float3) -> Float3 {
+        let count = 4096;
+        (0..count)
+            .map(|index| {
+                let wavelengths = SampledWavelengths::sample_visible(
+                    (index as Float0 + 0.5) / count as Float0,
+                    space,
+                );
+                wavelengths.to_rgb(spectrum(&wavelengths))
+            })
+            .fold(Float3::new([0.0, 0.0, 0.0]), |sum, rgb| sum + rgb)
+            .scale(1.0 / count as Float0)
+    }
+
+    #[test]
+    fn visible_wavelength_pdf_integrates_to_one() {
+        let steps = 47_000;
+        let width = (LAMBDA_MAX - LAMBDA_MIN) / steps as Float0;
+        let integral: Float0 = (0..steps)
+            .map(|step| visible_wavelength_pdf(LAMBDA_MIN + (step as Float0 + 0.5) * width) * width)
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-3, "{integral}");
+        assert!((sample_visible_wavelength(0.0) - LAMBDA_MIN).abs() < 0.5);
+        assert!((sample_visible_wavelength(1.0) - LAMBDA_MAX).abs() < 0.5);
+    }
+
+    #[test]
+    fn flat_spectrum_is_white_in_every_working_space() {
+        for space in [
+            ColorSpace::LinearSrgb,
+            ColorSpace::AcesCg,
+            ColorSpace::Rec2020,
+        ] {
+            let white = estimate(space, |wavelengths| wavelengths.evaluate(|_| 1.0));
+            assert!(
+                (white - Float3::new([1.0, 1.0, 1.0])).length() < 0.01,
+                "{white:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn uplifted_colors_round_trip() {
+        for rgb in [
+            [1.0, 1.0, 1.0],
+            [0.8, 0.1, 0.1],
+            [0.1, 0.6, 0.2],
+            [0.2, 0.3, 0.9],
+        ] {
+            let rgb = Float3::new(rgb);
+            let estimate = estimate(ColorSpace::LinearSrgb, |wavelengths| {
+                wavelengths.uplift(rgb)
+            });
+            assert!(
+                (estimate - rgb).length() < 0.1,
+                "{rgb:?} came back as {estimate:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn blackbody_color_follows_temperature() {
+        let warm = Spectrum::blackbody(2000.0, 1.0, ColorSpace::LinearSrgb).rgb();
+        let daylight = Spectrum::blackbody(6500.0, 1.0, ColorSpace::LinearSrgb).rgb();
+        assert!(warm.x() > 2.0 * warm.z());
+        assert!(daylight.z() > warm.z());
+
+        let spectrum = Spectrum::blackbody(3200.0, 2.0, ColorSpace::AcesCg);
+        let estimate = estimate(ColorSpace::AcesCg, |wavelengths| {
+            spectrum.evaluate(Some(wavelengths))
+        });
+        assert!((estimate - spectrum.rgb()).length() < 0.02 * spectrum.rgb().length());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{scene::Float0, utils::vector::Float3};
+
+// Smits, "An RGB to Spectrum Conversion for Reflectances": ten bins evenly
+// covering 380–720 nm. Any RGB colour is built from white plus at most one
+// of cyan, magenta, yellow and one of red, green, blue, which keeps the
+// spectra smooth and in [0, 1] for colours inside [0, 1].
+const BIN_START: Float0 = 380.0;
+const BIN_WIDTH: Float0 = 34.0;
+
+const WHITE: [Float0; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const CYAN: [Float0; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const MAGENTA: [Float0; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const YELLOW: [Float0; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const RED: [Float0; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [Float0; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const BLUE: [Float0; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Value at `lambda` nanometres of the spectrum for a linear sRGB colour.
+// Wavelengths outside the table use the nearest bin.
+pub fn uplift(rgb: Float3, lambda: Float0) -> Float0 {
+    let bin = (((lambda - BIN_START) / BIN_WIDTH).floor().max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    if r <= g && r <= b {
+        let (secondary, primary) = if g <= b {
+            ((g - r) * CYAN[bin], (b - g) * BLUE[bin])
+        } else {
+            ((b - r) * CYAN[bin], (g - b) * GREEN[bin])
+        };
+        r * WHITE[bin] + secondary + primary
+    } else if g <= r && g <= b {
+        let (secondary, primary) = if r <= b {
+            ((r - g) * MAGENTA[bin], (b - r) * BLUE[bin])
+        } else {
+            ((b - g) * MAGENTA[bin], (r - b) * RED[bin])
+        };
+        g * WHITE[bin] + secondary + primary
+    } else {
+        let (secondary, primary) = if r <= g {
+            ((r - b) * YELLOW[bin], (g - r) * GREEN[bin])
+        } else {
+            ((g - b) * YELLOW[bin], (r - g) * RED[bin])
+        };
+        b * WHITE[bin] + secondary + primary
+    }
+}
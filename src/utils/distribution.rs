@@ -0,0 +1,146 @@
+use crate::{scene::Float0, utils::vector::Float2};
+
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<Float0>,
+    cdf: Vec<Float0>,
+    integral: Float0,
+    min: Float0,
+    max: Float0,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[Float0], min: Float0, max: Float0) -> Self {
+        let func: Vec<Float0> = func.iter().map(|value| value.abs()).collect();
+        let count = func.len().max(1);
+        let mut cdf = vec![0.0; count + 1];
+        for (index, value) in func.iter().enumerate() {
+            cdf[index + 1] = cdf[index] + value * (max - min) / count as Float0;
+        }
+
+        let integral = cdf[count];
+        if integral == 0.0 {
+            for (index, entry) in cdf.iter_mut().enumerate() {
+                *entry = index as Float0 / count as Float0;
+            }
+        } else {
+            for entry in cdf.iter_mut() {
+                *entry /= integral;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+            min,
+            max,
+        }
+    }
+
+    pub fn integral(&self) -> Float0 {
+        self.integral
+    }
+
+    // Returns the sampled value in [min, max), its density and the bucket index.
+    pub fn sample_continuous(&self, u: Float0) -> (Float0, Float0, usize) {
+        let offset = self
+            .cdf
+            .partition_point(|entry| *entry <= u)
+            .saturating_sub(1)
+            .min(self.cdf.len() - 2);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let pdf = self.bucket_pdf(offset);
+        let t = (offset as Float0 + du) / (self.cdf.len() - 1) as Float0;
+        (self.min + t * (self.max - self.min), pdf, offset)
+    }
+
+    pub fn pdf(&self, x: Float0) -> Float0 {
+        let t = (x - self.min) / (self.max - self.min);
+        if !(0.0..=1.0).contains(&t) {
+            return 0.0;
+        }
+        let count = self.cdf.len() - 1;
+        self.bucket_pdf(((t * count as Float0) as usize).min(count - 1))
+    }
+
+    fn bucket_pdf(&self, offset: usize) -> Float0 {
+        if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            1.0 / (self.max - self.min)
+        }
+    }
+}
+
+// Piecewise-constant distribution over [0, 1]^2 from a `width` by `height`
+// grid of values stored row by row, sampled through the marginal over rows
+// and the conditional within the chosen row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[Float0], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks_exact(width.max(1))
+            .take(height)
+            .map(|row| Distribution1D::new(row, 0.0, 1.0))
+            .collect();
+        let integrals: Vec<Float0> = conditional.iter().map(Distribution1D::integral).collect();
+        Self {
+            marginal: Distribution1D::new(&integrals, 0.0, 1.0),
+            conditional,
+        }
+    }
+
+    // Returns the sampled point and its density.
+    pub fn sample_continuous(&self, u: Float2) -> (Float2, Float0) {
+        let (v, marginal_pdf, row) = self.marginal.sample_continuous(u.y());
+        let (u, conditional_pdf, _) = self.conditional[row].sample_continuous(u.x());
+        (Float2::new([u, v]), marginal_pdf * conditional_pdf)
+    }
+
+    pub fn pdf(&self, point: Float2) -> Float0 {
+        let rows = self.conditional.len();
+        let row = ((point.y() * rows as Float0).max(0.0) as usize).min(rows - 1);
+        self.marginal.pdf(point.y()) * self.conditional[row].pdf(point.x())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_proportionally_to_the_function() {
+        let distribution = Distribution1D::new(&[1.0, 3.0], 0.0, 1.0);
+        assert_eq!(distribution.integral(), 2.0);
+
+        let (x, pdf, offset) = distribution.sample_continuous(0.5);
+        assert_eq!(offset, 1);
+        assert!((x - 0.5 - 1.0 / 6.0).abs() < 1e-12);
+        assert_eq!(pdf, 1.5);
+        assert_eq!(distribution.pdf(0.25), 0.5);
+    }
+
+    #[test]
+    fn samples_2d_functions_by_rows_and_columns() {
+        // Row 0 holds a quarter of the total, row 1 the rest.
+        let distribution = Distribution2D::new(&[1.0, 0.0, 1.0, 2.0], 2, 2);
+        let (point, pdf) = distribution.sample_continuous(Float2::new([0.5, 0.5]));
+        assert!(point.y() >= 0.5);
+        assert!((pdf - distribution.pdf(point)).abs() < 1e-12);
+        assert_eq!(distribution.pdf(Float2::new([0.75, 0.25])), 0.0);
+        assert!((distribution.pdf(Float2::new([0.25, 0.25])) - 1.0).abs() < 1e-12);
+        assert!((distribution.pdf(Float2::new([0.75, 0.75])) - 2.0).abs() < 1e-12);
+    }
+}
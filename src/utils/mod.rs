@@ -1,3 +1,4 @@
+pub mod distribution;
 pub mod matrix;
 
 #[cfg(feature = "vector_constgenerics")]